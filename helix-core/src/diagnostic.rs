@@ -1,4 +1,7 @@
-#[derive(Debug, Eq, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Severity {
     Error,
     Warning,
@@ -18,4 +21,6 @@ pub struct Diagnostic {
     pub line: usize,
     pub message: String,
     pub severity: Option<Severity>,
+    /// Id of the language server that reported the diagnostic.
+    pub language_server_id: usize,
 }
@@ -452,18 +452,19 @@ where
                 file_types: vec!["rs".to_string()],
                 language_id: "Rust".to_string(),
                 highlight_config: OnceCell::new(),
-                config: None,
                 //
                 injection_regex: None,
                 roots: vec![],
                 comment_token: None,
+                config: None,
                 auto_format: false,
-                language_server: None,
+                language_servers: Vec::new(),
                 indent: Some(IndentationConfiguration {
                     tab_width: 4,
                     unit: String::from("    "),
                 }),
                 indent_query: OnceCell::new(),
+                textobject_query: OnceCell::new(),
             }],
         });
 
@@ -16,6 +16,7 @@ mod position;
 pub mod register;
 pub mod search;
 pub mod selection;
+pub mod snippet;
 mod state;
 pub mod surround;
 pub mod syntax;
@@ -35,28 +36,36 @@ pub fn find_first_non_whitespace_char(line: RopeSlice) -> Option<usize> {
     line.chars().position(|ch| !ch.is_whitespace())
 }
 
-pub fn find_root(root: Option<&str>) -> Option<std::path::PathBuf> {
+/// Find the project root of `root`, which defaults to the current directory: the top-most
+/// ancestor containing one of the `root_markers` (like `Cargo.toml`), not looking past the
+/// repository root (the closest ancestor with a `.git` directory). Falls back to the repository
+/// root if none of the ancestors has a marker.
+pub fn find_root(
+    root: Option<&std::path::Path>,
+    root_markers: &[String],
+) -> Option<std::path::PathBuf> {
     let current_dir = std::env::current_dir().expect("unable to determine current directory");
 
     let root = match root {
-        Some(root) => {
-            let root = std::path::Path::new(root);
-            if root.is_absolute() {
-                root.to_path_buf()
-            } else {
-                current_dir.join(root)
-            }
-        }
+        Some(root) if root.is_absolute() => root.to_path_buf(),
+        Some(root) => current_dir.join(root),
         None => current_dir,
     };
 
+    let mut top_marker = None;
     for ancestor in root.ancestors() {
-        // TODO: also use defined roots if git isn't found
+        if root_markers
+            .iter()
+            .any(|marker| ancestor.join(marker).exists())
+        {
+            // keep going, a workspace may contain nested projects
+            top_marker = Some(ancestor);
+        }
         if ancestor.join(".git").is_dir() {
-            return Some(ancestor.to_path_buf());
+            return Some(top_marker.unwrap_or(ancestor).to_path_buf());
         }
     }
-    None
+    top_marker.map(|ancestor| ancestor.to_path_buf())
 }
 
 pub fn runtime_dir() -> std::path::PathBuf {
@@ -203,3 +212,31 @@ pub use state::State;
 
 pub use line_ending::{LineEnding, DEFAULT_LINE_ENDING};
 pub use transaction::{Assoc, Change, ChangeSet, Operation, Transaction};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_root_with_markers() {
+        let dir = std::env::temp_dir().join(format!("helix-find-root-{}", std::process::id()));
+        let repo = dir.join("repo");
+        let project = repo.join("project");
+        let src = project.join("src");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(project.join("Cargo.toml"), "").unwrap();
+        // outside of the repository, so never the root
+        std::fs::write(dir.join("Cargo.toml"), "").unwrap();
+
+        let markers = vec!["Cargo.toml".to_string()];
+        assert_eq!(find_root(Some(&src), &markers), Some(project.clone()));
+        assert_eq!(find_root(Some(&src), &[]), Some(repo.clone()));
+
+        // the top-most marker wins, e.g. a workspace containing the project
+        std::fs::write(repo.join("Cargo.toml"), "").unwrap();
+        assert_eq!(find_root(Some(&src), &markers), Some(repo.clone()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
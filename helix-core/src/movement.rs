@@ -1,6 +1,7 @@
 use std::iter;
 
 use ropey::iter::Chars;
+use tree_sitter::{Node, QueryCursor};
 
 use crate::{
     chars::{categorize_char, char_is_line_ending, CharCategory},
@@ -9,7 +10,9 @@ use crate::{
         next_grapheme_boundary, nth_next_grapheme_boundary, nth_prev_grapheme_boundary,
         prev_grapheme_boundary,
     },
-    pos_at_coords, Position, Range, RopeSlice,
+    pos_at_coords,
+    syntax::LanguageConfiguration,
+    Position, Range, RopeSlice,
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -141,6 +144,61 @@ fn word_move(slice: RopeSlice, range: Range, count: usize, target: WordMotionTar
     })
 }
 
+/// Move to the start of the `count`th next or previous node captured as `<object_name>.around`
+/// in the language's `textobjects.scm` query, eg. the next function.
+///
+/// Moving selects the whole node, extending moves the head to the node's end (or start when
+/// moving backwards).
+#[allow(clippy::too_many_arguments)]
+pub fn goto_treesitter_object(
+    slice: RopeSlice,
+    range: Range,
+    object_name: &str,
+    dir: Direction,
+    slice_tree: Node,
+    lang_config: &LanguageConfiguration,
+    count: usize,
+    behaviour: Movement,
+) -> Range {
+    let get_range = move || -> Option<Range> {
+        let byte_pos = slice.char_to_byte(range.cursor(slice));
+
+        let capture_name = format!("{}.around", object_name);
+        let mut cursor = QueryCursor::new();
+        let mut nodes: Vec<_> = lang_config
+            .textobject_query()?
+            .capture_nodes(&capture_name, slice_tree, slice, &mut cursor)?
+            .map(|node| node.byte_range())
+            .filter(|node| match dir {
+                Direction::Forward => node.start > byte_pos,
+                Direction::Backward => node.start < byte_pos,
+            })
+            .collect();
+        nodes.sort_by_key(|node| node.start);
+        nodes.dedup();
+
+        let node = match dir {
+            Direction::Forward => nodes.get(count - 1)?,
+            Direction::Backward => nodes.iter().rev().nth(count - 1)?,
+        };
+
+        let len = slice.len_bytes();
+        if node.start > len || node.end > len {
+            return None;
+        }
+        let start = slice.byte_to_char(node.start);
+        let end = slice.byte_to_char(node.end);
+
+        Some(match (behaviour, dir) {
+            (Movement::Move, Direction::Forward) => Range::new(start, end),
+            (Movement::Move, Direction::Backward) => Range::new(end, start),
+            (Movement::Extend, Direction::Forward) => Range::new(range.anchor, end),
+            (Movement::Extend, Direction::Backward) => Range::new(range.anchor, start),
+        })
+    };
+    get_range().unwrap_or(range)
+}
+
 // ---- util ------------
 
 #[inline]
@@ -1123,4 +1181,64 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_goto_treesitter_object() {
+        use crate::syntax::{rust_loader, rust_syntax};
+        use Direction::{Backward, Forward};
+        use Movement::{Extend, Move};
+
+        let source = "fn a() {}
+
+fn b() {
+    let x = 1;
+}
+
+fn c() {}
+";
+        let doc = Rope::from(source);
+
+        let loader = rust_loader();
+        let language_config = loader.language_config_for_scope("source.rust").unwrap();
+        let syntax = rust_syntax(&doc);
+        let slice = doc.slice(..);
+
+        let a = (0, source.find("\n\nfn b").unwrap());
+        let b = (
+            source.find("fn b").unwrap(),
+            source.find("\n\nfn c").unwrap(),
+        );
+        let c = (source.find("fn c").unwrap(), source.len() - 1);
+
+        // (cursor range, direction, count, movement, final range)
+        let tests = &[
+            ((0, 0), Forward, 1, Move, b),
+            ((0, 0), Forward, 2, Move, c),
+            ((b.0 + 10, b.0 + 10), Forward, 1, Move, c),
+            // backwards from inside a function goes to its start first
+            ((b.0 + 10, b.0 + 10), Backward, 1, Move, (b.1, b.0)),
+            ((b.0, b.0), Backward, 1, Move, (a.1, a.0)),
+            ((c.0, c.0), Backward, 2, Move, (a.1, a.0)),
+            ((2, 2), Forward, 1, Extend, (2, b.1)),
+            ((c.0 + 2, c.0 + 2), Backward, 2, Extend, (c.0 + 2, b.0)),
+            // nothing past the last or before the first function leaves the range untouched
+            ((c.0 + 2, c.0 + 2), Forward, 1, Move, (c.0 + 2, c.0 + 2)),
+            ((0, 0), Backward, 1, Move, (0, 0)),
+            ((0, 0), Forward, 3, Move, (0, 0)),
+        ];
+
+        for (i, &(range, dir, count, behaviour, expected)) in tests.iter().enumerate() {
+            let result = goto_treesitter_object(
+                slice,
+                range.into(),
+                "function",
+                dir,
+                syntax.tree().root_node(),
+                &language_config,
+                count,
+                behaviour,
+            );
+            assert_eq!(result, expected.into(), "\nCase {} failed", i);
+        }
+    }
 }
@@ -1,7 +1,9 @@
-use crate::{Range, RopeSlice, Selection, Syntax};
+use crate::{
+    movement::{Direction, Movement},
+    Range, RopeSlice, Selection, Syntax,
+};
+use tree_sitter::Node;
 
-// TODO: to contract_selection we'd need to store the previous ranges before expand.
-// Maybe just contract to the first child node?
 pub fn expand_selection(syntax: &Syntax, text: RopeSlice, selection: &Selection) -> Selection {
     let tree = syntax.tree();
 
@@ -29,3 +31,144 @@ pub fn expand_selection(syntax: &Syntax, text: RopeSlice, selection: &Selection)
         }
     })
 }
+
+/// Shrink each range to the first named child of the syntax node matching it. Ranges that don't
+/// exactly match a node, or whose node has no named children, are left as they are.
+pub fn shrink_selection(syntax: &Syntax, text: RopeSlice, selection: &Selection) -> Selection {
+    let tree = syntax.tree();
+
+    selection.clone().transform(|range| {
+        let from = text.char_to_byte(range.from());
+        let to = text.char_to_byte(range.to());
+
+        let child = match tree
+            .root_node()
+            .descendant_for_byte_range(from, to)
+            .filter(|node| node.start_byte() == from && node.end_byte() == to)
+            .and_then(|node| node.named_child(0))
+        {
+            Some(child) => child,
+            None => return range,
+        };
+
+        let from = text.byte_to_char(child.start_byte());
+        let to = text.byte_to_char(child.end_byte());
+
+        if range.head < range.anchor {
+            Range::new(to, from)
+        } else {
+            Range::new(from, to)
+        }
+    })
+}
+
+/// Select the `count`th next or previous named sibling of the syntax node matching each range.
+/// If the node has no sibling in that direction, the closest ancestor that does is used instead.
+pub fn select_sibling(
+    syntax: &Syntax,
+    text: RopeSlice,
+    selection: &Selection,
+    dir: Direction,
+    count: usize,
+    behaviour: Movement,
+) -> Selection {
+    let tree = syntax.tree();
+
+    // walk up until a node that has a sibling in the given direction is found
+    fn find_sibling(mut node: Node, dir: Direction) -> Option<Node> {
+        loop {
+            let sibling = match dir {
+                Direction::Forward => node.next_named_sibling(),
+                Direction::Backward => node.prev_named_sibling(),
+            };
+            match sibling {
+                Some(sibling) => return Some(sibling),
+                None => node = node.parent()?,
+            }
+        }
+    }
+
+    selection.clone().transform(|range| {
+        let from = text.char_to_byte(range.from());
+        let to = text.char_to_byte(range.to());
+
+        let mut node = match tree
+            .root_node()
+            .named_descendant_for_byte_range(from, to)
+            .and_then(|node| find_sibling(node, dir))
+        {
+            Some(node) => node,
+            None => return range,
+        };
+        for _ in 1..count {
+            match find_sibling(node, dir) {
+                Some(sibling) => node = sibling,
+                None => break,
+            }
+        }
+
+        let from = text.byte_to_char(node.start_byte());
+        let to = text.byte_to_char(node.end_byte());
+
+        match (behaviour, dir) {
+            (Movement::Move, Direction::Forward) => Range::new(from, to),
+            (Movement::Move, Direction::Backward) => Range::new(to, from),
+            (Movement::Extend, Direction::Forward) => Range::new(range.from(), to),
+            (Movement::Extend, Direction::Backward) => Range::new(range.to(), from),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{syntax::rust_syntax, Rope};
+
+    #[test]
+    fn test_select_sibling() {
+        use Direction::{Backward, Forward};
+        use Movement::{Extend, Move};
+
+        let source = "fn a() {}
+
+fn b() {
+    let x = 1;
+}
+
+fn c() {}
+";
+        let doc = Rope::from(source);
+        let syntax = rust_syntax(&doc);
+        let text = doc.slice(..);
+
+        let a = (0, source.find("\n\nfn b").unwrap());
+        let b = (
+            source.find("fn b").unwrap(),
+            source.find("\n\nfn c").unwrap(),
+        );
+        let c = (source.find("fn c").unwrap(), source.len() - 1);
+        let statement = source.find("let x").unwrap();
+
+        // (selected range, direction, count, movement, final range)
+        let tests = &[
+            (a, Forward, 1, Move, b),
+            (a, Forward, 2, Move, c),
+            (b, Backward, 1, Move, (a.1, a.0)),
+            (a, Forward, 1, Extend, (a.0, b.1)),
+            (c, Backward, 2, Extend, (c.1, a.0)),
+            // a node without a sibling moves on from its closest ancestor that has one
+            ((statement, statement), Forward, 1, Move, c),
+            // counts past the last sibling stop at it
+            (a, Forward, 5, Move, c),
+            // nothing after the last or before the first node leaves the range untouched
+            (c, Forward, 1, Move, c),
+            (a, Backward, 1, Move, a),
+        ];
+
+        for (i, &(range, dir, count, behaviour, expected)) in tests.iter().enumerate() {
+            let selection = Selection::single(range.0, range.1);
+            let result = select_sibling(&syntax, text, &selection, dir, count, behaviour);
+            assert_eq!(result.primary(), expected.into(), "\nCase {} failed", i);
+        }
+    }
+}
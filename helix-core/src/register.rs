@@ -60,4 +60,8 @@ impl Registers {
     pub fn read(&self, name: char) -> Option<&[String]> {
         self.get(name).map(|reg| reg.read())
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Register> {
+        self.inner.values()
+    }
 }
@@ -495,6 +495,16 @@ impl Selection {
     pub fn len(&self) -> usize {
         self.ranges.len()
     }
+
+    /// Returns true if `other` has the same number of ranges and each of them lies within the
+    /// corresponding range of this selection.
+    pub fn contains(&self, other: &Selection) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(this, other)| this.from() <= other.from() && other.to() <= this.to())
+    }
 }
 
 impl<'a> IntoIterator for &'a Selection {
@@ -690,6 +700,27 @@ mod test {
         assert_eq!(range.contains(6), true);
     }
 
+    #[test]
+    fn test_selection_contains() {
+        fn selection(ranges: &[(usize, usize)]) -> Selection {
+            Selection::new(
+                ranges
+                    .iter()
+                    .map(|&(anchor, head)| Range::new(anchor, head))
+                    .collect(),
+                0,
+            )
+        }
+
+        let outer = selection(&[(0, 5), (10, 20)]);
+        assert!(outer.contains(&selection(&[(1, 3), (12, 10)])));
+        assert!(outer.contains(&outer));
+        // ranges outside
+        assert!(!outer.contains(&selection(&[(1, 6), (12, 14)])));
+        // different number of ranges
+        assert!(!outer.contains(&selection(&[(1, 3)])));
+    }
+
     #[test]
     fn test_overlaps() {
         fn overlaps(a: (usize, usize), b: (usize, usize)) -> bool {
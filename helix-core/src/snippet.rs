@@ -0,0 +1,614 @@
+//! Parser for the snippet syntax used by language servers in completion items, see
+//! <https://microsoft.github.io/language-server-protocol/specifications/specification-current/#snippet_syntax>.
+
+use crate::{ChangeSet, LineEnding, Range, Rope, Selection, Tendril, Transaction};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnippetElement {
+    /// `$1` or `${1}`
+    Tabstop {
+        tabstop: usize,
+    },
+    /// `${1:value}`, where the value may contain further elements.
+    Placeholder {
+        tabstop: usize,
+        value: Vec<SnippetElement>,
+    },
+    /// `${1|one,two,three|}`
+    Choice {
+        tabstop: usize,
+        choices: Vec<String>,
+    },
+    /// `$name`, `${name:default}` or `${name/regex/format/options}`
+    Variable {
+        name: String,
+        default: Vec<SnippetElement>,
+        transform: Option<Transform>,
+    },
+    Text(String),
+}
+
+/// Regex transformation of a variable. Parsed but not applied, the variable's value is inserted
+/// as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    pub regex: String,
+    pub format: String,
+    pub options: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Snippet {
+    pub elements: Vec<SnippetElement>,
+}
+
+/// Parse a snippet. Like other editors, constructs that fail to parse (such as an unterminated
+/// `${1:foo`) are inserted as plain text instead of rejecting the whole snippet.
+pub fn parse(s: &str) -> Snippet {
+    let mut parser = Parser {
+        chars: s.chars().collect(),
+        pos: 0,
+    };
+    Snippet {
+        elements: parser.elements(false),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse elements until the end of input, or until an unescaped `}` if `nested`.
+    fn elements(&mut self, nested: bool) -> Vec<SnippetElement> {
+        let mut elements = Vec::new();
+        let mut text = String::new();
+
+        while let Some(ch) = self.peek() {
+            match ch {
+                '}' if nested => break,
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(ch) if matches!(ch, '$' | '}' | '\\') => {
+                            text.push(ch);
+                            self.pos += 1;
+                        }
+                        _ => text.push('\\'),
+                    }
+                }
+                '$' => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    match self.dollar() {
+                        Some(element) => {
+                            if !text.is_empty() {
+                                elements.push(SnippetElement::Text(std::mem::take(&mut text)));
+                            }
+                            elements.push(element);
+                        }
+                        None => {
+                            // not a valid construct, treat the `$` as text
+                            self.pos = start + 1;
+                            text.push('$');
+                        }
+                    }
+                }
+                ch => {
+                    text.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            elements.push(SnippetElement::Text(text));
+        }
+        elements
+    }
+
+    /// Parse whatever follows a `$`.
+    fn dollar(&mut self) -> Option<SnippetElement> {
+        if let Some(tabstop) = self.int() {
+            return Some(SnippetElement::Tabstop { tabstop });
+        }
+        if let Some(name) = self.var_name() {
+            return Some(SnippetElement::Variable {
+                name,
+                default: Vec::new(),
+                transform: None,
+            });
+        }
+        if !self.eat('{') {
+            return None;
+        }
+
+        if let Some(tabstop) = self.int() {
+            if self.eat('}') {
+                return Some(SnippetElement::Tabstop { tabstop });
+            }
+            if self.eat(':') {
+                let value = self.elements(true);
+                return if self.eat('}') {
+                    Some(SnippetElement::Placeholder { tabstop, value })
+                } else {
+                    None
+                };
+            }
+            if self.eat('|') {
+                let choices = self.choices()?;
+                return Some(SnippetElement::Choice { tabstop, choices });
+            }
+            return None;
+        }
+
+        let name = self.var_name()?;
+        if self.eat('}') {
+            return Some(SnippetElement::Variable {
+                name,
+                default: Vec::new(),
+                transform: None,
+            });
+        }
+        if self.eat(':') {
+            let default = self.elements(true);
+            return if self.eat('}') {
+                Some(SnippetElement::Variable {
+                    name,
+                    default,
+                    transform: None,
+                })
+            } else {
+                None
+            };
+        }
+        if self.eat('/') {
+            let regex = self.until('/')?;
+            let format = self.until('/')?;
+            let options = self.until('}')?;
+            return Some(SnippetElement::Variable {
+                name,
+                default: Vec::new(),
+                transform: Some(Transform {
+                    regex,
+                    format,
+                    options,
+                }),
+            });
+        }
+        None
+    }
+
+    fn int(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(ch) if ch.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return None;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn var_name(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(ch) if ch == '_' || ch.is_ascii_alphabetic() => (),
+            _ => return None,
+        }
+        let start = self.pos;
+        while matches!(self.peek(), Some(ch) if ch == '_' || ch.is_ascii_alphanumeric()) {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Parse the comma separated options of a choice, up to and including the closing `|}`.
+    fn choices(&mut self) -> Option<Vec<String>> {
+        let mut choices = Vec::new();
+        let mut choice = String::new();
+        loop {
+            match self.peek()? {
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(ch) if matches!(ch, ',' | '|' | '$' | '}' | '\\') => {
+                            choice.push(ch);
+                            self.pos += 1;
+                        }
+                        _ => choice.push('\\'),
+                    }
+                }
+                ',' => {
+                    self.pos += 1;
+                    choices.push(std::mem::take(&mut choice));
+                }
+                '|' => {
+                    self.pos += 1;
+                    if !self.eat('}') {
+                        return None;
+                    }
+                    choices.push(choice);
+                    return Some(choices);
+                }
+                ch => {
+                    choice.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Read raw text up to and including an unescaped `end`.
+    fn until(&mut self, end: char) -> Option<String> {
+        let mut text = String::new();
+        loop {
+            match self.peek()? {
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek()? {
+                        ch if ch == end => text.push(ch),
+                        ch => {
+                            text.push('\\');
+                            text.push(ch);
+                        }
+                    }
+                    self.pos += 1;
+                }
+                ch if ch == end => {
+                    self.pos += 1;
+                    return Some(text);
+                }
+                ch => {
+                    text.push(ch);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Snippet {
+    /// Render the snippet into plain text, replacing each newline with `newline`. Variables are
+    /// replaced with their value from `variables`, or with their default if it returns `None`.
+    ///
+    /// Returns the text and the char ranges of every tabstop relative to the start of the text,
+    /// in the order they are visited: `$1`, `$2`, ... and `$0` last. If the snippet has no `$0`,
+    /// the end of the text is used instead.
+    pub fn render(
+        &self,
+        newline: &str,
+        variables: impl Fn(&str) -> Option<String>,
+    ) -> (String, Vec<Vec<Range>>) {
+        let mut out = Output {
+            newline,
+            variables: &variables,
+            text: String::new(),
+            len: 0,
+            tabstops: BTreeMap::new(),
+        };
+        out.render(&self.elements);
+        let Output {
+            text,
+            len,
+            mut tabstops,
+            ..
+        } = out;
+
+        let end = tabstops
+            .remove(&0)
+            .unwrap_or_else(|| vec![Range::point(len)]);
+        let mut tabstops: Vec<Vec<Range>> = tabstops.into_values().collect();
+        tabstops.push(end);
+
+        (text, tabstops)
+    }
+}
+
+/// The text rendered so far and where its tabstops are.
+struct Output<'a> {
+    newline: &'a str,
+    variables: &'a dyn Fn(&str) -> Option<String>,
+    text: String,
+    /// Length of `text` in chars.
+    len: usize,
+    tabstops: BTreeMap<usize, Vec<Range>>,
+}
+
+impl Output<'_> {
+    fn push_str(&mut self, s: &str) {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.text.push_str(self.newline);
+                self.len += self.newline.chars().count();
+            }
+            self.text.push_str(line);
+            self.len += line.chars().count();
+        }
+    }
+
+    fn tabstop(&mut self, tabstop: usize, range: Range) {
+        self.tabstops.entry(tabstop).or_default().push(range);
+    }
+
+    fn render(&mut self, elements: &[SnippetElement]) {
+        for element in elements {
+            match element {
+                SnippetElement::Text(s) => self.push_str(s),
+                SnippetElement::Tabstop { tabstop } => {
+                    self.tabstop(*tabstop, Range::point(self.len))
+                }
+                SnippetElement::Placeholder { tabstop, value } => {
+                    let start = self.len;
+                    self.render(value);
+                    // selected from start to end, so typing replaces the whole placeholder
+                    self.tabstop(*tabstop, Range::new(start, self.len));
+                }
+                SnippetElement::Choice { tabstop, choices } => {
+                    let start = self.len;
+                    if let Some(choice) = choices.first() {
+                        self.push_str(choice);
+                    }
+                    self.tabstop(*tabstop, Range::new(start, self.len));
+                }
+                SnippetElement::Variable { name, default, .. } => match (self.variables)(name) {
+                    Some(value) => self.push_str(&value),
+                    None => self.render(default),
+                },
+            }
+        }
+    }
+}
+
+/// Replace `start..end` of `doc` with `snippet`. Subsequent lines of the snippet are indented like
+/// the line it's inserted on, and variables are resolved with `variables` (see
+/// [`Snippet::render`]). Returns the transaction along with a selection for each tabstop,
+/// positioned in the document as it is after the transaction was applied.
+pub fn into_transaction(
+    snippet: &Snippet,
+    doc: &Rope,
+    start: usize,
+    end: usize,
+    line_ending: LineEnding,
+    variables: impl Fn(&str) -> Option<String>,
+) -> (Transaction, Vec<Selection>) {
+    let line = doc.line(doc.char_to_line(start));
+    let indent: String = line
+        .chars()
+        .take_while(|&ch| ch == ' ' || ch == '\t')
+        .collect();
+    let newline = format!("{}{}", line_ending.as_str(), indent);
+
+    let (text, tabstops) = snippet.render(&newline, variables);
+    let replacement = if text.is_empty() {
+        None
+    } else {
+        Some(Tendril::from(text.as_str()))
+    };
+    let transaction = Transaction::change(doc, std::iter::once((start, end, replacement)));
+
+    let tabstops = tabstops
+        .into_iter()
+        .map(|ranges| {
+            let ranges = ranges
+                .into_iter()
+                .map(|range| Range::new(range.anchor + start, range.head + start))
+                .collect();
+            Selection::new(ranges, 0)
+        })
+        .collect();
+
+    (transaction, tabstops)
+}
+
+/// A snippet whose tabstops are being visited. The tabstops are kept in sync with further edits.
+#[derive(Debug, Clone)]
+pub struct ActiveSnippet {
+    tabstops: Vec<Selection>,
+    current: usize,
+}
+
+impl ActiveSnippet {
+    /// Start at the first of `tabstops`. Returns `None` if there's nothing left to visit after it.
+    pub fn new(tabstops: Vec<Selection>) -> Option<Self> {
+        if tabstops.len() > 1 {
+            Some(Self {
+                tabstops,
+                current: 0,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn map(&mut self, changes: &ChangeSet) {
+        for tabstop in &mut self.tabstops {
+            *tabstop = tabstop.clone().map(changes);
+        }
+    }
+
+    /// The selection of the tabstop being visited.
+    pub fn current(&self) -> &Selection {
+        &self.tabstops[self.current]
+    }
+
+    /// Advance to the next tabstop and return its selection.
+    pub fn next_tabstop(&mut self) -> Option<Selection> {
+        if self.current + 1 >= self.tabstops.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.tabstops[self.current].clone())
+    }
+
+    /// Go back to the previous tabstop and return its selection.
+    pub fn prev_tabstop(&mut self) -> Option<Selection> {
+        if self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        Some(self.tabstops[self.current].clone())
+    }
+
+    /// Whether the final tabstop (`$0`) was reached, after which the snippet is done.
+    pub fn is_done(&self) -> bool {
+        self.current + 1 == self.tabstops.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use SnippetElement::*;
+
+    fn text(s: &str) -> SnippetElement {
+        Text(s.to_string())
+    }
+
+    #[test]
+    fn parse_tabstops_and_placeholders() {
+        assert_eq!(
+            parse("match(${1:value}) {$2}$0").elements,
+            vec![
+                text("match("),
+                Placeholder {
+                    tabstop: 1,
+                    value: vec![text("value")],
+                },
+                text(") {"),
+                Tabstop { tabstop: 2 },
+                text("}"),
+                Tabstop { tabstop: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_nested_placeholder() {
+        assert_eq!(
+            parse("${1:foo ${2:bar}}").elements,
+            vec![Placeholder {
+                tabstop: 1,
+                value: vec![
+                    text("foo "),
+                    Placeholder {
+                        tabstop: 2,
+                        value: vec![text("bar")],
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_choices_and_variables() {
+        assert_eq!(
+            parse("${1|one,t\\,wo|} $TM_FILENAME ${NAME:default} ${VAR/(.*)/$1/g}").elements,
+            vec![
+                Choice {
+                    tabstop: 1,
+                    choices: vec!["one".to_string(), "t,wo".to_string()],
+                },
+                text(" "),
+                Variable {
+                    name: "TM_FILENAME".to_string(),
+                    default: vec![],
+                    transform: None,
+                },
+                text(" "),
+                Variable {
+                    name: "NAME".to_string(),
+                    default: vec![text("default")],
+                    transform: None,
+                },
+                text(" "),
+                Variable {
+                    name: "VAR".to_string(),
+                    default: vec![],
+                    transform: Some(Transform {
+                        regex: "(.*)".to_string(),
+                        format: "$1".to_string(),
+                        options: "g".to_string(),
+                    }),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_escapes_and_invalid() {
+        assert_eq!(
+            parse("\\$1 \\} $ ${1:unterminated").elements,
+            vec![text("$1 } $ ${1:unterminated")]
+        );
+    }
+
+    #[test]
+    fn render_tabstops() {
+        let snippet = parse("fn ${1:name}($2) {\n\t$0\n}\n$1");
+        let (text, tabstops) = snippet.render("\n    ", |_| None);
+        assert_eq!(text, "fn name() {\n    \t\n    }\n    ");
+        assert_eq!(
+            tabstops,
+            vec![
+                vec![Range::new(3, 7), Range::point(28)],
+                vec![Range::point(8)],
+                vec![Range::point(17)],
+            ]
+        );
+    }
+
+    #[test]
+    fn render_variables() {
+        let snippet = parse("$TM_FILENAME ${UNKNOWN:fallback} ${1:$TM_LINE_NUMBER}$UNKNOWN.");
+        let (text, tabstops) = snippet.render("\n", |name| match name {
+            "TM_FILENAME" => Some("main.rs".to_string()),
+            "TM_LINE_NUMBER" => Some("12".to_string()),
+            _ => None,
+        });
+        // unknown variables are replaced with their default, which may be empty
+        assert_eq!(text, "main.rs fallback 12.");
+        assert_eq!(tabstops[0], vec![Range::new(17, 19)]);
+    }
+
+    #[test]
+    fn transaction_and_active_snippet() {
+        let mut doc = Rope::from("    foo.ba\n");
+        let snippet = parse("bar(${1:x}, $2)");
+        let (transaction, tabstops) =
+            into_transaction(&snippet, &doc, 8, 10, LineEnding::LF, |_| None);
+        assert!(transaction.apply(&mut doc));
+        assert_eq!(doc, "    foo.bar(x, )\n");
+        assert_eq!(tabstops[0], Selection::single(12, 13));
+
+        let mut snippet = ActiveSnippet::new(tabstops).unwrap();
+        // typing at the first tabstop moves the later ones
+        let transaction = Transaction::change(&doc, std::iter::once((12, 12, Some("ab".into()))));
+        assert!(transaction.apply(&mut doc));
+        snippet.map(transaction.changes());
+
+        assert_eq!(snippet.next_tabstop(), Some(Selection::point(17)));
+        assert_eq!(snippet.next_tabstop(), Some(Selection::point(18)));
+        assert!(snippet.is_done());
+        assert_eq!(snippet.next_tabstop(), None);
+        assert_eq!(snippet.prev_tabstop(), Some(Selection::point(17)));
+    }
+}
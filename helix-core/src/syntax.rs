@@ -30,6 +30,26 @@ where
         .transpose()
 }
 
+fn deserialize_language_servers<'de, D>(
+    deserializer: D,
+) -> Result<Vec<LanguageServerConfiguration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    // a single server can still be configured as a table instead of a list of them
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(LanguageServerConfiguration),
+        Many(Vec<LanguageServerConfiguration>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(server) => vec![server],
+        OneOrMany::Many(servers) => servers,
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Configuration {
     pub language: Vec<LanguageConfiguration>,
@@ -45,6 +65,9 @@ pub struct LanguageConfiguration {
     pub file_types: Vec<String>, // filename ends_with? <Gemfile, rb, etc>
     pub roots: Vec<String>,      // these indicate project roots <.git, Cargo.toml>
     pub comment_token: Option<String>,
+    /// Deprecated: JSON sent as the `initializationOptions` of the first language server, use
+    /// its `initialization-options` instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub config: Option<String>,
 
     #[serde(default)]
@@ -58,22 +81,100 @@ pub struct LanguageConfiguration {
     #[serde(skip)]
     pub(crate) highlight_config: OnceCell<Option<Arc<HighlightConfiguration>>>,
     // tags_config OnceCell<> https://github.com/tree-sitter/tree-sitter/pull/583
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub language_server: Option<LanguageServerConfiguration>,
+    /// Language servers to start for this language. Requests go to the first server that
+    /// supports them.
+    #[serde(
+        default,
+        rename = "language-server",
+        deserialize_with = "deserialize_language_servers",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub language_servers: Vec<LanguageServerConfiguration>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub indent: Option<IndentationConfiguration>,
 
     #[serde(skip)]
     pub(crate) indent_query: OnceCell<Option<IndentQuery>>,
+    #[serde(skip)]
+    pub(crate) textobject_query: OnceCell<Option<TextObjectQuery>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct LanguageServerConfiguration {
     pub command: String,
     #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub args: Vec<String>,
+    /// Only use the server for these features.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub only_features: Vec<LanguageServerFeature>,
+    /// Never use the server for these features.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub except_features: Vec<LanguageServerFeature>,
+    /// Sent as `initializationOptions` when initializing the server.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initialization_options: Option<toml::Value>,
+    /// Sent through `workspace/didChangeConfiguration` once the server is initialized, and used to
+    /// answer `workspace/configuration` requests.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<toml::Value>,
+    /// Environment variables set for the server process.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub environment: HashMap<String, String>,
+    /// Seconds to wait for a response before a request fails.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Write the JSON-RPC messages exchanged with the server to a trace file.
+    #[serde(default)]
+    pub trace: bool,
+}
+
+fn default_timeout() -> u64 {
+    20
+}
+
+impl LanguageServerConfiguration {
+    /// Whether the configuration allows using the server for `feature`. The server might still
+    /// lack the capability for it.
+    pub fn has_feature(&self, feature: LanguageServerFeature) -> bool {
+        (self.only_features.is_empty() || self.only_features.contains(&feature))
+            && !self.except_features.contains(&feature)
+    }
+}
+
+/// Editor features provided by language servers, used to route requests when a language has
+/// several servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LanguageServerFeature {
+    Format,
+    FormatSelections,
+    OnTypeFormatting,
+    GotoDefinition,
+    GotoTypeDefinition,
+    GotoImplementation,
+    GotoReference,
+    SignatureHelp,
+    Hover,
+    Completion,
+    CodeAction,
+    WorkspaceCommand,
+    DocumentSymbols,
+    WorkspaceSymbols,
+    RenameSymbol,
+    Diagnostics,
+    InlayHints,
+    DocumentHighlight,
+    CallHierarchy,
+    TypeHierarchy,
+    SemanticTokens,
+    CodeLens,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,6 +195,37 @@ pub struct IndentQuery {
     pub outdent: HashSet<String>,
 }
 
+#[derive(Debug)]
+pub struct TextObjectQuery {
+    pub query: Query,
+}
+
+impl TextObjectQuery {
+    /// Run the query on the given node and return the nodes captured under `capture_name`.
+    /// Returns `None` if the query doesn't define such a capture.
+    pub fn capture_nodes<'a>(
+        &'a self,
+        capture_name: &str,
+        node: Node<'a>,
+        slice: RopeSlice<'a>,
+        cursor: &'a mut QueryCursor,
+    ) -> Option<impl Iterator<Item = Node<'a>>> {
+        let capture_idx = self.query.capture_index_for_name(capture_name)?;
+        let nodes = cursor
+            .captures(&self.query, node, RopeProvider(slice))
+            .filter_map(move |(mat, idx)| {
+                let capture = mat.captures[idx];
+                if capture.index == capture_idx {
+                    Some(capture.node)
+                } else {
+                    None
+                }
+            });
+
+        Some(nodes)
+    }
+}
+
 fn load_runtime_file(language: &str, filename: &str) -> Result<String, std::io::Error> {
     let path = crate::RUNTIME_DIR
         .join("queries")
@@ -192,6 +324,23 @@ impl LanguageConfiguration {
             .as_ref()
     }
 
+    pub fn textobject_query(&self) -> Option<&TextObjectQuery> {
+        self.textobject_query
+            .get_or_init(|| {
+                let language = self.language_id.to_ascii_lowercase();
+
+                let query = read_query(&language, "textobjects.scm");
+                if query.is_empty() {
+                    return None;
+                }
+                let language = get_language(&crate::RUNTIME_DIR, &self.language_id).ok()?;
+                // TODO: surface invalid queries to the user
+                let query = Query::new(language, &query).ok()?;
+                Some(TextObjectQuery { query })
+            })
+            .as_ref()
+    }
+
     pub fn scope(&self) -> &str {
         &self.scope
     }
@@ -1793,11 +1942,89 @@ impl<I: Iterator<Item = HighlightEvent>> Iterator for Merge<I> {
     }
 }
 
+/// A loader with only Rust configured, reading its queries from the runtime directory.
+#[cfg(test)]
+pub(crate) fn rust_loader() -> Loader {
+    let loader = Loader::new(Configuration {
+        language: vec![LanguageConfiguration {
+            scope: "source.rust".to_string(),
+            file_types: vec!["rs".to_string()],
+            language_id: "Rust".to_string(),
+            highlight_config: OnceCell::new(),
+            injection_regex: None,
+            roots: vec![],
+            comment_token: None,
+            config: None,
+            auto_format: false,
+            language_servers: Vec::new(),
+            indent: None,
+            indent_query: OnceCell::new(),
+            textobject_query: OnceCell::new(),
+        }],
+    });
+
+    // set runtime path so we can find the queries
+    let mut runtime = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    runtime.push("../runtime");
+    std::env::set_var("HELIX_RUNTIME", runtime.to_str().unwrap());
+
+    loader
+}
+
+/// Parse `doc` as Rust.
+#[cfg(test)]
+pub(crate) fn rust_syntax(doc: &Rope) -> Syntax {
+    let loader = rust_loader();
+    let language_config = loader.language_config_for_scope("source.rust").unwrap();
+    let highlight_config = language_config.highlight_config(&[]).unwrap();
+    Syntax::new(doc, highlight_config)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{Rope, Transaction};
 
+    #[test]
+    fn language_server_configuration() {
+        let config: Configuration = toml::from_str(
+            r#"
+            [[language]]
+            name = "rust"
+            scope = "source.rust"
+            file-types = ["rs"]
+            roots = []
+            language-server = { command = "rust-analyzer" }
+
+            [[language]]
+            name = "python"
+            scope = "source.python"
+            file-types = ["py"]
+            roots = []
+            language-server = [
+                { command = "pyright-langserver", args = ["--stdio"], except-features = ["format"] },
+                { command = "ruff-lsp", only-features = ["diagnostics", "format"], timeout = 5 },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let rust = &config.language[0].language_servers;
+        assert_eq!(rust.len(), 1);
+        assert_eq!(rust[0].command, "rust-analyzer");
+        assert!(rust[0].has_feature(LanguageServerFeature::Format));
+        assert_eq!(rust[0].timeout, 20);
+        assert!(rust[0].initialization_options.is_none());
+
+        let python = &config.language[1].language_servers;
+        assert_eq!(python.len(), 2);
+        assert!(!python[0].has_feature(LanguageServerFeature::Format));
+        assert!(python[0].has_feature(LanguageServerFeature::Hover));
+        assert!(python[1].has_feature(LanguageServerFeature::Format));
+        assert!(!python[1].has_feature(LanguageServerFeature::Hover));
+        assert_eq!(python[1].timeout, 5);
+    }
+
     #[test]
     fn test_parser() {
         let highlight_names: Vec<String> = [
@@ -1,9 +1,13 @@
+use std::fmt::Display;
+
 use ropey::RopeSlice;
+use tree_sitter::{Node, QueryCursor};
 
 use crate::chars::{categorize_char, char_is_whitespace, CharCategory};
 use crate::graphemes::next_grapheme_boundary;
 use crate::movement::Direction;
 use crate::surround;
+use crate::syntax::LanguageConfiguration;
 use crate::Range;
 
 fn find_word_boundary(slice: RopeSlice, mut pos: usize, direction: Direction) -> usize {
@@ -51,6 +55,15 @@ pub enum TextObject {
     Inside,
 }
 
+impl Display for TextObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Around => "around",
+            Self::Inside => "inside",
+        })
+    }
+}
+
 // count doesn't do anything yet
 pub fn textobject_word(
     slice: RopeSlice,
@@ -108,6 +121,44 @@ pub fn textobject_surround(
         .unwrap_or(range)
 }
 
+/// Select the smallest node captured as `<object_name>.inside` or `<object_name>.around` in the
+/// language's `textobjects.scm` query that contains the cursor.
+// count doesn't do anything yet
+pub fn textobject_treesitter(
+    slice: RopeSlice,
+    range: Range,
+    textobject: TextObject,
+    object_name: &str,
+    slice_tree: Node,
+    lang_config: &LanguageConfiguration,
+    _count: usize,
+) -> Range {
+    let get_range = move || -> Option<Range> {
+        let byte_pos = slice.char_to_byte(range.cursor(slice));
+
+        let capture_name = format!("{}.{}", object_name, textobject); // eg. function.inside
+        let mut cursor = QueryCursor::new();
+        let node = lang_config
+            .textobject_query()?
+            .capture_nodes(&capture_name, slice_tree, slice, &mut cursor)?
+            .filter(|node| node.byte_range().contains(&byte_pos))
+            .min_by_key(|node| node.byte_range().len())?;
+
+        let len = slice.len_bytes();
+        let start_byte = node.start_byte();
+        let end_byte = node.end_byte();
+        if start_byte > len || end_byte > len {
+            return None;
+        }
+
+        let start_char = slice.byte_to_char(start_byte);
+        let end_char = slice.byte_to_char(end_byte);
+
+        Some(Range::new(start_char, end_char))
+    };
+    get_range().unwrap_or(range)
+}
+
 #[cfg(test)]
 mod test {
     use super::TextObject::*;
@@ -318,4 +369,53 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_textobject_treesitter() {
+        use crate::syntax::{rust_loader, rust_syntax};
+
+        let doc = Rope::from(
+            "// comment
+struct Foo {
+    bar: usize,
+}
+
+fn foo(a: usize, b: Foo) -> usize {
+    a + b.bar
+}
+",
+        );
+
+        let loader = rust_loader();
+        let language_config = loader.language_config_for_scope("source.rust").unwrap();
+        let syntax = rust_syntax(&doc);
+        let slice = doc.slice(..);
+
+        // (cursor position, textobject, object name, final range)
+        let tests = &[
+            (3, Inside, "comment", (0, 10)),
+            (30, Inside, "class", (22, 41)),
+            (30, Around, "class", (11, 41)),
+            (80, Inside, "function", (77, 94)),
+            (56, Around, "function", (43, 94)),
+            (53, Inside, "parameter", (50, 58)),
+            (62, Around, "parameter", (60, 66)),
+            // no match leaves the selection untouched
+            (42, Around, "function", (42, 42)),
+        ];
+
+        for &case in tests {
+            let (pos, objtype, obj_name, expected_range) = case;
+            let result = textobject_treesitter(
+                slice,
+                Range::point(pos),
+                objtype,
+                obj_name,
+                syntax.tree().root_node(),
+                &language_config,
+                1,
+            );
+            assert_eq!(result, expected_range.into(), "\nCase failed: {:?}", case);
+        }
+    }
 }
@@ -1,17 +1,23 @@
 use crate::{
-    transport::{Payload, Transport},
+    trace::Trace,
+    transport::{Payload, RequestLatencies, RequestLatency, Transport},
     Call, Error, OffsetEncoding, Result,
 };
 
-use helix_core::{find_root, ChangeSet, Rope};
+use helix_core::{
+    syntax::{LanguageServerConfiguration, LanguageServerFeature},
+    ChangeSet, Rope,
+};
 use jsonrpc_core as jsonrpc;
 use lsp_types as lsp;
 use serde_json::Value;
+use std::collections::{hash_map::Entry, HashMap};
 use std::future::Future;
+use std::path::Path;
 use std::process::Stdio;
 use std::sync::{
     atomic::{AtomicU64, Ordering},
-    Arc,
+    Arc, Mutex,
 };
 use tokio::{
     io::{BufReader, BufWriter},
@@ -25,24 +31,65 @@ use tokio::{
 #[derive(Debug)]
 pub struct Client {
     id: usize,
-    _process: Child,
+    process: Child,
     server_tx: UnboundedSender<Payload>,
     request_counter: AtomicU64,
+    latencies: RequestLatencies,
     pub(crate) capabilities: OnceCell<lsp::ServerCapabilities>,
+    /// Whether the server advertised `typeHierarchyProvider`, which lsp-types doesn't parse.
+    type_hierarchy_provider: OnceCell<bool>,
     offset_encoding: OffsetEncoding,
     config: Option<Value>,
+    settings: Option<Value>,
+    server_config: LanguageServerConfiguration,
+    /// Edits that haven't been sent to the server yet, by document.
+    pending_changes: Mutex<HashMap<lsp::Url, PendingChanges>>,
+    /// Version of each open document as last sent to the server.
+    document_versions: Mutex<HashMap<lsp::Url, i32>>,
+    /// Capabilities registered through `client/registerCapability`, by registration id.
+    dynamic_capabilities: Mutex<HashMap<String, lsp::Registration>>,
+    workspace_folders: Mutex<WorkspaceFolders>,
+}
+
+#[derive(Debug, Default)]
+struct WorkspaceFolders {
+    folders: Vec<lsp::WorkspaceFolder>,
+    /// Whether folders added from now on have to be announced with
+    /// `workspace/didChangeWorkspaceFolders`, which is the case once the server is initialized.
+    announce: bool,
+}
+
+/// Consecutive edits to a document, collected so they can be sent in one
+/// `textDocument/didChange`.
+#[derive(Debug)]
+struct PendingChanges {
+    version: i32,
+    text: Rope,
+    /// Incremental changes in the order they were made, empty for full sync.
+    changes: Vec<lsp::TextDocumentContentChangeEvent>,
 }
 
 impl Client {
     #[allow(clippy::type_complexity)]
     pub fn start(
-        cmd: &str,
-        args: &[String],
-        config: Option<Value>,
+        server_config: &LanguageServerConfiguration,
         id: usize,
+        root: Option<&Path>,
     ) -> Result<(Self, UnboundedReceiver<(usize, Call)>, Arc<Notify>)> {
-        let process = Command::new(cmd)
-            .args(args)
+        let config = server_config
+            .initialization_options
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+        let settings = server_config
+            .settings
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?;
+
+        let process = Command::new(&server_config.command)
+            .args(&server_config.args)
+            .envs(&server_config.environment)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -57,17 +104,37 @@ impl Client {
         let reader = BufReader::new(process.stdout.take().expect("Failed to open stdout"));
         let stderr = BufReader::new(process.stderr.take().expect("Failed to open stderr"));
 
+        let trace = if server_config.trace {
+            Trace::open(&server_config.command, id)
+                .map_err(|err| log::error!("failed to open LSP trace file: {}", err))
+                .ok()
+        } else {
+            None
+        };
+        let latencies = RequestLatencies::default();
+
         let (server_rx, server_tx, initialize_notify) =
-            Transport::start(reader, writer, stderr, id);
+            Transport::start(reader, writer, stderr, id, latencies.clone(), trace);
 
         let client = Self {
             id,
-            _process: process,
+            process,
             server_tx,
             request_counter: AtomicU64::new(0),
+            latencies,
             capabilities: OnceCell::new(),
+            type_hierarchy_provider: OnceCell::new(),
             offset_encoding: OffsetEncoding::Utf8,
             config,
+            settings,
+            server_config: server_config.clone(),
+            pending_changes: Mutex::new(HashMap::new()),
+            document_versions: Mutex::new(HashMap::new()),
+            dynamic_capabilities: Mutex::new(HashMap::new()),
+            workspace_folders: Mutex::new(WorkspaceFolders {
+                folders: root.and_then(workspace_folder).into_iter().collect(),
+                announce: false,
+            }),
         };
 
         Ok((client, server_rx, initialize_notify))
@@ -77,6 +144,26 @@ impl Client {
         self.id
     }
 
+    /// Name of the server, which is the command used to start it.
+    pub fn name(&self) -> &str {
+        &self.server_config.command
+    }
+
+    /// Response times of the requests answered so far, by method.
+    pub fn request_latencies(&self) -> Vec<(String, RequestLatency)> {
+        self.latencies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(method, latency)| (method.clone(), *latency))
+            .collect()
+    }
+
+    /// Process id of the server, `None` once it has exited.
+    pub fn pid(&self) -> Option<u32> {
+        self.process.id()
+    }
+
     fn next_request_id(&self) -> jsonrpc::Id {
         let id = self.request_counter.fetch_add(1, Ordering::Relaxed);
         jsonrpc::Id::Num(id)
@@ -107,6 +194,287 @@ impl Client {
         self.offset_encoding
     }
 
+    /// The configured settings of the server.
+    pub fn settings(&self) -> Option<&Value> {
+        self.settings.as_ref()
+    }
+
+    /// Look up a dotted `section` path (like `rust-analyzer.cargo`) in the settings, as asked for
+    /// by `workspace/configuration`. The whole settings are returned if there's no section.
+    pub fn configuration_section(&self, section: Option<&str>) -> Option<&Value> {
+        let mut value = self.settings.as_ref()?;
+        if let Some(section) = section {
+            for key in section.split('.') {
+                value = value.get(key)?;
+            }
+        }
+        Some(value)
+    }
+
+    /// Record capabilities the server registered dynamically.
+    pub fn register_capabilities(&self, registrations: Vec<lsp::Registration>) {
+        let mut dynamic_capabilities = self.dynamic_capabilities.lock().unwrap();
+        for registration in registrations {
+            dynamic_capabilities.insert(registration.id.clone(), registration);
+        }
+    }
+
+    /// Forget dynamically registered capabilities.
+    pub fn unregister_capabilities(&self, unregistrations: &[lsp::Unregistration]) {
+        let mut dynamic_capabilities = self.dynamic_capabilities.lock().unwrap();
+        for unregistration in unregistrations {
+            dynamic_capabilities.remove(&unregistration.id);
+        }
+    }
+
+    /// Dynamic registrations for `method`, e.g. `workspace/didChangeWatchedFiles`.
+    pub fn dynamic_registrations(&self, method: &str) -> Vec<lsp::Registration> {
+        self.dynamic_capabilities
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|registration| registration.method == method)
+            .cloned()
+            .collect()
+    }
+
+    /// Workspace folders the server was told about, starting with the root it was started in.
+    pub fn workspace_folders(&self) -> Vec<lsp::WorkspaceFolder> {
+        self.workspace_folders.lock().unwrap().folders.clone()
+    }
+
+    /// Whether the server accepts `workspace/didChangeWorkspaceFolders`, as opposed to only
+    /// working on the root it was started in.
+    pub fn supports_workspace_folders(&self) -> bool {
+        let method = <lsp::notification::DidChangeWorkspaceFolders as lsp::notification::Notification>::METHOD;
+        if !self.dynamic_registrations(method).is_empty() {
+            return true;
+        }
+
+        let folders = match self.capabilities.get() {
+            Some(lsp::ServerCapabilities {
+                workspace:
+                    Some(lsp::WorkspaceServerCapabilities {
+                        workspace_folders: Some(folders),
+                        ..
+                    }),
+                ..
+            }) => folders,
+            _ => return false,
+        };
+        folders.supported == Some(true)
+            && matches!(
+                folders.change_notifications,
+                Some(lsp::OneOf::Left(true)) | Some(lsp::OneOf::Right(_))
+            )
+    }
+
+    /// Add the project `root` of a newly opened document to the workspace folders. Once the
+    /// server is initialized, new folders are announced to it if it supports them. Returns
+    /// `false` if the server is stuck with a different root.
+    pub fn add_workspace_folder(&self, root: &Path) -> bool {
+        let folder = match workspace_folder(root) {
+            Some(folder) => folder,
+            None => return true,
+        };
+
+        let mut workspace_folders = self.workspace_folders.lock().unwrap();
+        if workspace_folders.folders.contains(&folder) {
+            return true;
+        }
+        if workspace_folders.announce && !self.supports_workspace_folders() {
+            return false;
+        }
+
+        workspace_folders.folders.push(folder.clone());
+        if workspace_folders.announce {
+            self.did_change_workspace_folders(vec![folder], Vec::new());
+        }
+        true
+    }
+
+    /// Announce the folders added while the server was initializing, called once it's
+    /// initialized. `initial` are the folders sent with `initialize`.
+    pub(crate) fn announce_workspace_folders(&self, initial: &[lsp::WorkspaceFolder]) {
+        let mut workspace_folders = self.workspace_folders.lock().unwrap();
+        workspace_folders.announce = true;
+
+        let added: Vec<_> = workspace_folders
+            .folders
+            .iter()
+            .filter(|folder| !initial.contains(folder))
+            .cloned()
+            .collect();
+        if added.is_empty() {
+            return;
+        }
+        if self.supports_workspace_folders() {
+            self.did_change_workspace_folders(added, Vec::new());
+        } else {
+            log::warn!(
+                "language server `{}` doesn't support workspace folders, ignoring {} folder(s)",
+                self.name(),
+                added.len()
+            );
+            workspace_folders
+                .folders
+                .retain(|folder| initial.contains(folder));
+        }
+    }
+
+    /// Methods of all dynamically registered capabilities, sorted.
+    pub fn dynamic_methods(&self) -> Vec<String> {
+        let mut methods: Vec<_> = self
+            .dynamic_capabilities
+            .lock()
+            .unwrap()
+            .values()
+            .map(|registration| registration.method.clone())
+            .collect();
+        methods.sort();
+        methods.dedup();
+        methods
+    }
+
+    /// Whether the server is initialized, advertises the capability for `feature` and isn't
+    /// excluded from it in the configuration.
+    pub fn supports_feature(&self, feature: LanguageServerFeature) -> bool {
+        if !self.server_config.has_feature(feature) {
+            return false;
+        }
+
+        let capabilities = match self.capabilities.get() {
+            Some(capabilities) => capabilities,
+            None => return false,
+        };
+
+        use lsp::OneOf;
+        fn is_enabled<T>(provider: &Option<OneOf<bool, T>>) -> bool {
+            matches!(provider, Some(OneOf::Left(true)) | Some(OneOf::Right(_)))
+        }
+
+        match feature {
+            LanguageServerFeature::Format => is_enabled(&capabilities.document_formatting_provider),
+            LanguageServerFeature::FormatSelections => {
+                is_enabled(&capabilities.document_range_formatting_provider)
+            }
+            LanguageServerFeature::OnTypeFormatting => {
+                capabilities.document_on_type_formatting_provider.is_some()
+            }
+            LanguageServerFeature::GotoDefinition => is_enabled(&capabilities.definition_provider),
+            LanguageServerFeature::GotoTypeDefinition => matches!(
+                capabilities.type_definition_provider,
+                Some(lsp::TypeDefinitionProviderCapability::Simple(true))
+                    | Some(lsp::TypeDefinitionProviderCapability::Options(_))
+            ),
+            LanguageServerFeature::GotoImplementation => matches!(
+                capabilities.implementation_provider,
+                Some(lsp::ImplementationProviderCapability::Simple(true))
+                    | Some(lsp::ImplementationProviderCapability::Options(_))
+            ),
+            LanguageServerFeature::GotoReference => is_enabled(&capabilities.references_provider),
+            LanguageServerFeature::SignatureHelp => capabilities.signature_help_provider.is_some(),
+            LanguageServerFeature::Hover => matches!(
+                capabilities.hover_provider,
+                Some(lsp::HoverProviderCapability::Simple(true))
+                    | Some(lsp::HoverProviderCapability::Options(_))
+            ),
+            LanguageServerFeature::Completion => capabilities.completion_provider.is_some(),
+            LanguageServerFeature::CodeAction => matches!(
+                capabilities.code_action_provider,
+                Some(lsp::CodeActionProviderCapability::Simple(true))
+                    | Some(lsp::CodeActionProviderCapability::Options(_))
+            ),
+            LanguageServerFeature::WorkspaceCommand => {
+                capabilities.execute_command_provider.is_some()
+            }
+            LanguageServerFeature::DocumentSymbols => {
+                is_enabled(&capabilities.document_symbol_provider)
+            }
+            LanguageServerFeature::WorkspaceSymbols => {
+                is_enabled(&capabilities.workspace_symbol_provider)
+            }
+            LanguageServerFeature::RenameSymbol => is_enabled(&capabilities.rename_provider),
+            // diagnostics are pushed by the server without a capability
+            LanguageServerFeature::Diagnostics => true,
+            LanguageServerFeature::InlayHints => is_enabled(&capabilities.inlay_hint_provider),
+            LanguageServerFeature::DocumentHighlight => {
+                is_enabled(&capabilities.document_highlight_provider)
+            }
+            LanguageServerFeature::CallHierarchy => matches!(
+                capabilities.call_hierarchy_provider,
+                Some(lsp::CallHierarchyServerCapability::Simple(true))
+                    | Some(lsp::CallHierarchyServerCapability::Options(_))
+            ),
+            LanguageServerFeature::TypeHierarchy => {
+                self.type_hierarchy_provider.get().copied().unwrap_or(false)
+            }
+            LanguageServerFeature::CodeLens => capabilities.code_lens_provider.is_some(),
+            LanguageServerFeature::SemanticTokens => matches!(
+                self.semantic_tokens_options(),
+                Some(lsp::SemanticTokensOptions {
+                    full: Some(lsp::SemanticTokensFullOptions::Bool(true))
+                        | Some(lsp::SemanticTokensFullOptions::Delta { .. }),
+                    ..
+                })
+            ),
+        }
+    }
+
+    fn semantic_tokens_options(&self) -> Option<&lsp::SemanticTokensOptions> {
+        match self.capabilities.get()?.semantic_tokens_provider.as_ref()? {
+            lsp::SemanticTokensServerCapabilities::SemanticTokensOptions(options) => Some(options),
+            lsp::SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options) => {
+                Some(&options.semantic_tokens_options)
+            }
+        }
+    }
+
+    fn diagnostic_options(&self) -> Option<&lsp::DiagnosticOptions> {
+        match self.capabilities.get()?.diagnostic_provider.as_ref()? {
+            lsp::DiagnosticServerCapabilities::Options(options) => Some(options),
+            lsp::DiagnosticServerCapabilities::RegistrationOptions(options) => {
+                Some(&options.diagnostic_options)
+            }
+        }
+    }
+
+    /// Whether diagnostics can be pulled from the server with `textDocument/diagnostic` instead
+    /// of waiting for them to be published.
+    pub fn supports_pull_diagnostics(&self) -> bool {
+        self.supports_feature(LanguageServerFeature::Diagnostics)
+            && self.diagnostic_options().is_some()
+    }
+
+    /// Whether diagnostics of the whole workspace can be pulled with `workspace/diagnostic`.
+    pub fn supports_workspace_diagnostics(&self) -> bool {
+        self.supports_feature(LanguageServerFeature::Diagnostics)
+            && matches!(
+                self.diagnostic_options(),
+                Some(lsp::DiagnosticOptions {
+                    workspace_diagnostics: true,
+                    ..
+                })
+            )
+    }
+
+    /// The legend the server uses to encode token types and modifiers in semantic tokens.
+    pub fn semantic_tokens_legend(&self) -> Option<&lsp::SemanticTokensLegend> {
+        self.semantic_tokens_options()
+            .map(|options| &options.legend)
+    }
+
+    /// Whether the server can send semantic tokens as edits to a previous result.
+    pub fn supports_semantic_tokens_delta(&self) -> bool {
+        matches!(
+            self.semantic_tokens_options(),
+            Some(lsp::SemanticTokensOptions {
+                full: Some(lsp::SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                ..
+            })
+        )
+    }
+
     /// Execute a RPC request on the language server.
     async fn request<R: lsp::request::Request>(&self, params: R::Params) -> Result<R::Result>
     where
@@ -127,8 +495,12 @@ impl Client {
     where
         R::Params: serde::Serialize,
     {
+        // the request has to see every edit made before it
+        self.flush_changes();
+
         let server_tx = self.server_tx.clone();
         let id = self.next_request_id();
+        let timeout_secs = self.server_config.timeout;
 
         async move {
             use std::time::Duration;
@@ -152,8 +524,8 @@ impl Client {
                 })
                 .map_err(|e| Error::Other(e.into()))?;
 
-            // TODO: specifiable timeout, delay other calls until initialize success
-            timeout(Duration::from_secs(20), rx.recv())
+            // TODO: delay other calls until initialize success
+            timeout(Duration::from_secs(timeout_secs), rx.recv())
                 .await
                 .map_err(|_| Error::Timeout)? // return Timeout
                 .ok_or(Error::StreamClosed)?
@@ -161,6 +533,9 @@ impl Client {
     }
 
     /// Send a RPC notification to the language server.
+    ///
+    /// Pending document changes are flushed first and the notification is queued right away, so
+    /// notifications always reach the server in the order they were made.
     pub fn notify<R: lsp::notification::Notification>(
         &self,
         params: R::Params,
@@ -168,23 +543,25 @@ impl Client {
     where
         R::Params: serde::Serialize,
     {
-        let server_tx = self.server_tx.clone();
-
-        async move {
-            let params = serde_json::to_value(params)?;
+        self.flush_changes();
+        std::future::ready(self.send_notification::<R>(params))
+    }
 
-            let notification = jsonrpc::Notification {
-                jsonrpc: Some(jsonrpc::Version::V2),
-                method: R::METHOD.to_string(),
-                params: Self::value_into_params(params),
-            };
+    fn send_notification<R: lsp::notification::Notification>(&self, params: R::Params) -> Result<()>
+    where
+        R::Params: serde::Serialize,
+    {
+        let params = serde_json::to_value(params)?;
 
-            server_tx
-                .send(Payload::Notification(notification))
-                .map_err(|e| Error::Other(e.into()))?;
+        let notification = jsonrpc::Notification {
+            jsonrpc: Some(jsonrpc::Version::V2),
+            method: R::METHOD.to_string(),
+            params: Self::value_into_params(params),
+        };
 
-            Ok(())
-        }
+        self.server_tx
+            .send(Payload::Notification(notification))
+            .map_err(|e| Error::Other(e.into()))
     }
 
     /// Reply to a language server RPC call.
@@ -223,9 +600,12 @@ impl Client {
     // General messages
     // -------------------------------------------------------------------------------------------
 
-    pub(crate) async fn initialize(&self) -> Result<lsp::InitializeResult> {
+    pub(crate) async fn initialize(
+        &self,
+        workspace_folders: Vec<lsp::WorkspaceFolder>,
+    ) -> Result<lsp::InitializeResult> {
         // TODO: delay any requests that are triggered prior to initialize
-        let root = find_root(None).and_then(|root| lsp::Url::from_file_path(root).ok());
+        let root = workspace_folders.first().map(|folder| folder.uri.clone());
 
         if self.config.is_some() {
             log::info!("Using custom LSP config: {}", self.config.as_ref().unwrap());
@@ -242,7 +622,7 @@ impl Client {
                 text_document: Some(lsp::TextDocumentClientCapabilities {
                     completion: Some(lsp::CompletionClientCapabilities {
                         completion_item: Some(lsp::CompletionItemCapability {
-                            snippet_support: Some(false),
+                            snippet_support: Some(true),
                             ..Default::default()
                         }),
                         completion_item_kind: Some(lsp::CompletionItemKindCapability {
@@ -257,6 +637,78 @@ impl Client {
                         content_format: Some(vec![lsp::MarkupKind::Markdown]),
                         ..Default::default()
                     }),
+                    rename: Some(lsp::RenameClientCapabilities {
+                        dynamic_registration: Some(false),
+                        prepare_support: Some(true),
+                        prepare_support_default_behavior: None,
+                        honors_change_annotations: Some(false),
+                    }),
+                    call_hierarchy: Some(lsp::CallHierarchyClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    type_hierarchy: Some(lsp::TypeHierarchyClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    inlay_hint: Some(lsp::InlayHintClientCapabilities {
+                        dynamic_registration: Some(false),
+                        resolve_support: None,
+                    }),
+                    code_lens: Some(lsp::CodeLensClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    semantic_tokens: Some(lsp::SemanticTokensClientCapabilities {
+                        dynamic_registration: Some(false),
+                        requests: lsp::SemanticTokensClientCapabilitiesRequests {
+                            range: Some(false),
+                            full: Some(lsp::SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                        },
+                        token_types: vec![
+                            lsp::SemanticTokenType::NAMESPACE,
+                            lsp::SemanticTokenType::TYPE,
+                            lsp::SemanticTokenType::CLASS,
+                            lsp::SemanticTokenType::ENUM,
+                            lsp::SemanticTokenType::INTERFACE,
+                            lsp::SemanticTokenType::STRUCT,
+                            lsp::SemanticTokenType::TYPE_PARAMETER,
+                            lsp::SemanticTokenType::PARAMETER,
+                            lsp::SemanticTokenType::VARIABLE,
+                            lsp::SemanticTokenType::PROPERTY,
+                            lsp::SemanticTokenType::ENUM_MEMBER,
+                            lsp::SemanticTokenType::EVENT,
+                            lsp::SemanticTokenType::FUNCTION,
+                            lsp::SemanticTokenType::METHOD,
+                            lsp::SemanticTokenType::MACRO,
+                            lsp::SemanticTokenType::KEYWORD,
+                            lsp::SemanticTokenType::MODIFIER,
+                            lsp::SemanticTokenType::COMMENT,
+                            lsp::SemanticTokenType::STRING,
+                            lsp::SemanticTokenType::NUMBER,
+                            lsp::SemanticTokenType::REGEXP,
+                            lsp::SemanticTokenType::OPERATOR,
+                            lsp::SemanticTokenType::DECORATOR,
+                        ],
+                        token_modifiers: vec![
+                            lsp::SemanticTokenModifier::DECLARATION,
+                            lsp::SemanticTokenModifier::DEFINITION,
+                            lsp::SemanticTokenModifier::READONLY,
+                            lsp::SemanticTokenModifier::STATIC,
+                            lsp::SemanticTokenModifier::DEPRECATED,
+                            lsp::SemanticTokenModifier::ABSTRACT,
+                            lsp::SemanticTokenModifier::ASYNC,
+                            lsp::SemanticTokenModifier::MODIFICATION,
+                            lsp::SemanticTokenModifier::DOCUMENTATION,
+                            lsp::SemanticTokenModifier::DEFAULT_LIBRARY,
+                        ],
+                        formats: vec![lsp::TokenFormat::RELATIVE],
+                        overlapping_token_support: Some(false),
+                        multiline_token_support: Some(false),
+                        server_cancel_support: Some(false),
+                        augments_syntax_tokens: Some(true),
+                    }),
+                    diagnostic: Some(lsp::DiagnosticClientCapabilities {
+                        dynamic_registration: Some(false),
+                        related_document_support: Some(false),
+                    }),
                     code_action: Some(lsp::CodeActionClientCapabilities {
                         code_action_literal_support: Some(lsp::CodeActionLiteralSupport {
                             code_action_kind: lsp::CodeActionKindLiteralSupport {
@@ -281,17 +733,59 @@ impl Client {
                 }),
                 window: Some(lsp::WindowClientCapabilities {
                     work_done_progress: Some(true),
+                    show_message: Some(lsp::ShowMessageRequestClientCapabilities {
+                        message_action_item: Some(lsp::MessageActionItemCapabilities {
+                            additional_properties_support: Some(false),
+                        }),
+                    }),
+                    show_document: Some(lsp::ShowDocumentClientCapabilities { support: true }),
+                }),
+                workspace: Some(lsp::WorkspaceClientCapabilities {
+                    apply_edit: Some(true),
+                    workspace_folders: Some(true),
+                    workspace_edit: Some(lsp::WorkspaceEditClientCapabilities {
+                        document_changes: Some(true),
+                        resource_operations: Some(vec![
+                            lsp::ResourceOperationKind::Create,
+                            lsp::ResourceOperationKind::Rename,
+                            lsp::ResourceOperationKind::Delete,
+                        ]),
+                        failure_handling: Some(lsp::FailureHandlingKind::Abort),
+                        ..Default::default()
+                    }),
+                    execute_command: Some(lsp::DynamicRegistrationClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    configuration: Some(true),
+                    did_change_configuration: Some(lsp::DynamicRegistrationClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    did_change_watched_files: Some(lsp::DidChangeWatchedFilesClientCapabilities {
+                        dynamic_registration: Some(true),
+                        relative_pattern_support: Some(true),
+                    }),
+                    diagnostic: Some(lsp::DiagnosticWorkspaceClientCapabilities {
+                        refresh_support: Some(true),
+                    }),
                     ..Default::default()
                 }),
                 ..Default::default()
             },
             trace: None,
-            workspace_folders: None,
+            workspace_folders: Some(workspace_folders),
             client_info: None,
             locale: None, // TODO
         };
 
-        self.request::<lsp::request::Initialize>(params).await
+        // keep the raw response around for capabilities lsp-types doesn't parse yet
+        let response = self.call::<lsp::request::Initialize>(params).await?;
+        let type_hierarchy_provider = matches!(
+            response.pointer("/capabilities/typeHierarchyProvider"),
+            Some(Value::Bool(true)) | Some(Value::Object(_))
+        );
+        let _ = self.type_hierarchy_provider.set(type_hierarchy_provider);
+
+        Ok(serde_json::from_value(response)?)
     }
 
     pub async fn shutdown(&self) -> Result<()> {
@@ -317,6 +811,40 @@ impl Client {
         self.exit().await
     }
 
+    // -------------------------------------------------------------------------------------------
+    // Workspace
+    // -------------------------------------------------------------------------------------------
+
+    pub fn did_change_configuration(&self, settings: Value) -> impl Future<Output = Result<()>> {
+        self.notify::<lsp::notification::DidChangeConfiguration>(
+            lsp::DidChangeConfigurationParams { settings },
+        )
+    }
+
+    fn did_change_workspace_folders(
+        &self,
+        added: Vec<lsp::WorkspaceFolder>,
+        removed: Vec<lsp::WorkspaceFolder>,
+    ) {
+        let params = lsp::DidChangeWorkspaceFoldersParams {
+            event: lsp::WorkspaceFoldersChangeEvent { added, removed },
+        };
+        if let Err(err) =
+            self.send_notification::<lsp::notification::DidChangeWorkspaceFolders>(params)
+        {
+            log::error!("failed to send workspace folders: {}", err);
+        }
+    }
+
+    pub fn did_change_watched_files(
+        &self,
+        changes: Vec<lsp::FileEvent>,
+    ) -> impl Future<Output = Result<()>> {
+        self.notify::<lsp::notification::DidChangeWatchedFiles>(lsp::DidChangeWatchedFilesParams {
+            changes,
+        })
+    }
+
     // -------------------------------------------------------------------------------------------
     // Text document
     // -------------------------------------------------------------------------------------------
@@ -328,6 +856,10 @@ impl Client {
         doc: &Rope,
         language_id: String,
     ) -> impl Future<Output = Result<()>> {
+        self.document_versions
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), version);
         self.notify::<lsp::notification::DidOpenTextDocument>(lsp::DidOpenTextDocumentParams {
             text_document: lsp::TextDocumentItem {
                 uri,
@@ -439,57 +971,161 @@ impl Client {
         changes
     }
 
-    pub fn text_document_did_change(
-        &self,
-        text_document: lsp::VersionedTextDocumentIdentifier,
-        old_text: &Rope,
-        new_text: &Rope,
-        changes: &ChangeSet,
-    ) -> Option<impl Future<Output = Result<()>>> {
-        // figure out what kind of sync the server supports
-
-        let capabilities = self.capabilities.get().unwrap();
-
-        let sync_capabilities = match capabilities.text_document_sync {
+    /// How the server wants document changes to be synced. Servers that don't specify a change
+    /// kind don't want changes at all, unknown kinds fall back to sending the full document.
+    pub fn text_document_sync_kind(&self) -> lsp::TextDocumentSyncKind {
+        let kind = match self.capabilities().text_document_sync {
             Some(lsp::TextDocumentSyncCapability::Kind(kind))
             | Some(lsp::TextDocumentSyncCapability::Options(lsp::TextDocumentSyncOptions {
                 change: Some(kind),
                 ..
             })) => kind,
             // None | SyncOptions { changes: None }
-            _ => return None,
+            _ => return lsp::TextDocumentSyncKind::NONE,
         };
 
-        let changes = match sync_capabilities {
-            lsp::TextDocumentSyncKind::Full => {
-                vec![lsp::TextDocumentContentChangeEvent {
-                    // range = None -> whole document
-                    range: None,        //Some(Range)
-                    range_length: None, // u64 apparently deprecated
-                    text: new_text.to_string(),
-                }]
+        match kind {
+            lsp::TextDocumentSyncKind::NONE
+            | lsp::TextDocumentSyncKind::FULL
+            | lsp::TextDocumentSyncKind::INCREMENTAL => kind,
+            kind => {
+                log::warn!(
+                    "unknown text document sync kind {:?}, falling back to full sync",
+                    kind
+                );
+                lsp::TextDocumentSyncKind::FULL
             }
-            lsp::TextDocumentSyncKind::Incremental => {
+        }
+    }
+
+    /// Queue a change to a document. Consecutive changes are sent as a single
+    /// `textDocument/didChange` by the next [`Client::flush_changes`].
+    pub fn text_document_did_change(
+        &self,
+        text_document: lsp::VersionedTextDocumentIdentifier,
+        old_text: &Rope,
+        new_text: &Rope,
+        changes: &ChangeSet,
+    ) {
+        // the sync kind isn't known before the server is initialized, which opens the document
+        // with its latest text anyway
+        if self.capabilities.get().is_none() {
+            return;
+        }
+
+        let changes = match self.text_document_sync_kind() {
+            lsp::TextDocumentSyncKind::NONE => return,
+            lsp::TextDocumentSyncKind::INCREMENTAL => {
                 Self::changeset_to_changes(old_text, new_text, changes, self.offset_encoding)
             }
-            lsp::TextDocumentSyncKind::None => return None,
+            // the full text is taken from the latest version when flushing
+            _ => Vec::new(),
         };
 
-        Some(self.notify::<lsp::notification::DidChangeTextDocument>(
+        let mut pending_changes = self.pending_changes.lock().unwrap();
+        match pending_changes.entry(text_document.uri) {
+            Entry::Occupied(mut entry) => {
+                let pending = entry.get_mut();
+                pending.version = text_document.version;
+                pending.text = new_text.clone();
+                pending.changes.extend(changes);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(PendingChanges {
+                    version: text_document.version,
+                    text: new_text.clone(),
+                    changes,
+                });
+            }
+        }
+    }
+
+    /// Send the changes queued by [`Client::text_document_did_change`]. This happens once per
+    /// event loop tick and before any other message is sent to the server.
+    pub fn flush_changes(&self) {
+        let pending_changes: Vec<_> = self.pending_changes.lock().unwrap().drain().collect();
+
+        for (uri, pending) in pending_changes {
+            let content_changes = match self.text_document_sync_kind() {
+                lsp::TextDocumentSyncKind::INCREMENTAL => pending.changes,
+                _ => vec![Self::full_change(&pending.text)],
+            };
+
+            let text_document = lsp::VersionedTextDocumentIdentifier::new(uri, pending.version);
+            if let Err(err) = self.send_did_change(text_document, content_changes) {
+                log::error!("failed to send textDocument/didChange: {}", err);
+            }
+        }
+    }
+
+    /// Resend the full text of a document, whatever sync kind the server negotiated. Returns
+    /// `None` if the server doesn't want document changes.
+    pub fn text_document_did_change_full(
+        &self,
+        text_document: lsp::VersionedTextDocumentIdentifier,
+        text: &Rope,
+    ) -> Option<impl Future<Output = Result<()>>> {
+        if self.text_document_sync_kind() == lsp::TextDocumentSyncKind::NONE {
+            return None;
+        }
+
+        self.flush_changes();
+        Some(std::future::ready(self.send_did_change(
+            text_document,
+            vec![Self::full_change(text)],
+        )))
+    }
+
+    /// Version of the document as last sent to the server, including changes that are still
+    /// pending.
+    pub fn document_version(&self, uri: &lsp::Url) -> Option<i32> {
+        if let Some(pending) = self.pending_changes.lock().unwrap().get(uri) {
+            return Some(pending.version);
+        }
+        self.document_versions.lock().unwrap().get(uri).copied()
+    }
+
+    fn full_change(text: &Rope) -> lsp::TextDocumentContentChangeEvent {
+        lsp::TextDocumentContentChangeEvent {
+            // range = None -> whole document
+            range: None,        //Some(Range)
+            range_length: None, // u64 apparently deprecated
+            text: text.to_string(),
+        }
+    }
+
+    fn send_did_change(
+        &self,
+        text_document: lsp::VersionedTextDocumentIdentifier,
+        content_changes: Vec<lsp::TextDocumentContentChangeEvent>,
+    ) -> Result<()> {
+        self.document_versions
+            .lock()
+            .unwrap()
+            .insert(text_document.uri.clone(), text_document.version);
+        self.send_notification::<lsp::notification::DidChangeTextDocument>(
             lsp::DidChangeTextDocumentParams {
                 text_document,
-                content_changes: changes,
+                content_changes,
             },
-        ))
+        )
     }
 
     pub fn text_document_did_close(
         &self,
         text_document: lsp::TextDocumentIdentifier,
     ) -> impl Future<Output = Result<()>> {
-        self.notify::<lsp::notification::DidCloseTextDocument>(lsp::DidCloseTextDocumentParams {
-            text_document,
-        })
+        // any pending changes are sent before the document is closed
+        let notify = self.notify::<lsp::notification::DidCloseTextDocument>(
+            lsp::DidCloseTextDocumentParams {
+                text_document: text_document.clone(),
+            },
+        );
+        self.document_versions
+            .lock()
+            .unwrap()
+            .remove(&text_document.uri);
+        notify
     }
 
     // will_save / will_save_wait_until
@@ -586,6 +1222,141 @@ impl Client {
         self.call::<lsp::request::HoverRequest>(params)
     }
 
+    pub fn text_document_inlay_hints(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        range: lsp::Range,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::InlayHintParams {
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+            text_document,
+            range,
+        };
+
+        self.call::<lsp::request::InlayHintRequest>(params)
+    }
+
+    pub fn text_document_diagnostic(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        previous_result_id: Option<String>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::DocumentDiagnosticParams {
+            text_document,
+            identifier: self
+                .diagnostic_options()
+                .and_then(|options| options.identifier.clone()),
+            previous_result_id,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+            partial_result_params: lsp::PartialResultParams::default(),
+        };
+
+        self.call::<lsp::request::DocumentDiagnosticRequest>(params)
+    }
+
+    pub fn workspace_diagnostic(
+        &self,
+        previous_result_ids: Vec<lsp::PreviousResultId>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::WorkspaceDiagnosticParams {
+            identifier: self
+                .diagnostic_options()
+                .and_then(|options| options.identifier.clone()),
+            previous_result_ids,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+            partial_result_params: lsp::PartialResultParams::default(),
+        };
+
+        self.call::<lsp::request::WorkspaceDiagnosticRequest>(params)
+    }
+
+    pub fn text_document_code_lens(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::CodeLensParams {
+            text_document,
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+
+        self.call::<lsp::request::CodeLensRequest>(params)
+    }
+
+    /// Fill in the command of a code lens with `codeLens/resolve`. Returns `None` if the server
+    /// doesn't resolve code lenses.
+    pub fn code_lens_resolve(
+        &self,
+        code_lens: lsp::CodeLens,
+    ) -> Option<impl Future<Output = Result<Value>>> {
+        match self.capabilities.get()?.code_lens_provider {
+            Some(lsp::CodeLensOptions {
+                resolve_provider: Some(true),
+            }) => (),
+            _ => return None,
+        }
+
+        Some(self.call::<lsp::request::CodeLensResolve>(code_lens))
+    }
+
+    pub fn text_document_semantic_tokens_full(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::SemanticTokensParams {
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+            text_document,
+        };
+
+        self.call::<lsp::request::SemanticTokensFullRequest>(params)
+    }
+
+    pub fn text_document_semantic_tokens_full_delta(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        previous_result_id: String,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::SemanticTokensDeltaParams {
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+            text_document,
+            previous_result_id,
+        };
+
+        self.call::<lsp::request::SemanticTokensFullDeltaRequest>(params)
+    }
+
+    pub fn text_document_document_highlight(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        position: lsp::Position,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::DocumentHighlightParams {
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document,
+                position,
+            },
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+
+        self.call::<lsp::request::DocumentHighlightRequest>(params)
+    }
+
     // formatting
 
     pub fn text_document_formatting(
@@ -619,22 +1390,21 @@ impl Client {
         })
     }
 
-    pub async fn text_document_range_formatting(
+    pub fn text_document_range_formatting(
         &self,
         text_document: lsp::TextDocumentIdentifier,
         range: lsp::Range,
         options: lsp::FormattingOptions,
         work_done_token: Option<lsp::ProgressToken>,
-    ) -> anyhow::Result<Vec<lsp::TextEdit>> {
-        let capabilities = self.capabilities.get().unwrap();
+    ) -> Option<impl Future<Output = Result<Vec<lsp::TextEdit>>>> {
+        let capabilities = self.capabilities.get()?;
 
         // check if we're able to format
         match capabilities.document_range_formatting_provider {
             Some(lsp::OneOf::Left(true)) | Some(lsp::OneOf::Right(_)) => (),
             // None | Some(false)
-            _ => return Ok(Vec::new()),
+            _ => return None,
         };
-        // TODO: return err::unavailable so we can fall back to tree sitter formatting
 
         let params = lsp::DocumentRangeFormattingParams {
             text_document,
@@ -643,11 +1413,60 @@ impl Client {
             work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
         };
 
-        let response = self
-            .request::<lsp::request::RangeFormatting>(params)
-            .await?;
+        let request = self.call::<lsp::request::RangeFormatting>(params);
 
-        Ok(response.unwrap_or_default())
+        Some(async move {
+            let json = request.await?;
+            let response: Option<Vec<lsp::TextEdit>> = serde_json::from_value(json)?;
+            Ok(response.unwrap_or_default())
+        })
+    }
+
+    /// Whether typing `ch` should trigger `textDocument/onTypeFormatting`.
+    pub fn is_on_type_formatting_trigger(&self, ch: char) -> bool {
+        let options = match self.capabilities.get() {
+            Some(lsp::ServerCapabilities {
+                document_on_type_formatting_provider: Some(options),
+                ..
+            }) => options,
+            _ => return false,
+        };
+
+        let mut triggers = std::iter::once(&options.first_trigger_character)
+            .chain(options.more_trigger_character.iter().flatten());
+        // triggers are single characters
+        triggers.any(|trigger| trigger.starts_with(ch))
+    }
+
+    /// Format the document after `ch` was typed, with `position` right after it.
+    pub fn text_document_on_type_formatting(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        position: lsp::Position,
+        ch: char,
+        options: lsp::FormattingOptions,
+    ) -> Option<impl Future<Output = Result<Vec<lsp::TextEdit>>>> {
+        self.capabilities
+            .get()?
+            .document_on_type_formatting_provider
+            .as_ref()?;
+
+        let params = lsp::DocumentOnTypeFormattingParams {
+            text_document_position: lsp::TextDocumentPositionParams {
+                text_document,
+                position,
+            },
+            ch: ch.to_string(),
+            options,
+        };
+
+        let request = self.call::<lsp::request::OnTypeFormatting>(params);
+
+        Some(async move {
+            let json = request.await?;
+            let response: Option<Vec<lsp::TextEdit>> = serde_json::from_value(json)?;
+            Ok(response.unwrap_or_default())
+        })
     }
 
     fn goto_request<
@@ -733,6 +1552,92 @@ impl Client {
         self.call::<lsp::request::References>(params)
     }
 
+    pub fn prepare_call_hierarchy(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        position: lsp::Position,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::CallHierarchyPrepareParams {
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document,
+                position,
+            },
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+        };
+
+        self.call::<lsp::request::CallHierarchyPrepare>(params)
+    }
+
+    pub fn call_hierarchy_incoming_calls(
+        &self,
+        item: lsp::CallHierarchyItem,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::CallHierarchyIncomingCallsParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+            partial_result_params: lsp::PartialResultParams::default(),
+        };
+
+        self.call::<lsp::request::CallHierarchyIncomingCalls>(params)
+    }
+
+    pub fn call_hierarchy_outgoing_calls(
+        &self,
+        item: lsp::CallHierarchyItem,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::CallHierarchyOutgoingCallsParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+            partial_result_params: lsp::PartialResultParams::default(),
+        };
+
+        self.call::<lsp::request::CallHierarchyOutgoingCalls>(params)
+    }
+
+    pub fn prepare_type_hierarchy(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        position: lsp::Position,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::TypeHierarchyPrepareParams {
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document,
+                position,
+            },
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+        };
+
+        self.call::<lsp::request::TypeHierarchyPrepare>(params)
+    }
+
+    pub fn type_hierarchy_supertypes(
+        &self,
+        item: lsp::TypeHierarchyItem,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::TypeHierarchySupertypesParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+            partial_result_params: lsp::PartialResultParams::default(),
+        };
+
+        self.call::<lsp::request::TypeHierarchySupertypes>(params)
+    }
+
+    pub fn type_hierarchy_subtypes(
+        &self,
+        item: lsp::TypeHierarchyItem,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::TypeHierarchySubtypesParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+            partial_result_params: lsp::PartialResultParams::default(),
+        };
+
+        self.call::<lsp::request::TypeHierarchySubtypes>(params)
+    }
+
     pub fn document_symbols(
         &self,
         text_document: lsp::TextDocumentIdentifier,
@@ -754,7 +1659,7 @@ impl Client {
             partial_result_params: lsp::PartialResultParams::default(),
         };
 
-        self.call::<lsp::request::WorkspaceSymbol>(params)
+        self.call::<lsp::request::WorkspaceSymbolRequest>(params)
     }
 
     pub fn code_actions(
@@ -772,4 +1677,114 @@ impl Client {
 
         self.call::<lsp::request::CodeActionRequest>(params)
     }
+
+    /// Whether the server can validate a rename location and return the symbol's range with
+    /// `textDocument/prepareRename`.
+    pub fn supports_prepare_rename(&self) -> bool {
+        matches!(
+            self.capabilities().rename_provider,
+            Some(lsp::OneOf::Right(lsp::RenameOptions {
+                prepare_provider: Some(true),
+                ..
+            }))
+        )
+    }
+
+    pub fn prepare_rename(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        position: lsp::Position,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::TextDocumentPositionParams {
+            text_document,
+            position,
+        };
+
+        self.call::<lsp::request::PrepareRenameRequest>(params)
+    }
+
+    pub fn rename_symbol(
+        &self,
+        text_document: lsp::TextDocumentIdentifier,
+        position: lsp::Position,
+        new_name: String,
+    ) -> impl Future<Output = Result<Value>> {
+        let params = lsp::RenameParams {
+            text_document_position: lsp::TextDocumentPositionParams {
+                text_document,
+                position,
+            },
+            new_name,
+            work_done_progress_params: lsp::WorkDoneProgressParams::default(),
+        };
+
+        self.call::<lsp::request::Rename>(params)
+    }
+
+    // -------------------------------------------------------------------------------------------
+    // Workspace
+    // -------------------------------------------------------------------------------------------
+
+    /// Run a command on the server with `workspace/executeCommand`. Returns `None` if the server
+    /// doesn't support executing commands.
+    pub fn command(
+        &self,
+        command: lsp::Command,
+        work_done_token: Option<lsp::ProgressToken>,
+    ) -> Option<impl Future<Output = Result<Value>>> {
+        self.capabilities.get()?.execute_command_provider.as_ref()?;
+
+        let params = lsp::ExecuteCommandParams {
+            command: command.command,
+            arguments: command.arguments.unwrap_or_default(),
+            work_done_progress_params: lsp::WorkDoneProgressParams { work_done_token },
+        };
+
+        Some(self.call::<lsp::request::ExecuteCommand>(params))
+    }
+}
+
+fn workspace_folder(root: &Path) -> Option<lsp::WorkspaceFolder> {
+    let uri = lsp::Url::from_file_path(root).ok()?;
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| uri.to_string());
+    Some(lsp::WorkspaceFolder { uri, name })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn looks_up_configuration_sections() {
+        let config: LanguageServerConfiguration = serde_json::from_value(json!({
+            "command": "cat",
+            "settings": { "rust-analyzer": { "cargo": { "features": "all" } } },
+        }))
+        .unwrap();
+        let (client, _incoming, _initialize_notify) = Client::start(&config, 0, None).unwrap();
+
+        assert_eq!(
+            client.configuration_section(Some("rust-analyzer.cargo.features")),
+            Some(&json!("all"))
+        );
+        assert_eq!(
+            client.configuration_section(Some("rust-analyzer.cargo")),
+            Some(&json!({ "features": "all" }))
+        );
+        assert_eq!(client.configuration_section(None), client.settings());
+        // missing sections, including ones below a value that isn't a table
+        assert_eq!(
+            client.configuration_section(Some("rust-analyzer.check")),
+            None
+        );
+        assert_eq!(client.configuration_section(Some("pyright")), None);
+        assert_eq!(
+            client.configuration_section(Some("rust-analyzer.cargo.features.all")),
+            None
+        );
+    }
 }
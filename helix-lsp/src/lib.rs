@@ -1,4 +1,5 @@
 mod client;
+mod trace;
 mod transport;
 
 pub use client::Client;
@@ -7,12 +8,18 @@ pub use jsonrpc::Call;
 pub use jsonrpc_core as jsonrpc;
 pub use lsp::{Position, Url};
 pub use lsp_types as lsp;
+pub use trace::Trace;
+pub use transport::RequestLatency;
 
 use futures_util::stream::select_all::SelectAll;
-use helix_core::syntax::LanguageConfiguration;
+use helix_core::{
+    find_root,
+    syntax::{LanguageConfiguration, LanguageServerConfiguration},
+};
 
 use std::{
     collections::{hash_map::Entry, HashMap},
+    path::Path,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -21,6 +28,7 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::sync::mpsc::UnboundedReceiver;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -183,6 +191,97 @@ pub mod util {
         )
     }
 
+    /// Combine the edits of several formatting responses, e.g. one per selection, into a single
+    /// list that can be turned into a transaction. Edits are sorted by position, duplicates and
+    /// edits overlapping an earlier one are dropped.
+    pub fn merge_text_edits(edits: Vec<Vec<lsp::TextEdit>>) -> Vec<lsp::TextEdit> {
+        let mut edits: Vec<_> = edits.into_iter().flatten().collect();
+        // stable, so inserts at the same position keep their order
+        edits.sort_by_key(|edit| edit.range.start);
+
+        let mut merged: Vec<lsp::TextEdit> = Vec::with_capacity(edits.len());
+        for edit in edits {
+            if let Some(last) = merged.last() {
+                if *last == edit || edit.range.start < last.range.end {
+                    continue;
+                }
+            }
+            merged.push(edit);
+        }
+        merged
+    }
+
+    /// Applies the edits of a `textDocument/semanticTokens/full/delta` response to the tokens of
+    /// the previous result. Edit offsets count the integers of the encoded array, five per token,
+    /// and don't have to fall on token boundaries. Returns false if the edits left a partial
+    /// token, which is dropped.
+    pub fn apply_semantic_tokens_edits(
+        data: &mut Vec<lsp::SemanticToken>,
+        mut edits: Vec<lsp::SemanticTokensEdit>,
+    ) -> bool {
+        fn flatten(tokens: &[lsp::SemanticToken]) -> impl Iterator<Item = u32> + '_ {
+            tokens.iter().flat_map(|token| {
+                [
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    token.token_type,
+                    token.token_modifiers_bitset,
+                ]
+            })
+        }
+
+        let mut integers: Vec<u32> = flatten(data).collect();
+        // apply the edits back to front so the offsets of the remaining ones stay valid
+        edits.sort_by_key(|edit| edit.start);
+        for edit in edits.into_iter().rev() {
+            let start = (edit.start as usize).min(integers.len());
+            let end = (start + edit.delete_count as usize).min(integers.len());
+            let inserted = edit.data.unwrap_or_default();
+            integers.splice(start..end, flatten(&inserted));
+        }
+
+        *data = integers
+            .chunks(5)
+            .filter(|chunk| chunk.len() == 5)
+            .map(|chunk| lsp::SemanticToken {
+                delta_line: chunk[0],
+                delta_start: chunk[1],
+                length: chunk[2],
+                token_type: chunk[3],
+                token_modifiers_bitset: chunk[4],
+            })
+            .collect();
+        data.len() * 5 == integers.len()
+    }
+
+    /// Decodes relatively encoded semantic tokens into `(start, end, token_type,
+    /// token_modifiers_bitset)` char ranges of the document. Tokens outside of the document are
+    /// dropped.
+    pub fn decode_semantic_tokens(
+        doc: &Rope,
+        data: &[lsp::SemanticToken],
+        offset_encoding: OffsetEncoding,
+    ) -> Vec<(usize, usize, u32, u32)> {
+        let mut line = 0u32;
+        let mut character = 0u32;
+        data.iter()
+            .filter_map(|token| {
+                if token.delta_line != 0 {
+                    line = line.saturating_add(token.delta_line);
+                    character = token.delta_start;
+                } else {
+                    character = character.saturating_add(token.delta_start);
+                }
+                let start = lsp::Position::new(line, character);
+                let end = lsp::Position::new(line, character.saturating_add(token.length));
+                let start = lsp_pos_to_pos(doc, start, offset_encoding)?;
+                let end = lsp_pos_to_pos(doc, end, offset_encoding)?;
+                Some((start, end, token.token_type, token.token_modifiers_bitset))
+            })
+            .collect()
+    }
+
     /// The result of asking the language server to format the document. This can be turned into a
     /// `Transaction`, but the advantage of not doing that straight away is that this one is
     /// `Send` and `Sync`.
@@ -203,24 +302,56 @@ pub mod util {
 #[derive(Debug, PartialEq, Clone)]
 pub enum MethodCall {
     WorkDoneProgressCreate(lsp::WorkDoneProgressCreateParams),
+    ApplyWorkspaceEdit(lsp::ApplyWorkspaceEditParams),
+    WorkspaceConfiguration(lsp::ConfigurationParams),
+    WorkspaceDiagnosticRefresh,
+    ShowMessageRequest(lsp::ShowMessageRequestParams),
+    ShowDocument(lsp::ShowDocumentParams),
+    RegisterCapability(lsp::RegistrationParams),
+    UnregisterCapability(lsp::UnregistrationParams),
 }
 
 impl MethodCall {
-    pub fn parse(method: &str, params: jsonrpc::Params) -> Option<MethodCall> {
+    /// Parse a request sent by the server, failing with the error to reply with for unknown
+    /// methods and malformed params.
+    pub fn parse(
+        method: &str,
+        params: jsonrpc::Params,
+    ) -> core::result::Result<MethodCall, jsonrpc::Error> {
         use lsp::request::Request;
         let request = match method {
             lsp::request::WorkDoneProgressCreate::METHOD => {
-                let params: lsp::WorkDoneProgressCreateParams = params
-                    .parse()
-                    .expect("Failed to parse WorkDoneCreate params");
+                let params: lsp::WorkDoneProgressCreateParams = params.parse()?;
                 Self::WorkDoneProgressCreate(params)
             }
-            _ => {
-                log::warn!("unhandled lsp request: {}", method);
-                return None;
+            lsp::request::ApplyWorkspaceEdit::METHOD => {
+                let params: lsp::ApplyWorkspaceEditParams = params.parse()?;
+                Self::ApplyWorkspaceEdit(params)
+            }
+            lsp::request::WorkspaceConfiguration::METHOD => {
+                let params: lsp::ConfigurationParams = params.parse()?;
+                Self::WorkspaceConfiguration(params)
+            }
+            lsp::request::WorkspaceDiagnosticRefresh::METHOD => Self::WorkspaceDiagnosticRefresh,
+            lsp::request::ShowMessageRequest::METHOD => {
+                let params: lsp::ShowMessageRequestParams = params.parse()?;
+                Self::ShowMessageRequest(params)
             }
+            lsp::request::ShowDocument::METHOD => {
+                let params: lsp::ShowDocumentParams = params.parse()?;
+                Self::ShowDocument(params)
+            }
+            lsp::request::RegisterCapability::METHOD => {
+                let params: lsp::RegistrationParams = params.parse()?;
+                Self::RegisterCapability(params)
+            }
+            lsp::request::UnregisterCapability::METHOD => {
+                let params: lsp::UnregistrationParams = params.parse()?;
+                Self::UnregisterCapability(params)
+            }
+            _ => return Err(jsonrpc::Error::method_not_found()),
         };
-        Some(request)
+        Ok(request)
     }
 }
 
@@ -228,6 +359,8 @@ impl MethodCall {
 pub enum Notification {
     // we inject this notification to signal the LSP is ready
     Initialized,
+    // we inject this notification when the connection to the server is lost
+    Exit,
     PublishDiagnostics(lsp::PublishDiagnosticsParams),
     ShowMessage(lsp::ShowMessageParams),
     LogMessage(lsp::LogMessageParams),
@@ -240,6 +373,7 @@ impl Notification {
 
         let notification = match method {
             lsp::notification::Initialized::METHOD => Self::Initialized,
+            lsp::notification::Exit::METHOD => Self::Exit,
             lsp::notification::PublishDiagnostics::METHOD => {
                 let params: lsp::PublishDiagnosticsParams = params
                     .parse()
@@ -276,7 +410,7 @@ impl Notification {
 
 #[derive(Debug)]
 pub struct Registry {
-    inner: HashMap<LanguageId, (usize, Arc<Client>)>,
+    inner: HashMap<LanguageId, Vec<(usize, Arc<Client>)>>,
 
     counter: AtomicUsize,
     pub incoming: SelectAll<UnboundedReceiverStream<(usize, Call)>>,
@@ -297,74 +431,182 @@ impl Registry {
         }
     }
 
-    pub fn get_by_id(&self, id: usize) -> Option<&Client> {
+    pub fn get_by_id(&self, id: usize) -> Option<&Arc<Client>> {
         self.inner
             .values()
+            .flatten()
             .find(|(client_id, _)| client_id == &id)
-            .map(|(_, client)| client.as_ref())
+            .map(|(_, client)| client)
     }
 
-    pub fn get(&mut self, language_config: &LanguageConfiguration) -> Result<Arc<Client>> {
-        let config = match &language_config.language_server {
-            Some(config) => config,
-            None => return Err(Error::LspNotDefined),
-        };
+    /// Get the language servers configured for a language, starting them if they aren't running
+    /// yet. Servers that fail to start are logged and skipped, it's an error if none of them
+    /// started.
+    ///
+    /// The project root of `path` is found with the `roots` of the language. New servers are
+    /// started in it, running ones get it added to their workspace folders.
+    pub fn get(
+        &mut self,
+        language_config: &LanguageConfiguration,
+        path: Option<&Path>,
+    ) -> Result<Vec<Arc<Client>>> {
+        if language_config.language_servers.is_empty() {
+            return Err(Error::LspNotDefined);
+        }
+
+        let root = find_root(path, &language_config.roots);
 
         match self.inner.entry(language_config.scope.clone()) {
-            Entry::Occupied(entry) => Ok(entry.get().1.clone()),
+            Entry::Occupied(entry) => {
+                let clients = entry.get();
+                if let Some(root) = &root {
+                    for (_, client) in clients {
+                        if !client.add_workspace_folder(root) {
+                            log::warn!(
+                                "language server `{}` doesn't support workspace folders, {} is handled with the root it was started in",
+                                client.name(),
+                                root.display()
+                            );
+                        }
+                    }
+                }
+                Ok(clients.iter().map(|(_, client)| client.clone()).collect())
+            }
             Entry::Vacant(entry) => {
-                // initialize a new client
-                let id = self.counter.fetch_add(1, Ordering::Relaxed);
-                let (client, incoming, initialize_notify) = Client::start(
-                    &config.command,
-                    &config.args,
-                    serde_json::from_str(language_config.config.as_deref().unwrap_or(""))
-                        .map_err(|e| {
-                            log::error!(
-                                "LSP Config, {}, in `languages.toml` for `{}`",
-                                e,
-                                language_config.scope()
-                            )
-                        })
-                        .ok(),
-                    id,
-                )?;
-                self.incoming.push(UnboundedReceiverStream::new(incoming));
-                let client = Arc::new(client);
-
-                // Initialize the client asynchronously
-                let _client = client.clone();
-                tokio::spawn(async move {
-                    use futures_util::TryFutureExt;
-                    let value = _client
-                        .capabilities
-                        .get_or_try_init(|| {
-                            _client
-                                .initialize()
-                                .map_ok(|response| response.capabilities)
-                        })
-                        .await;
-
-                    value.expect("failed to initialize capabilities");
-
-                    // next up, notify<initialized>
-                    _client
-                        .notify::<lsp::notification::Initialized>(lsp::InitializedParams {})
-                        .await
-                        .unwrap();
-
-                    initialize_notify.notify_one();
-                });
-
-                entry.insert((id, client.clone()));
-                Ok(client)
+                let mut clients = Vec::new();
+                for (i, config) in language_config.language_servers.iter().enumerate() {
+                    let legacy_config;
+                    let config = match &language_config.config {
+                        Some(options) if i == 0 => {
+                            legacy_config = with_legacy_config(config, options, language_config);
+                            &legacy_config
+                        }
+                        _ => config,
+                    };
+                    let id = self.counter.fetch_add(1, Ordering::Relaxed);
+                    match start_client(config, id, root.as_deref()) {
+                        Ok((client, incoming)) => {
+                            self.incoming.push(UnboundedReceiverStream::new(incoming));
+                            clients.push((id, client));
+                        }
+                        Err(e) => log::error!(
+                            "Failed to start language server `{}` for `{}`: {}",
+                            config.command,
+                            language_config.scope(),
+                            e
+                        ),
+                    }
+                }
+
+                // nothing is cached when every server failed, so they're retried next time
+                if clients.is_empty() {
+                    return Err(Error::Other(anyhow::anyhow!(
+                        "no language server could be started"
+                    )));
+                }
+                let clients = entry.insert(clients);
+                Ok(clients.iter().map(|(_, client)| client.clone()).collect())
             }
         }
     }
 
+    /// Remove a single language server, e.g. after it exited. The other servers of its language
+    /// keep running.
+    pub fn remove(&mut self, id: usize) -> Option<Arc<Client>> {
+        self.inner.values_mut().find_map(|clients| {
+            let index = clients.iter().position(|(client_id, _)| *client_id == id)?;
+            Some(clients.remove(index).1)
+        })
+    }
+
+    /// Remove all language servers of a language, so the next [`Registry::get`] starts them
+    /// again.
+    pub fn remove_language(&mut self, scope: &str) -> Vec<Arc<Client>> {
+        self.inner
+            .remove(scope)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, client)| client)
+            .collect()
+    }
+
     pub fn iter_clients(&self) -> impl Iterator<Item = &Arc<Client>> {
-        self.inner.values().map(|(_, client)| client)
+        self.inner.values().flatten().map(|(_, client)| client)
+    }
+}
+
+/// Apply the deprecated `config` of a language, a JSON string, as the initialization options of
+/// its first server unless that server has its own.
+fn with_legacy_config(
+    config: &LanguageServerConfiguration,
+    options: &str,
+    language_config: &LanguageConfiguration,
+) -> LanguageServerConfiguration {
+    log::warn!(
+        "`config` of `{}` in `languages.toml` is deprecated, use the `initialization-options` of its language server instead",
+        language_config.scope()
+    );
+    let mut config = config.clone();
+    if config.initialization_options.is_none() {
+        config.initialization_options = serde_json::from_str(options)
+            .map_err(|e| {
+                log::error!(
+                    "LSP Config, {}, in `languages.toml` for `{}`",
+                    e,
+                    language_config.scope()
+                )
+            })
+            .ok();
     }
+    config
+}
+
+/// Start a language server and initialize it in the background.
+#[allow(clippy::type_complexity)]
+fn start_client(
+    config: &LanguageServerConfiguration,
+    id: usize,
+    root: Option<&Path>,
+) -> Result<(Arc<Client>, UnboundedReceiver<(usize, Call)>)> {
+    let (client, incoming, initialize_notify) = Client::start(config, id, root)?;
+    let client = Arc::new(client);
+
+    // Initialize the client asynchronously
+    let _client = client.clone();
+    tokio::spawn(async move {
+        use futures_util::TryFutureExt;
+        let workspace_folders = _client.workspace_folders();
+        let value = _client
+            .capabilities
+            .get_or_try_init(|| {
+                _client
+                    .initialize(workspace_folders.clone())
+                    .map_ok(|response| response.capabilities)
+            })
+            .await;
+
+        value.expect("failed to initialize capabilities");
+
+        // next up, notify<initialized>
+        _client
+            .notify::<lsp::notification::Initialized>(lsp::InitializedParams {})
+            .await
+            .unwrap();
+
+        // folders of documents opened while the server was initializing
+        _client.announce_workspace_folders(&workspace_folders);
+
+        // send the configured settings, servers may also ask for them via workspace/configuration
+        if let Some(settings) = _client.settings() {
+            if let Err(e) = _client.did_change_configuration(settings.clone()).await {
+                log::error!("failed to send settings to language server: {}", e);
+            }
+        }
+
+        initialize_notify.notify_one();
+    });
+
+    Ok((client, incoming))
 }
 
 #[derive(Debug)]
@@ -415,6 +657,11 @@ impl LspProgressMap {
             .unwrap_or_default()
     }
 
+    /// Forgets all progress of the server with `id`, e.g. after it was stopped.
+    pub fn remove(&mut self, id: usize) {
+        self.0.remove(&id);
+    }
+
     pub fn create(&mut self, id: usize, token: lsp::ProgressToken) {
         self.0
             .entry(id)
@@ -447,8 +694,65 @@ impl LspProgressMap {
 
 #[cfg(test)]
 mod tests {
-    use super::{lsp, util::*, OffsetEncoding};
-    use helix_core::Rope;
+    use super::{jsonrpc, lsp, util::*, with_legacy_config, MethodCall, OffsetEncoding};
+    use helix_core::{syntax::LanguageConfiguration, Rope};
+    use serde_json::json;
+
+    #[test]
+    fn method_calls_fail_with_the_error_to_reply() {
+        let params = |value| serde_json::from_value(value).unwrap();
+
+        let call = MethodCall::parse(
+            "workspace/configuration",
+            params(json!({ "items": [{ "section": "rust" }] })),
+        );
+        assert!(matches!(call, Ok(MethodCall::WorkspaceConfiguration(_))));
+
+        let err = MethodCall::parse("workspace/configuration", params(json!({ "items": 1 })))
+            .unwrap_err();
+        assert_eq!(err.code, jsonrpc::ErrorCode::InvalidParams);
+
+        let err = MethodCall::parse("workspace/unknown", params(json!({}))).unwrap_err();
+        assert_eq!(err.code, jsonrpc::ErrorCode::MethodNotFound);
+    }
+
+    #[test]
+    fn applies_legacy_config() {
+        let language_config: LanguageConfiguration = serde_json::from_value(json!({
+            "name": "rust",
+            "scope": "source.rust",
+            "file-types": ["rs"],
+            "roots": [],
+            "config": r#"{ "procMacro": { "enable": false } }"#,
+            "language-server": [
+                { "command": "rust-analyzer" },
+                { "command": "other", "initialization-options": { "enable": true } },
+            ],
+        }))
+        .unwrap();
+        let options = language_config.config.as_deref().unwrap();
+
+        let config = with_legacy_config(
+            &language_config.language_servers[0],
+            options,
+            &language_config,
+        );
+        assert_eq!(
+            serde_json::to_value(config.initialization_options).unwrap(),
+            json!({ "procMacro": { "enable": false } })
+        );
+
+        // a server's own options take precedence
+        let config = with_legacy_config(
+            &language_config.language_servers[1],
+            options,
+            &language_config,
+        );
+        assert_eq!(
+            serde_json::to_value(config.initialization_options).unwrap(),
+            json!({ "enable": true })
+        );
+    }
 
     #[test]
     fn converts_lsp_pos_to_pos() {
@@ -474,4 +778,71 @@ mod tests {
         test_case!("test\n\n\n\ncase", (4, 5) => None);
         test_case!("", (u32::MAX, u32::MAX) => None);
     }
+
+    #[test]
+    fn decodes_semantic_tokens() {
+        let token = |delta_line, delta_start, length, token_type| lsp::SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type,
+            token_modifiers_bitset: 0,
+        };
+        let doc = Rope::from("let x = 1;\nfoo(x);\n");
+        let mut data = vec![token(0, 4, 1, 0), token(1, 0, 3, 1), token(0, 4, 1, 0)];
+        assert_eq!(
+            decode_semantic_tokens(&doc, &data, OffsetEncoding::Utf16),
+            vec![(4, 5, 0, 0), (11, 14, 1, 0), (15, 16, 0, 0)]
+        );
+
+        // replace the call with a macro and drop the argument
+        apply_semantic_tokens_edits(
+            &mut data,
+            vec![lsp::SemanticTokensEdit {
+                start: 5,
+                delete_count: 10,
+                data: Some(vec![token(1, 0, 3, 2)]),
+            }],
+        );
+        assert_eq!(
+            decode_semantic_tokens(&doc, &data, OffsetEncoding::Utf16),
+            vec![(4, 5, 0, 0), (11, 14, 2, 0)]
+        );
+
+        // edits don't have to be aligned to tokens: replace the length, type and modifiers of
+        // the first token along with the position of the second one
+        assert!(apply_semantic_tokens_edits(
+            &mut data,
+            vec![lsp::SemanticTokensEdit {
+                start: 2,
+                delete_count: 5,
+                data: Some(vec![token(2, 3, 0, 1)]),
+            }],
+        ));
+        assert_eq!(
+            decode_semantic_tokens(&doc, &data, OffsetEncoding::Utf16),
+            vec![(4, 6, 3, 0), (11, 14, 2, 0)]
+        );
+    }
+
+    #[test]
+    fn merges_text_edits() {
+        let edit = |start, end, text: &str| {
+            lsp::TextEdit::new(
+                lsp::Range::new(lsp::Position::new(0, start), lsp::Position::new(0, end)),
+                text.to_string(),
+            )
+        };
+
+        let merged = merge_text_edits(vec![
+            vec![edit(8, 10, "b"), edit(0, 2, "a")],
+            // the same edit from an overlapping selection
+            vec![edit(0, 2, "a"), edit(1, 4, "c")],
+            vec![edit(10, 10, "d")],
+        ]);
+        assert_eq!(
+            merged,
+            vec![edit(0, 2, "a"), edit(8, 10, "b"), edit(10, 10, "d")]
+        );
+    }
 }
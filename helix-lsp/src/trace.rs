@@ -0,0 +1,79 @@
+//! Opt-in trace of the JSON-RPC messages exchanged with a language server, enabled with
+//! `trace = true` in its configuration.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Trace files are rotated once they grow past this size, keeping a single older file around.
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Trace {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl Trace {
+    /// Directory trace files are written to.
+    pub fn dir() -> PathBuf {
+        helix_core::cache_dir().join("lsp-trace")
+    }
+
+    /// Open the trace file of the server started with `command`, appending to it. The file is
+    /// named after the editor's pid and the server's id too, so concurrent editors and servers
+    /// started with the same command don't write to the same file.
+    pub fn open(command: &str, id: usize) -> io::Result<Self> {
+        let name = std::path::Path::new(command)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| command.to_string());
+
+        let dir = Self::dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}-{}-{}.log", name, std::process::id(), id));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+
+        Ok(Self { path, file, len })
+    }
+
+    /// Append a message, prefixed with a timestamp, the id of the server and `label`.
+    pub fn write(&mut self, id: usize, label: &str, message: &str) {
+        let line = format!(
+            "{} [{}] {} {}\n",
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+            id,
+            label,
+            message
+        );
+
+        if let Err(err) = self.rotate(line.len() as u64) {
+            log::error!("failed to rotate {}: {}", self.path.display(), err);
+        }
+        match self.file.write_all(line.as_bytes()) {
+            Ok(()) => self.len += line.len() as u64,
+            Err(err) => log::error!("failed to write to {}: {}", self.path.display(), err),
+        }
+    }
+
+    /// Move the file to `<name>.log.1` and start a new one if `additional` bytes don't fit.
+    fn rotate(&mut self, additional: u64) -> io::Result<()> {
+        if self.len == 0 || self.len + additional <= MAX_SIZE {
+            return Ok(());
+        }
+
+        let mut old = self.path.clone().into_os_string();
+        old.push(".1");
+        fs::rename(&self.path, old)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
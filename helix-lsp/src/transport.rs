@@ -1,4 +1,4 @@
-use crate::{Error, Result};
+use crate::{trace::Trace, Error, Result};
 use anyhow::Context;
 use jsonrpc_core as jsonrpc;
 use log::{error, info};
@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::{
     io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
     process::{ChildStderr, ChildStdin, ChildStdout},
@@ -36,10 +37,36 @@ enum ServerMessage {
     Call(jsonrpc::Call),
 }
 
+/// Response times of the requests sent with one method.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLatency {
+    pub count: u32,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl RequestLatency {
+    pub fn mean(&self) -> Duration {
+        self.total / self.count.max(1)
+    }
+}
+
+/// Request latencies by method, shared between a client and its transport.
+pub type RequestLatencies = Arc<std::sync::Mutex<HashMap<String, RequestLatency>>>;
+
+#[derive(Debug)]
+struct PendingRequest {
+    chan: Sender<Result<Value>>,
+    method: String,
+    sent: Instant,
+}
+
 #[derive(Debug)]
 pub struct Transport {
     id: usize,
-    pending_requests: Mutex<HashMap<jsonrpc::Id, Sender<Result<Value>>>>,
+    pending_requests: Mutex<HashMap<jsonrpc::Id, PendingRequest>>,
+    latencies: RequestLatencies,
+    trace: Option<std::sync::Mutex<Trace>>,
 }
 
 impl Transport {
@@ -48,6 +75,8 @@ impl Transport {
         server_stdin: BufWriter<ChildStdin>,
         server_stderr: BufReader<ChildStderr>,
         id: usize,
+        latencies: RequestLatencies,
+        trace: Option<Trace>,
     ) -> (
         UnboundedReceiver<(usize, jsonrpc::Call)>,
         UnboundedSender<Payload>,
@@ -60,6 +89,8 @@ impl Transport {
         let transport = Self {
             id,
             pending_requests: Mutex::new(HashMap::default()),
+            latencies,
+            trace: trace.map(std::sync::Mutex::new),
         };
 
         let transport = Arc::new(transport);
@@ -154,14 +185,31 @@ impl Transport {
         //TODO: reuse string
         let json = match payload {
             Payload::Request { chan, value } => {
-                self.pending_requests
-                    .lock()
-                    .await
-                    .insert(value.id.clone(), chan);
+                self.trace(
+                    || format!("-> request #{} {}", display_id(&value.id), value.method),
+                    &value,
+                );
+                self.pending_requests.lock().await.insert(
+                    value.id.clone(),
+                    PendingRequest {
+                        chan,
+                        method: value.method.clone(),
+                        sent: Instant::now(),
+                    },
+                );
+                serde_json::to_string(&value)?
+            }
+            Payload::Notification(value) => {
+                self.trace(|| format!("-> notification {}", value.method), &value);
                 serde_json::to_string(&value)?
             }
-            Payload::Notification(value) => serde_json::to_string(&value)?,
-            Payload::Response(error) => serde_json::to_string(&error)?,
+            Payload::Response(output) => {
+                self.trace(
+                    || format!("-> response #{}", display_id(output_id(&output))),
+                    &output,
+                );
+                serde_json::to_string(&output)?
+            }
         };
         self.send_string_to_server(server_stdin, json).await
     }
@@ -194,6 +242,24 @@ impl Transport {
         match msg {
             ServerMessage::Output(output) => self.process_request_response(output).await?,
             ServerMessage::Call(call) => {
+                match &call {
+                    jsonrpc::Call::MethodCall(method_call) => self.trace(
+                        || {
+                            format!(
+                                "<- request #{} {}",
+                                display_id(&method_call.id),
+                                method_call.method
+                            )
+                        },
+                        method_call,
+                    ),
+                    jsonrpc::Call::Notification(notification) => self.trace(
+                        || format!("<- notification {}", notification.method),
+                        notification,
+                    ),
+                    jsonrpc::Call::Invalid { .. } => self.trace(|| "<- invalid".to_string(), &call),
+                }
+
                 client_tx
                     .send((self.id, call))
                     .context("failed to send a message to server")?;
@@ -204,6 +270,33 @@ impl Transport {
     }
 
     async fn process_request_response(&self, output: jsonrpc::Output) -> Result<()> {
+        let request = self
+            .pending_requests
+            .lock()
+            .await
+            .remove(output_id(&output))
+            .expect("pending_request with id not found!");
+
+        let elapsed = request.sent.elapsed();
+        self.trace(
+            || {
+                format!(
+                    "<- response #{} {} ({}ms)",
+                    display_id(output_id(&output)),
+                    request.method,
+                    elapsed.as_millis()
+                )
+            },
+            &output,
+        );
+        {
+            let mut latencies = self.latencies.lock().unwrap();
+            let latency = latencies.entry(request.method).or_default();
+            latency.count += 1;
+            latency.total += elapsed;
+            latency.max = latency.max.max(elapsed);
+        }
+
         let (id, result) = match output {
             jsonrpc::Output::Success(jsonrpc::Success { id, result, .. }) => {
                 info!("<- {}", result);
@@ -215,14 +308,7 @@ impl Transport {
             }
         };
 
-        let tx = self
-            .pending_requests
-            .lock()
-            .await
-            .remove(&id)
-            .expect("pending_request with id not found!");
-
-        match tx.send(result).await {
+        match request.chan.send(result).await {
             Ok(_) => (),
             Err(_) => error!(
                 "Tried sending response into a closed channel (id={:?}), original request likely timed out",
@@ -233,6 +319,15 @@ impl Transport {
         Ok(())
     }
 
+    /// Append a message to the trace file if tracing is enabled for the server. `label` is only
+    /// built when it is.
+    fn trace(&self, label: impl FnOnce() -> String, message: &impl Serialize) {
+        if let Some(trace) = &self.trace {
+            let message = serde_json::to_string(message).unwrap_or_default();
+            trace.lock().unwrap().write(self.id, &label(), &message);
+        }
+    }
+
     async fn recv(
         transport: Arc<Self>,
         mut server_stdout: BufReader<ChildStdout>,
@@ -256,6 +351,26 @@ impl Transport {
                 }
             }
         }
+
+        // the server is gone, fail the requests still waiting for a response
+        for (_, request) in transport.pending_requests.lock().await.drain() {
+            let _ = request.chan.send(Err(Error::StreamClosed)).await;
+        }
+
+        use lsp_types::notification::Notification;
+        // Hack: inject an exit notification so the editor can tell the server stopped
+        let notification =
+            ServerMessage::Call(jsonrpc::Call::Notification(jsonrpc::Notification {
+                jsonrpc: None,
+                method: lsp_types::notification::Exit::METHOD.to_string(),
+                params: jsonrpc::Params::None,
+            }));
+        if let Err(err) = transport
+            .process_server_message(&client_tx, notification)
+            .await
+        {
+            error!("err: <- {:?}", err);
+        }
     }
 
     async fn err(_transport: Arc<Self>, mut server_stderr: BufReader<ChildStderr>) {
@@ -363,3 +478,18 @@ impl Transport {
         }
     }
 }
+
+fn output_id(output: &jsonrpc::Output) -> &jsonrpc::Id {
+    match output {
+        jsonrpc::Output::Success(jsonrpc::Success { id, .. })
+        | jsonrpc::Output::Failure(jsonrpc::Failure { id, .. }) => id,
+    }
+}
+
+fn display_id(id: &jsonrpc::Id) -> String {
+    match id {
+        jsonrpc::Id::Num(id) => id.to_string(),
+        jsonrpc::Id::Str(id) => id.clone(),
+        jsonrpc::Id::Null => "null".to_string(),
+    }
+}
@@ -1,8 +1,8 @@
-use helix_core::{merge_toml_values, syntax};
-use helix_lsp::{lsp, util::lsp_pos_to_pos, LspProgressMap};
+use helix_core::{merge_toml_values, syntax, syntax::LanguageServerFeature};
+use helix_lsp::lsp;
 use helix_view::{theme, Editor};
 
-use crate::{args::Args, compositor::Compositor, config::Config, job::Jobs, ui};
+use crate::{args::Args, commands, compositor::Compositor, config::Config, job::Jobs, ui};
 
 use log::{error, warn};
 
@@ -45,7 +45,6 @@ pub struct Application {
 
     signals: Signals,
     jobs: Jobs,
-    lsp_progress: LspProgressMap,
 }
 
 impl Application {
@@ -93,6 +92,7 @@ impl Application {
             syn_loader.clone(),
             config.editor.clone(),
         );
+        editor.load_session();
 
         let editor_view = Box::new(ui::EditorView::new(std::mem::take(&mut config.keys)));
         compositor.push(editor_view);
@@ -121,6 +121,7 @@ impl Application {
         }
 
         editor.set_theme(theme);
+        editor.file_watcher.start();
 
         #[cfg(windows)]
         let signals = futures_util::stream::empty();
@@ -138,7 +139,6 @@ impl Application {
 
             signals,
             jobs: Jobs::new(),
-            lsp_progress: LspProgressMap::new(),
         };
 
         Ok(app)
@@ -199,10 +199,52 @@ impl Application {
                     self.jobs.handle_callback(&mut self.editor, &mut self.compositor, callback);
                     self.render();
                 }
+                Some(events) = self.editor.file_watcher.events.recv() => {
+                    self.editor.handle_file_events(events);
+                    self.render();
+                }
+                _ = &mut self.editor.idle_timer => {
+                    // idle timeout
+                    self.editor.clear_idle_timer();
+                    self.handle_idle_timeout();
+                }
+            }
+
+            // send the document changes made during this tick in one go
+            for client in self.editor.language_servers.iter_clients() {
+                client.flush_changes();
             }
+            self.editor.refresh_watched_documents();
         }
     }
 
+    pub fn handle_idle_timeout(&mut self) {
+        use crate::compositor::EventResult;
+        let editor_view = self
+            .compositor
+            .find(std::any::type_name::<ui::EditorView>())
+            .expect("expected at least one EditorView");
+        let editor_view = editor_view
+            .as_any_mut()
+            .downcast_mut::<ui::EditorView>()
+            .unwrap();
+
+        let mut cx = crate::compositor::Context {
+            editor: &mut self.editor,
+            jobs: &mut self.jobs,
+            scroll: None,
+        };
+        if let EventResult::Consumed(_) = editor_view.handle_idle_timeout(&mut cx) {
+            self.render();
+        }
+
+        commands::compute_inlay_hints_for_all_views(&mut self.editor, &mut self.jobs);
+        commands::compute_document_highlights(&mut self.editor, &mut self.jobs);
+        commands::compute_semantic_tokens(&mut self.editor, &mut self.jobs);
+        commands::compute_code_lenses(&mut self.editor, &mut self.jobs);
+        commands::pull_diagnostics(&mut self.editor, &mut self.jobs);
+    }
+
     #[cfg(windows)]
     // no signal handling available on windows
     pub async fn handle_signals(&mut self, _signal: ()) {}
@@ -229,6 +271,19 @@ impl Application {
     }
 
     pub fn handle_terminal_events(&mut self, event: Option<Result<Event, crossterm::ErrorKind>>) {
+        // scrolling with the mouse should refresh inlay hints too
+        if let Some(Ok(Event::Key(_) | Event::Mouse(_))) = event {
+            self.editor.reset_idle_timer();
+        }
+
+        if let (Some(Ok(Event::Key(key))), Some(recording)) =
+            (&event, &mut self.editor.macro_recording)
+        {
+            let mut key = helix_view::input::KeyEvent::from(*key);
+            ui::editor::canonicalize_key(&mut key);
+            recording.push(key);
+        }
+
         let mut cx = crate::compositor::Context {
             editor: &mut self.editor,
             jobs: &mut self.jobs,
@@ -285,9 +340,10 @@ impl Application {
                                 }
                             };
 
-                        let docs = self.editor.documents().filter(|doc| {
-                            doc.language_server().map(|server| server.id()) == Some(server_id)
-                        });
+                        let docs = self
+                            .editor
+                            .documents()
+                            .filter(|doc| doc.supports_language_server(server_id));
 
                         // trigger textDocument/didOpen for docs that are already open
                         for doc in docs {
@@ -305,75 +361,57 @@ impl Application {
                                 language_id,
                             ));
                         }
+
+                        commands::compute_inlay_hints_for_all_views(
+                            &mut self.editor,
+                            &mut self.jobs,
+                        );
+                        commands::compute_semantic_tokens(&mut self.editor, &mut self.jobs);
+                        commands::compute_code_lenses(&mut self.editor, &mut self.jobs);
+                        commands::pull_diagnostics(&mut self.editor, &mut self.jobs);
                     }
-                    Notification::PublishDiagnostics(params) => {
-                        let path = params.uri.to_file_path().unwrap();
-                        let doc = self.editor.document_by_path_mut(&path);
-
-                        if let Some(doc) = doc {
-                            let text = doc.text();
-
-                            let diagnostics = params
-                                .diagnostics
-                                .into_iter()
-                                .filter_map(|diagnostic| {
-                                    use helix_core::{
-                                        diagnostic::{Range, Severity::*},
-                                        Diagnostic,
-                                    };
-                                    use lsp::DiagnosticSeverity;
-
-                                    let language_server = doc.language_server().unwrap();
-
-                                    // TODO: convert inside server
-                                    let start = if let Some(start) = lsp_pos_to_pos(
-                                        text,
-                                        diagnostic.range.start,
-                                        language_server.offset_encoding(),
-                                    ) {
-                                        start
-                                    } else {
-                                        log::warn!("lsp position out of bounds - {:?}", diagnostic);
-                                        return None;
-                                    };
-
-                                    let end = if let Some(end) = lsp_pos_to_pos(
-                                        text,
-                                        diagnostic.range.end,
-                                        language_server.offset_encoding(),
-                                    ) {
-                                        end
-                                    } else {
-                                        log::warn!("lsp position out of bounds - {:?}", diagnostic);
-                                        return None;
-                                    };
-
-                                    Some(Diagnostic {
-                                        range: Range { start, end },
-                                        line: diagnostic.range.start.line as usize,
-                                        message: diagnostic.message,
-                                        severity: diagnostic.severity.map(
-                                            |severity| match severity {
-                                                DiagnosticSeverity::Error => Error,
-                                                DiagnosticSeverity::Warning => Warning,
-                                                DiagnosticSeverity::Information => Info,
-                                                DiagnosticSeverity::Hint => Hint,
-                                            },
-                                        ),
-                                        // code
-                                        // source
-                                    })
-                                })
-                                .collect();
-
-                            doc.set_diagnostics(diagnostics);
+                    Notification::Exit => {
+                        editor_view.spinners_mut().get_or_create(server_id).stop();
+
+                        // servers stopped on purpose are already gone from the registry
+                        if let Some(language_server) = self.editor.language_server_exited(server_id)
+                        {
+                            self.editor.set_error(format!(
+                                "Language server `{}` exited, use :lsp-restart to start it again",
+                                language_server.name()
+                            ));
                         }
                     }
+                    Notification::PublishDiagnostics(params) => {
+                        let offset_encoding =
+                            match self.editor.language_servers.get_by_id(server_id) {
+                                Some(language_server)
+                                    if language_server
+                                        .supports_feature(LanguageServerFeature::Diagnostics) =>
+                                {
+                                    language_server.offset_encoding()
+                                }
+                                _ => return,
+                            };
+
+                        self.editor.set_lsp_diagnostics(
+                            server_id,
+                            offset_encoding,
+                            params.uri,
+                            params.diagnostics,
+                        );
+                    }
                     Notification::ShowMessage(params) => {
                         log::warn!("unhandled window/showMessage: {:?}", params);
                     }
                     Notification::LogMessage(params) => {
                         log::info!("window/logMessage: {:?}", params);
+
+                        let name = match self.editor.language_servers.get_by_id(server_id) {
+                            Some(language_server) => language_server.name().to_string(),
+                            None => return,
+                        };
+                        self.editor.push_lsp_log(&name, params.typ, &params.message);
                     }
                     Notification::ProgressMessage(params) => {
                         let lsp::ProgressParams { token, value } = params;
@@ -395,8 +433,8 @@ impl Application {
                                 if message.is_some() {
                                     (None, message, &None)
                                 } else {
-                                    self.lsp_progress.end_progress(server_id, &token);
-                                    if !self.lsp_progress.is_progressing(server_id) {
+                                    self.editor.lsp_progress.end_progress(server_id, &token);
+                                    if !self.editor.lsp_progress.is_progressing(server_id) {
                                         editor_view.spinners_mut().get_or_create(server_id).stop();
                                     }
                                     self.editor.clear_status();
@@ -438,12 +476,12 @@ impl Application {
                         };
 
                         if let lsp::WorkDoneProgress::End(_) = work {
-                            self.lsp_progress.end_progress(server_id, &token);
-                            if !self.lsp_progress.is_progressing(server_id) {
+                            self.editor.lsp_progress.end_progress(server_id, &token);
+                            if !self.editor.lsp_progress.is_progressing(server_id) {
                                 editor_view.spinners_mut().get_or_create(server_id).stop();
                             }
                         } else {
-                            self.lsp_progress.update(server_id, token, work);
+                            self.editor.lsp_progress.update(server_id, token, work);
                         }
 
                         if self.config.lsp.display_messages {
@@ -464,25 +502,18 @@ impl Application {
                 };
 
                 let call = match MethodCall::parse(&method, params) {
-                    Some(call) => call,
-                    None => {
-                        error!("Method not found {}", method);
-                        // language_server.reply(
-                        //     call.id,
-                        //     // TODO: make a Into trait that can cast to Err(jsonrpc::Error)
-                        //     Err(helix_lsp::jsonrpc::Error {
-                        //         code: helix_lsp::jsonrpc::ErrorCode::MethodNotFound,
-                        //         message: "Method not found".to_string(),
-                        //         data: None,
-                        //     }),
-                        // );
+                    Ok(call) => call,
+                    Err(err) => {
+                        error!("failed to handle {} request: {}", method, err.message);
+                        // answer anyway, the server would be waiting for a reply forever
+                        tokio::spawn(language_server.reply(id, Err(err)));
                         return;
                     }
                 };
 
                 match call {
                     MethodCall::WorkDoneProgressCreate(params) => {
-                        self.lsp_progress.create(server_id, params.token);
+                        self.editor.lsp_progress.create(server_id, params.token);
 
                         let spinner = editor_view.spinners_mut().get_or_create(server_id);
                         if spinner.is_stopped() {
@@ -490,6 +521,92 @@ impl Application {
                         }
                         tokio::spawn(language_server.reply(id, Ok(serde_json::Value::Null)));
                     }
+                    MethodCall::ApplyWorkspaceEdit(params) => {
+                        let offset_encoding = language_server.offset_encoding();
+                        let response = match commands::apply_workspace_edit(
+                            &mut self.editor,
+                            offset_encoding,
+                            &params.edit,
+                        ) {
+                            Ok(()) => lsp::ApplyWorkspaceEditResponse {
+                                applied: true,
+                                failure_reason: None,
+                                failed_change: None,
+                            },
+                            Err(err) => lsp::ApplyWorkspaceEditResponse {
+                                applied: false,
+                                failure_reason: Some(err.to_string()),
+                                failed_change: Some(err.failed_change as u32),
+                            },
+                        };
+
+                        if let Some(language_server) =
+                            self.editor.language_servers.get_by_id(server_id)
+                        {
+                            tokio::spawn(
+                                language_server
+                                    .reply(id, Ok(serde_json::to_value(response).unwrap())),
+                            );
+                        }
+                    }
+                    MethodCall::WorkspaceConfiguration(params) => {
+                        // answer with the matching part of the configured settings, or null
+                        let result: Vec<_> = params
+                            .items
+                            .iter()
+                            .map(|item| {
+                                language_server
+                                    .configuration_section(item.section.as_deref())
+                                    .cloned()
+                                    .unwrap_or(serde_json::Value::Null)
+                            })
+                            .collect();
+                        tokio::spawn(
+                            language_server.reply(id, Ok(serde_json::Value::Array(result))),
+                        );
+                    }
+                    MethodCall::ShowMessageRequest(params) => {
+                        commands::show_message_request(
+                            &mut self.editor,
+                            &mut self.compositor,
+                            server_id,
+                            id,
+                            params,
+                        );
+                    }
+                    MethodCall::ShowDocument(params) => {
+                        let offset_encoding = language_server.offset_encoding();
+                        let success =
+                            commands::show_document(&mut self.editor, &params, offset_encoding);
+
+                        if let Some(language_server) =
+                            self.editor.language_servers.get_by_id(server_id)
+                        {
+                            let result = lsp::ShowDocumentResult { success };
+                            tokio::spawn(
+                                language_server
+                                    .reply(id, Ok(serde_json::to_value(result).unwrap())),
+                            );
+                        }
+                    }
+                    MethodCall::RegisterCapability(params) => {
+                        language_server.register_capabilities(params.registrations);
+                        tokio::spawn(language_server.reply(id, Ok(serde_json::Value::Null)));
+                        self.editor.refresh_file_watchers();
+                    }
+                    MethodCall::UnregisterCapability(params) => {
+                        language_server.unregister_capabilities(&params.unregisterations);
+                        tokio::spawn(language_server.reply(id, Ok(serde_json::Value::Null)));
+                        self.editor.refresh_file_watchers();
+                    }
+                    MethodCall::WorkspaceDiagnosticRefresh => {
+                        tokio::spawn(language_server.reply(id, Ok(serde_json::Value::Null)));
+
+                        for doc in self.editor.documents.values_mut() {
+                            doc.clear_diagnostics_pulled_version(server_id);
+                        }
+                        commands::pull_diagnostics(&mut self.editor, &mut self.jobs);
+                    }
                 }
             }
             e => unreachable!("{:?}", e),
@@ -533,6 +650,8 @@ impl Application {
 
         self.event_loop().await;
 
+        self.editor.save_session();
+
         if self.editor.close_language_servers(None).await.is_err() {
             log::error!("Timed out waiting for language servers to shutdown");
         };
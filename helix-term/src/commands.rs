@@ -1,4 +1,5 @@
 use helix_core::{
+    chars::char_is_word,
     comment, coords_at_pos, find_first_non_whitespace_char, find_root, graphemes, indent,
     indent::IndentStyle,
     line_ending::{get_line_ending_of_str, line_end_char_index, str_is_line_ending},
@@ -7,19 +8,29 @@ use helix_core::{
     object, pos_at_coords,
     regex::{self, Regex, RegexBuilder},
     register::Register,
-    search, selection, surround, textobject, LineEnding, Position, Range, Rope, RopeGraphemes,
-    RopeSlice, Selection, SmallVec, Tendril, Transaction,
+    search, selection, surround,
+    syntax::LanguageServerFeature,
+    textobject, LineEnding, Position, Range, Rope, RopeGraphemes, RopeSlice, Selection, SmallVec,
+    Tendril, Transaction,
 };
 
 use helix_view::{
-    clipboard::ClipboardType, document::Mode, editor::Action, input::KeyEvent, keyboard::KeyCode,
-    view::View, Document, DocumentId, Editor, ViewId,
+    clipboard::ClipboardType,
+    document::Mode,
+    editor::{Action, MacroRecording},
+    input::KeyEvent,
+    keyboard::KeyCode,
+    view::View,
+    Document, DocumentId, Editor, ViewId,
 };
 
 use anyhow::{anyhow, bail, Context as _};
 use helix_lsp::{
     lsp,
-    util::{lsp_pos_to_pos, lsp_range_to_range, pos_to_lsp_pos, range_to_lsp_range},
+    util::{
+        apply_semantic_tokens_edits, decode_semantic_tokens, lsp_pos_to_pos, lsp_range_to_range,
+        pos_to_lsp_pos, range_to_lsp_range,
+    },
     OffsetEncoding,
 };
 use insert::*;
@@ -31,12 +42,16 @@ use crate::{
 };
 
 use crate::job::{self, Job, Jobs};
-use futures_util::{FutureExt, StreamExt};
+use futures_util::{
+    future::{self, BoxFuture},
+    FutureExt, StreamExt,
+};
 use std::num::NonZeroUsize;
 use std::{fmt, future::Future};
 
 use std::{
     borrow::Cow,
+    cell::Cell,
     path::{Path, PathBuf},
 };
 
@@ -61,7 +76,7 @@ pub struct Context<'a> {
 impl<'a> Context<'a> {
     /// Push a new component onto the compositor.
     pub fn push_layer(&mut self, component: Box<dyn Component>) {
-        self.callback = Some(Box::new(|compositor: &mut Compositor| {
+        self.callback = Some(Box::new(|compositor: &mut Compositor, _| {
             compositor.push(component)
         }));
     }
@@ -83,16 +98,7 @@ impl<'a> Context<'a> {
         T: for<'de> serde::Deserialize<'de> + Send + 'static,
         F: FnOnce(&mut Editor, &mut Compositor, T) + Send + 'static,
     {
-        let callback = Box::pin(async move {
-            let json = call.await?;
-            let response = serde_json::from_value(json)?;
-            let call: job::Callback =
-                Box::new(move |editor: &mut Editor, compositor: &mut Compositor| {
-                    callback(editor, compositor, response)
-                });
-            Ok(call)
-        });
-        self.jobs.callback(callback);
+        self.jobs.callback(make_job_callback(call, callback));
     }
 
     /// Returns 1 if no explicit count was provided
@@ -102,6 +108,27 @@ impl<'a> Context<'a> {
     }
 }
 
+/// Turn a language server request into a job that deserializes the response and then runs
+/// `callback` with it on the main thread.
+fn make_job_callback<T, F>(
+    call: impl Future<Output = helix_lsp::Result<serde_json::Value>> + 'static + Send,
+    callback: F,
+) -> impl Future<Output = anyhow::Result<job::Callback>>
+where
+    T: for<'de> serde::Deserialize<'de> + Send + 'static,
+    F: FnOnce(&mut Editor, &mut Compositor, T) + Send + 'static,
+{
+    Box::pin(async move {
+        let json = call.await?;
+        let response = serde_json::from_value(json)?;
+        let call: job::Callback =
+            Box::new(move |editor: &mut Editor, compositor: &mut Compositor| {
+                callback(editor, compositor, response)
+            });
+        Ok(call)
+    })
+}
+
 enum Align {
     Top,
     Center,
@@ -226,8 +253,17 @@ impl Command {
         command_mode, "Enter command mode",
         file_picker, "Open file picker",
         code_action, "Perform code action",
+        rename_symbol, "Rename symbol",
         buffer_picker, "Open buffer picker",
         symbol_picker, "Open symbol picker",
+        diagnostics_picker, "Open diagnostic picker",
+        workspace_diagnostics_picker, "Open workspace diagnostic picker",
+        select_references_to_symbol_under_cursor, "Select symbol references",
+        incoming_calls, "Open picker of incoming calls",
+        outgoing_calls, "Open picker of outgoing calls",
+        supertypes, "Open picker of supertypes",
+        code_lens, "Open picker of code lenses on the current line",
+        subtypes, "Open picker of subtypes",
         last_picker, "Open last picker",
         prepend_to_line, "Insert at start of line",
         append_to_line, "Insert at end of line",
@@ -254,6 +290,10 @@ impl Command {
         goto_last_diag, "Goto last diagnostic",
         goto_next_diag, "Goto next diagnostic",
         goto_prev_diag, "Goto previous diagnostic",
+        goto_next_function, "Goto next function",
+        goto_prev_function, "Goto previous function",
+        goto_next_class, "Goto next class",
+        goto_prev_class, "Goto previous class",
         goto_line_start, "Goto line start",
         goto_line_end, "Goto line end",
         // TODO: different description ?
@@ -263,7 +303,8 @@ impl Command {
         extend_to_line_end, "Extend to line end",
         extend_to_line_end_newline, "Extend to line end",
         signature_help, "Show signature help",
-        insert_tab, "Insert tab char",
+        insert_tab, "Insert tab char, or goto next snippet tabstop",
+        goto_prev_tabstop, "Goto previous snippet tabstop",
         insert_newline, "Insert newline char",
         delete_char_backward, "Delete previous char",
         delete_char_forward, "Delete next char",
@@ -298,6 +339,9 @@ impl Command {
         rotate_selection_contents_forward, "Rotate selection contents forward",
         rotate_selection_contents_backward, "Rotate selections contents backward",
         expand_selection, "Expand selection to parent syntax node",
+        shrink_selection, "Shrink selection to previously expanded syntax node",
+        select_next_sibling, "Select next sibling syntax node",
+        select_prev_sibling, "Select previous sibling syntax node",
         jump_forward, "Jump forward on jumplist",
         jump_backward, "Jump backward on jumplist",
         rotate_view, "Goto next window",
@@ -305,6 +349,8 @@ impl Command {
         vsplit, "Vertical right split",
         wclose, "Close window",
         select_register, "Select register",
+        record_macro, "Record macro",
+        replay_macro, "Replay macro",
         align_view_middle, "Align view middle",
         align_view_top, "Align view top",
         align_view_center, "Align view center",
@@ -1297,11 +1343,11 @@ fn global_search(cx: &mut Context) {
                 let picker = FilePicker::new(
                     all_matches,
                     move |(_line_num, path)| path.to_str().unwrap().into(),
-                    move |editor: &mut Editor, (line_num, path), action| {
-                        match editor.open(path.into(), action) {
+                    move |cx, (line_num, path), action| {
+                        match cx.editor.open(path.into(), action) {
                             Ok(_) => {}
                             Err(e) => {
-                                editor.set_error(format!(
+                                cx.editor.set_error(format!(
                                     "Failed to open file '{}': {}",
                                     path.display(),
                                     e
@@ -1311,7 +1357,7 @@ fn global_search(cx: &mut Context) {
                         }
 
                         let line_num = *line_num;
-                        let (view, doc) = current!(editor);
+                        let (view, doc) = current!(cx.editor);
                         let text = doc.text();
                         let start = text.line_to_char(line_num);
                         let end = text.line_to_char((line_num + 1).min(text.len_lines()));
@@ -1471,8 +1517,9 @@ fn append_mode(cx: &mut Context) {
 
 mod cmd {
     use super::*;
-    use std::collections::HashMap;
+    use std::{collections::HashMap, sync::Arc};
 
+    use helix_core::syntax::LanguageConfiguration;
     use helix_view::editor::Action;
     use ui::completers::{self, Completer};
 
@@ -1548,10 +1595,35 @@ mod cmd {
             shared
         });
         let future = doc.format_and_save(fmt);
-        cx.jobs.add(Job::new(future).wait_before_exiting());
+        cx.jobs.add(save_job(doc.id(), future));
         Ok(())
     }
 
+    /// Wait for `future` to save a document, then pull its diagnostics again since servers
+    /// usually update them on save.
+    fn save_job(
+        doc_id: DocumentId,
+        future: impl Future<Output = anyhow::Result<()>> + Send + 'static,
+    ) -> Job {
+        Job::with_callback(async move {
+            future.await?;
+            let call: job::Callback =
+                Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                    if let Some(doc) = editor.documents.get_mut(doc_id) {
+                        let server_ids: Vec<_> =
+                            doc.language_servers().map(|server| server.id()).collect();
+                        for server_id in server_ids {
+                            doc.clear_diagnostics_pulled_version(server_id);
+                        }
+                    }
+                    // the pull happens on the next idle timeout
+                    editor.reset_idle_timer();
+                });
+            Ok(call)
+        })
+        .wait_before_exiting()
+    }
+
     fn write(
         cx: &mut compositor::Context,
         args: &[&str],
@@ -1757,7 +1829,7 @@ mod cmd {
 
             // TODO: handle error.
             let handle = doc.save();
-            cx.jobs.add(Job::new(handle).wait_before_exiting());
+            cx.jobs.add(save_job(doc.id(), handle));
         }
 
         if quit {
@@ -2073,6 +2145,332 @@ mod cmd {
         Ok(())
     }
 
+    fn show_diagnostics_picker(
+        cx: &mut compositor::Context,
+        args: &[&str],
+        workspace: bool,
+    ) -> anyhow::Result<()> {
+        let min_severity = match args.first() {
+            Some(&"error") => Some(lsp::DiagnosticSeverity::ERROR),
+            Some(&"warning") => Some(lsp::DiagnosticSeverity::WARNING),
+            Some(&"info") => Some(lsp::DiagnosticSeverity::INFORMATION),
+            Some(&"hint") => Some(lsp::DiagnosticSeverity::HINT),
+            Some(severity) => bail!("unknown severity: {}", severity),
+            None => picker_min_severity(cx.editor),
+        };
+        let uri = if workspace {
+            None
+        } else {
+            let (_, doc) = current!(cx.editor);
+            Some(doc.url().context("document has no path")?)
+        };
+
+        let callback = async move {
+            let call: job::Callback =
+                Box::new(move |editor: &mut Editor, compositor: &mut Compositor| {
+                    let picker = diagnostics_picker_impl(editor, uri.as_ref(), min_severity);
+                    compositor.push(Box::new(picker));
+                });
+            Ok(call)
+        };
+        cx.jobs.callback(callback);
+        Ok(())
+    }
+
+    fn diagnostics(
+        cx: &mut compositor::Context,
+        args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        show_diagnostics_picker(cx, args, false)
+    }
+
+    fn workspace_diagnostics(
+        cx: &mut compositor::Context,
+        args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        show_diagnostics_picker(cx, args, true)
+    }
+
+    fn toggle_inlay_hints(
+        cx: &mut compositor::Context,
+        _args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        cx.editor.config.inlay_hints = !cx.editor.config.inlay_hints;
+
+        if cx.editor.config.inlay_hints {
+            compute_inlay_hints_for_all_views(cx.editor, cx.jobs);
+        } else {
+            for doc in cx.editor.documents.values_mut() {
+                doc.clear_inlay_hints();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resend the full text of the current document to its language servers, reporting servers
+    /// whose copy of the document had a different version.
+    fn lsp_resync(
+        cx: &mut compositor::Context,
+        _args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        let (_, doc) = current!(cx.editor);
+        let identifier = doc.versioned_identifier();
+
+        let mut resynced = 0;
+        let mut mismatches = Vec::new();
+        for language_server in doc.language_servers() {
+            let version = language_server.document_version(&identifier.uri);
+            let notify = match language_server
+                .text_document_did_change_full(identifier.clone(), doc.text())
+            {
+                Some(notify) => notify,
+                None => continue,
+            };
+            tokio::spawn(notify);
+            resynced += 1;
+
+            if version != Some(identifier.version) {
+                let version = version.map_or_else(|| "none".to_string(), |v| v.to_string());
+                mismatches.push(format!(
+                    "{} had version {}",
+                    language_server.name(),
+                    version
+                ));
+            }
+        }
+
+        if resynced == 0 {
+            bail!("No language server accepts document changes");
+        }
+        if !mismatches.is_empty() {
+            bail!(
+                "Resynced at version {}, but {}",
+                identifier.version,
+                mismatches.join(", ")
+            );
+        }
+        cx.editor.set_status(format!(
+            "Resynced {} language server(s) at version {}",
+            resynced, identifier.version
+        ));
+        Ok(())
+    }
+
+    /// Language configuration of the current document, for commands that act on its language
+    /// servers.
+    fn current_language(editor: &mut Editor) -> anyhow::Result<Arc<LanguageConfiguration>> {
+        let (_, doc) = current!(editor);
+        let scope = doc.language().context("document has no language")?;
+        let language = editor
+            .syn_loader
+            .language_config_for_scope(scope)
+            .context("unknown language")?;
+        if language.language_servers.is_empty() {
+            bail!("No language server is configured for {}", scope);
+        }
+        Ok(language)
+    }
+
+    fn lsp_restart(
+        cx: &mut compositor::Context,
+        _args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        let language = current_language(cx.editor)?;
+        let clients = cx.editor.restart_language_servers(&language)?;
+        if clients.is_empty() {
+            bail!("Failed to start the language servers of {}", language.scope);
+        }
+        let names: Vec<_> = clients.iter().map(|client| client.name()).collect();
+        cx.editor
+            .set_status(format!("Restarted {}", names.join(", ")));
+        Ok(())
+    }
+
+    fn lsp_stop(
+        cx: &mut compositor::Context,
+        _args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        let language = current_language(cx.editor)?;
+        let clients = cx.editor.stop_language_servers(&language.scope);
+        if clients.is_empty() {
+            bail!("No language server is running for {}", language.scope);
+        }
+        let names: Vec<_> = clients.iter().map(|client| client.name()).collect();
+        cx.editor
+            .set_status(format!("Stopped {}", names.join(", ")));
+        Ok(())
+    }
+
+    fn lsp_info(
+        cx: &mut compositor::Context,
+        _args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        let mut clients: Vec<_> = cx.editor.language_servers.iter_clients().collect();
+        if clients.is_empty() {
+            bail!("No language server is running");
+        }
+        clients.sort_by_key(|client| client.id());
+
+        let mut contents = String::new();
+        for client in clients {
+            let pid = client
+                .pid()
+                .map_or_else(|| "exited".to_string(), |pid| pid.to_string());
+            let offset_encoding = match client.offset_encoding() {
+                OffsetEncoding::Utf8 => "utf-8",
+                OffsetEncoding::Utf16 => "utf-16",
+            };
+            contents.push_str(&format!(
+                "### {} (id {})\n\n- pid: {}\n- offset encoding: {}\n",
+                client.name(),
+                client.id(),
+                pid,
+                offset_encoding
+            ));
+
+            let folders = client.workspace_folders();
+            if folders.is_empty() {
+                contents.push_str("- workspace folders: none\n");
+            } else {
+                contents.push_str("- workspace folders:\n");
+                for folder in folders {
+                    contents.push_str(&format!("  - {}\n", folder.uri));
+                }
+            }
+
+            let progress: Vec<_> = cx
+                .editor
+                .lsp_progress
+                .progress_map(client.id())
+                .into_iter()
+                .flat_map(|progress| progress.values())
+                .map(describe_progress)
+                .collect();
+            if progress.is_empty() {
+                contents.push_str("- progress: none\n");
+            } else {
+                contents.push_str("- progress:\n");
+                for progress in progress {
+                    contents.push_str(&format!("  - {}\n", progress));
+                }
+            }
+
+            let dynamic_methods = client.dynamic_methods();
+            if !dynamic_methods.is_empty() {
+                contents.push_str(&format!(
+                    "- dynamic registrations: {}\n",
+                    dynamic_methods.join(", ")
+                ));
+            }
+
+            let mut latencies = client.request_latencies();
+            if !latencies.is_empty() {
+                latencies.sort_by_key(|(_, latency)| std::cmp::Reverse(latency.max));
+                contents.push_str("- slowest requests:\n");
+                for (method, latency) in latencies.iter().take(LSP_INFO_LATENCIES) {
+                    contents.push_str(&format!(
+                        "  - {}: {} calls, mean {}ms, max {}ms\n",
+                        method,
+                        latency.count,
+                        latency.mean().as_millis(),
+                        latency.max.as_millis()
+                    ));
+                }
+            }
+
+            if client.is_initialized() {
+                let capabilities = serde_json::to_string_pretty(client.capabilities())?;
+                contents.push_str(&format!("\n```json\n{}\n```\n\n", capabilities));
+            } else {
+                contents.push_str("- initializing\n\n");
+            }
+        }
+
+        let callback = async move {
+            let call: job::Callback =
+                Box::new(move |editor: &mut Editor, compositor: &mut Compositor| {
+                    let contents = ui::Markdown::new(contents, editor.syn_loader.clone());
+                    compositor.push(Box::new(Popup::new(contents)));
+                });
+            Ok(call)
+        };
+        cx.jobs.callback(callback);
+        Ok(())
+    }
+
+    /// Number of request methods listed by `:lsp-info`, slowest first.
+    const LSP_INFO_LATENCIES: usize = 10;
+
+    fn lsp_log(
+        cx: &mut compositor::Context,
+        _args: &[&str],
+        _event: PromptEvent,
+    ) -> anyhow::Result<()> {
+        if cx.editor.lsp_log.is_empty() {
+            bail!("No language server has logged anything yet");
+        }
+
+        let mut text = String::new();
+        for message in &cx.editor.lsp_log {
+            text.push_str(message);
+            text.push('\n');
+        }
+        let doc = Document::from(Rope::from(text), None);
+        cx.editor
+            .new_file_from_document(Action::HorizontalSplit, doc);
+
+        // start at the latest message
+        let (view, doc) = current!(cx.editor);
+        let pos = doc
+            .text()
+            .line_to_char(doc.text().len_lines().saturating_sub(2));
+        doc.set_selection(view.id, Selection::point(pos));
+        Ok(())
+    }
+
+    fn describe_progress(status: &helix_lsp::ProgressStatus) -> String {
+        let progress = match status.progress() {
+            Some(progress) => progress,
+            None => return "created".to_string(),
+        };
+        let (title, message, percentage) = match progress {
+            lsp::WorkDoneProgress::Begin(lsp::WorkDoneProgressBegin {
+                title,
+                message,
+                percentage,
+                ..
+            }) => (Some(title), message, percentage),
+            lsp::WorkDoneProgress::Report(lsp::WorkDoneProgressReport {
+                message,
+                percentage,
+                ..
+            }) => (None, message, percentage),
+            lsp::WorkDoneProgress::End(lsp::WorkDoneProgressEnd { message }) => {
+                (None, message, &None)
+            }
+        };
+
+        let parts: Vec<String> = title
+            .cloned()
+            .into_iter()
+            .chain(message.clone())
+            .chain(percentage.map(|percentage| format!("{}%", percentage)))
+            .collect();
+        if parts.is_empty() {
+            "in progress".to_string()
+        } else {
+            parts.join(": ")
+        }
+    }
+
     pub const TYPABLE_COMMAND_LIST: &[TypableCommand] = &[
         TypableCommand {
             name: "quit",
@@ -2325,6 +2723,62 @@ mod cmd {
             doc: "Open the file in a horizontal split.",
             fun: hsplit,
             completer: Some(completers::filename),
+        },
+        TypableCommand {
+            name: "diagnostics",
+            alias: None,
+            doc: "Open a picker with the diagnostics of the current document, optionally only those at least as severe as the given severity (error, warning, info or hint).",
+            fun: diagnostics,
+            completer: Some(completers::diagnostic_severity),
+        },
+        TypableCommand {
+            name: "workspace-diagnostics",
+            alias: None,
+            doc: "Open a picker with the diagnostics of all files in the workspace, optionally only those at least as severe as the given severity (error, warning, info or hint).",
+            fun: workspace_diagnostics,
+            completer: Some(completers::diagnostic_severity),
+        },
+        TypableCommand {
+            name: "toggle-inlay-hints",
+            alias: None,
+            doc: "Toggle inlay hints from language servers.",
+            fun: toggle_inlay_hints,
+            completer: None,
+        },
+        TypableCommand {
+            name: "lsp-resync",
+            alias: None,
+            doc: "Resend the full text of the current document to its language servers and check that they had the same version.",
+            fun: lsp_resync,
+            completer: None,
+        },
+        TypableCommand {
+            name: "lsp-restart",
+            alias: None,
+            doc: "Restart the language servers of the current document's language.",
+            fun: lsp_restart,
+            completer: None,
+        },
+        TypableCommand {
+            name: "lsp-stop",
+            alias: None,
+            doc: "Stop the language servers of the current document's language.",
+            fun: lsp_stop,
+            completer: None,
+        },
+        TypableCommand {
+            name: "lsp-info",
+            alias: None,
+            doc: "Show the running language servers with their process id, offset encoding, progress and capabilities.",
+            fun: lsp_info,
+            completer: None,
+        },
+        TypableCommand {
+            name: "lsp-log",
+            alias: None,
+            doc: "Open a scratch buffer with the messages language servers logged through window/logMessage.",
+            fun: lsp_log,
+            completer: None,
         }
     ];
 
@@ -2415,7 +2869,7 @@ fn command_mode(cx: &mut Context) {
 }
 
 fn file_picker(cx: &mut Context) {
-    let root = find_root(None).unwrap_or_else(|| PathBuf::from("./"));
+    let root = find_root(None, &[]).unwrap_or_else(|| PathBuf::from("./"));
     let picker = ui::file_picker(root);
     cx.push_layer(Box::new(picker));
 }
@@ -2442,8 +2896,8 @@ fn buffer_picker(cx: &mut Context) {
                 None => "[scratch buffer]".into(),
             }
         },
-        |editor: &mut Editor, (id, _path): &(DocumentId, Option<PathBuf>), _action| {
-            editor.switch(*id, Action::Replace);
+        |cx, (id, _path): &(DocumentId, Option<PathBuf>), _action| {
+            cx.editor.switch(*id, Action::Replace);
         },
         |editor, (id, path)| {
             let doc = &editor.documents.get(*id)?;
@@ -2479,10 +2933,11 @@ fn symbol_picker(cx: &mut Context) {
     }
     let (_, doc) = current!(cx.editor);
 
-    let language_server = match doc.language_server() {
-        Some(language_server) => language_server,
-        None => return,
-    };
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::DocumentSymbols) {
+            Some(language_server) => language_server,
+            None => return,
+        };
     let offset_encoding = language_server.offset_encoding();
 
     let future = language_server.document_symbols(doc.identifier());
@@ -2510,9 +2965,9 @@ fn symbol_picker(cx: &mut Context) {
                 let picker = FilePicker::new(
                     symbols,
                     |symbol| (&symbol.name).into(),
-                    move |editor: &mut Editor, symbol, _action| {
-                        push_jump(editor);
-                        let (view, doc) = current!(editor);
+                    move |cx, symbol, _action| {
+                        push_jump(cx.editor);
+                        let (view, doc) = current!(cx.editor);
 
                         if let Some(range) =
                             lsp_range_to_range(doc.text(), symbol.location.range, offset_encoding)
@@ -2538,10 +2993,126 @@ fn symbol_picker(cx: &mut Context) {
     )
 }
 
+/// Build a picker over the diagnostics the editor knows of. Only diagnostics of `uri` are listed
+/// if given, and only those at least as severe as `min_severity`.
+fn diagnostics_picker_impl(
+    editor: &Editor,
+    uri: Option<&lsp::Url>,
+    min_severity: Option<lsp::DiagnosticSeverity>,
+) -> FilePicker<(lsp::Url, lsp::Diagnostic, usize)> {
+    let diagnostics: Vec<_> = editor
+        .diagnostics
+        .iter()
+        .filter(|(url, _)| uri.is_none() || uri == Some(*url))
+        .flat_map(|(url, diagnostics)| {
+            diagnostics
+                .iter()
+                .filter(|(diagnostic, _)| {
+                    // servers that don't specify a severity are treated as warnings, like in the
+                    // gutter
+                    let severity = diagnostic
+                        .severity
+                        .unwrap_or(lsp::DiagnosticSeverity::WARNING);
+                    min_severity.is_none() || Some(severity) <= min_severity
+                })
+                .map(move |(diagnostic, server_id)| (url.clone(), diagnostic.clone(), *server_id))
+        })
+        .collect();
+
+    let show_path = uri.is_none();
+
+    FilePicker::new(
+        diagnostics,
+        move |(url, diagnostic, _server_id)| {
+            let severity = match diagnostic.severity {
+                Some(lsp::DiagnosticSeverity::ERROR) => "error",
+                Some(lsp::DiagnosticSeverity::INFORMATION) => "info",
+                Some(lsp::DiagnosticSeverity::HINT) => "hint",
+                _ => "warning",
+            };
+            let message = diagnostic.message.lines().next().unwrap_or("");
+            let line = diagnostic.range.start.line + 1;
+            if show_path {
+                let path = url
+                    .to_file_path()
+                    .map(|path| {
+                        helix_core::path::get_relative_path(&path)
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .unwrap_or_else(|_| url.to_string());
+                format!("{:<7} {}:{} {}", severity, path, line, message).into()
+            } else {
+                format!("{:<7} {}: {}", severity, line, message).into()
+            }
+        },
+        move |cx, (url, diagnostic, server_id), action| {
+            let offset_encoding = match cx.editor.language_servers.get_by_id(*server_id) {
+                Some(language_server) => language_server.offset_encoding(),
+                None => return,
+            };
+            let path = match url.to_file_path() {
+                Ok(path) => path,
+                Err(_) => return,
+            };
+            push_jump(cx.editor);
+            if let Err(err) = cx.editor.open(path, action) {
+                cx.editor.set_error(format!("failed to open file: {}", err));
+                return;
+            }
+            let (view, doc) = current!(cx.editor);
+            if let Some(range) = lsp_range_to_range(doc.text(), diagnostic.range, offset_encoding) {
+                // place the cursor at the start of the diagnostic
+                doc.set_selection(view.id, Selection::single(range.head, range.anchor));
+                align_view(doc, view, Align::Center);
+            }
+        },
+        |_editor, (url, diagnostic, _server_id)| {
+            let path = url.to_file_path().ok()?;
+            let line = Some((
+                diagnostic.range.start.line as usize,
+                diagnostic.range.end.line as usize,
+            ));
+            Some((path, line))
+        },
+    )
+}
+
+/// The `diagnostics-picker-severity` of the config, as the lsp severity the pickers filter by.
+fn picker_min_severity(editor: &Editor) -> Option<lsp::DiagnosticSeverity> {
+    use helix_core::diagnostic::Severity;
+
+    editor
+        .config
+        .diagnostics_picker_severity
+        .map(|severity| match severity {
+            Severity::Error => lsp::DiagnosticSeverity::ERROR,
+            Severity::Warning => lsp::DiagnosticSeverity::WARNING,
+            Severity::Info => lsp::DiagnosticSeverity::INFORMATION,
+            Severity::Hint => lsp::DiagnosticSeverity::HINT,
+        })
+}
+
+fn diagnostics_picker(cx: &mut Context) {
+    let (_, doc) = current!(cx.editor);
+    if let Some(uri) = doc.url() {
+        let min_severity = picker_min_severity(cx.editor);
+        let picker = diagnostics_picker_impl(cx.editor, Some(&uri), min_severity);
+        cx.push_layer(Box::new(picker));
+    }
+}
+
+fn workspace_diagnostics_picker(cx: &mut Context) {
+    let min_severity = picker_min_severity(cx.editor);
+    let picker = diagnostics_picker_impl(cx.editor, None, min_severity);
+    cx.push_layer(Box::new(picker));
+}
+
 pub fn code_action(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
 
-    let language_server = match doc.language_server() {
+    let language_server = match doc.language_server_with_feature(LanguageServerFeature::CodeAction)
+    {
         Some(language_server) => language_server,
         None => return,
     };
@@ -2554,6 +3125,7 @@ pub fn code_action(cx: &mut Context) {
 
     let future = language_server.code_actions(doc.identifier(), range);
     let offset_encoding = language_server.offset_encoding();
+    let language_server_id = language_server.id();
 
     cx.callback(
         future,
@@ -2570,15 +3142,24 @@ pub fn code_action(cx: &mut Context) {
                         }
                         lsp::CodeActionOrCommand::Command(command) => command.title.as_str().into(),
                     },
-                    move |editor, code_action, _action| match code_action {
+                    move |cx, code_action, _action| match code_action {
                         lsp::CodeActionOrCommand::Command(command) => {
                             log::debug!("code action command: {:?}", command);
-                            editor.set_error(String::from("Handling code action command is not implemented yet, see https://github.com/helix-editor/helix/issues/183"));
+                            execute_lsp_command(cx, language_server_id, command.clone());
                         }
                         lsp::CodeActionOrCommand::CodeAction(code_action) => {
                             log::debug!("code action: {:?}", code_action);
+                            // the edit is applied before the command is executed
                             if let Some(ref workspace_edit) = code_action.edit {
-                                apply_workspace_edit(editor, offset_encoding, workspace_edit)
+                                if let Err(err) =
+                                    apply_workspace_edit(cx.editor, offset_encoding, workspace_edit)
+                                {
+                                    cx.editor.set_error(err.to_string());
+                                    return;
+                                }
+                            }
+                            if let Some(command) = &code_action.command {
+                                execute_lsp_command(cx, language_server_id, command.clone());
                             }
                         }
                     },
@@ -2589,87 +3170,495 @@ pub fn code_action(cx: &mut Context) {
     )
 }
 
-fn apply_workspace_edit(
+/// Apply `text_edits` to the document at `uri`, opening it in the background if it isn't open yet.
+fn apply_text_edits(
     editor: &mut Editor,
+    uri: &helix_lsp::Url,
+    text_edits: Vec<lsp::TextEdit>,
     offset_encoding: OffsetEncoding,
-    workspace_edit: &lsp::WorkspaceEdit,
-) {
-    if let Some(ref changes) = workspace_edit.changes {
-        log::debug!("workspace changes: {:?}", changes);
-        editor.set_error(String::from("Handling workspace_edit.changes is not implemented yet, see https://github.com/helix-editor/helix/issues/183"));
-        return;
-        // Not sure if it works properly, it'll be safer to just panic here to avoid breaking some parts of code on which code actions will be used
-        // TODO: find some example that uses workspace changes, and test it
-        // for (url, edits) in changes.iter() {
-        //     let file_path = url.origin().ascii_serialization();
-        //     let file_path = std::path::PathBuf::from(file_path);
-        //     let file = std::fs::File::open(file_path).unwrap();
-        //     let mut text = Rope::from_reader(file).unwrap();
-        //     let transaction = edits_to_changes(&text, edits);
-        //     transaction.apply(&mut text);
-        // }
-    }
-
-    if let Some(ref document_changes) = workspace_edit.document_changes {
-        match document_changes {
-            lsp::DocumentChanges::Edits(document_edits) => {
-                for document_edit in document_edits {
-                    let path = document_edit
-                        .text_document
-                        .uri
-                        .to_file_path()
-                        .expect("unable to convert URI to filepath");
-                    let current_view_id = view!(editor).id;
-                    let doc = editor
-                        .document_by_path_mut(path)
-                        .expect("Document for document_changes not found");
-
-                    // Need to determine a view for apply/append_changes_to_history
-                    let selections = doc.selections();
-                    let view_id = if selections.contains_key(&current_view_id) {
-                        // use current if possible
-                        current_view_id
-                    } else {
-                        // Hack: we take the first available view_id
-                        selections
-                            .keys()
-                            .next()
-                            .copied()
-                            .expect("No view_id available")
-                    };
+) -> anyhow::Result<()> {
+    let path = uri
+        .to_file_path()
+        .map_err(|_| anyhow!("unable to convert URI to filepath: {}", uri))?;
+
+    let current_view_id = view!(editor).id;
+    let doc_id = editor.open(path, Action::Load)?;
+    let doc = editor
+        .document_mut(doc_id)
+        .ok_or_else(|| anyhow!("document for {} not found", uri))?;
+
+    // Need to determine a view for apply/append_changes_to_history
+    let selections = doc.selections();
+    let view_id = if selections.contains_key(&current_view_id) {
+        // use current if possible
+        current_view_id
+    } else if let Some(view_id) = selections.keys().next().copied() {
+        // Hack: we take the first available view_id
+        view_id
+    } else {
+        // the document was just opened and isn't displayed anywhere yet
+        doc.set_selection(current_view_id, Selection::point(0));
+        current_view_id
+    };
 
-                    let edits = document_edit
-                        .edits
-                        .iter()
-                        .map(|edit| match edit {
-                            lsp::OneOf::Left(text_edit) => text_edit,
-                            lsp::OneOf::Right(annotated_text_edit) => {
-                                &annotated_text_edit.text_edit
-                            }
-                        })
-                        .cloned()
-                        .collect();
+    let transaction =
+        helix_lsp::util::generate_transaction_from_edits(doc.text(), text_edits, offset_encoding);
+    doc.apply(&transaction, view_id);
+    doc.append_changes_to_history(view_id);
+    Ok(())
+}
 
-                    let transaction = helix_lsp::util::generate_transaction_from_edits(
-                        doc.text(),
-                        edits,
-                        offset_encoding,
-                    );
-                    doc.apply(&transaction, view_id);
-                    doc.append_changes_to_history(view_id);
+fn apply_document_edit(
+    editor: &mut Editor,
+    document_edit: &lsp::TextDocumentEdit,
+    offset_encoding: OffsetEncoding,
+) -> anyhow::Result<()> {
+    let edits = document_edit
+        .edits
+        .iter()
+        .map(|edit| match edit {
+            lsp::OneOf::Left(text_edit) => text_edit,
+            lsp::OneOf::Right(annotated_text_edit) => &annotated_text_edit.text_edit,
+        })
+        .cloned()
+        .collect();
+
+    apply_text_edits(
+        editor,
+        &document_edit.text_document.uri,
+        edits,
+        offset_encoding,
+    )
+}
+
+fn apply_resource_op(editor: &mut Editor, op: &lsp::ResourceOp) -> anyhow::Result<()> {
+    use std::fs;
+
+    fn to_file_path(uri: &helix_lsp::Url) -> anyhow::Result<PathBuf> {
+        uri.to_file_path()
+            .map_err(|_| anyhow!("unable to convert URI to filepath: {}", uri))
+    }
+
+    match op {
+        lsp::ResourceOp::Create(op) => {
+            let path = to_file_path(&op.uri)?;
+            let options = op.options.as_ref();
+            let overwrite = options.and_then(|options| options.overwrite) == Some(true);
+            let ignore_if_exists =
+                options.and_then(|options| options.ignore_if_exists) == Some(true);
+
+            if path.exists() && !overwrite {
+                if ignore_if_exists {
+                    return Ok(());
+                }
+                bail!("file already exists: {}", path.display());
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, [])?;
+        }
+        lsp::ResourceOp::Delete(op) => {
+            let path = to_file_path(&op.uri)?;
+            let options = op.options.as_ref();
+            let recursive = options.and_then(|options| options.recursive) == Some(true);
+            let ignore_if_not_exists =
+                options.and_then(|options| options.ignore_if_not_exists) == Some(true);
+
+            if !path.exists() {
+                if ignore_if_not_exists {
+                    return Ok(());
+                }
+                bail!("file does not exist: {}", path.display());
+            }
+            // compare against the normalized paths of open documents
+            let path = helix_core::path::get_canonicalized_path(&path)?;
+            if path.is_dir() {
+                if recursive {
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_dir(&path)?;
+                }
+            } else {
+                fs::remove_file(&path)?;
+            }
+
+            // the documents stay open with their text, marked so it's clear the file is gone
+            for doc in editor.documents.values_mut() {
+                if matches!(doc.path(), Some(doc_path) if doc_path.starts_with(&path)) {
+                    doc.mark_deleted_on_disk();
+                }
+            }
+        }
+        lsp::ResourceOp::Rename(op) => {
+            let old_path = to_file_path(&op.old_uri)?;
+            let new_path = to_file_path(&op.new_uri)?;
+            let options = op.options.as_ref();
+            let overwrite = options.and_then(|options| options.overwrite) == Some(true);
+            let ignore_if_exists =
+                options.and_then(|options| options.ignore_if_exists) == Some(true);
+
+            if new_path.exists() && !overwrite {
+                if ignore_if_exists {
+                    return Ok(());
                 }
+                bail!("file already exists: {}", new_path.display());
+            }
+            if let Some(parent) = new_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&old_path, &new_path)?;
+
+            // keep open documents pointing at the file, or at the files inside the directory
+            let old_path = helix_core::path::get_canonicalized_path(&old_path)?;
+            for doc in editor.documents.values_mut() {
+                let relative = match doc.path().map(|path| path.strip_prefix(&old_path)) {
+                    Some(Ok(relative)) => relative.to_path_buf(),
+                    _ => continue,
+                };
+                doc.set_path(&new_path.join(relative))?;
             }
-            lsp::DocumentChanges::Operations(operations) => {
-                log::debug!("document changes - operations: {:?}", operations);
-                editor.set_error(String::from("Handling document operations is not implemented yet, see https://github.com/helix-editor/helix/issues/183"));
+        }
+    }
+
+    Ok(())
+}
+
+/// The change of a [`lsp::WorkspaceEdit`] that couldn't be applied.
+#[derive(Debug)]
+pub struct ApplyWorkspaceEditError {
+    /// Index of the failed change. Changes before it have been applied.
+    pub failed_change: usize,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for ApplyWorkspaceEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+/// Apply the changes of a workspace edit in order, stopping at the first one that fails.
+pub fn apply_workspace_edit(
+    editor: &mut Editor,
+    offset_encoding: OffsetEncoding,
+    workspace_edit: &lsp::WorkspaceEdit,
+) -> Result<(), ApplyWorkspaceEditError> {
+    let result = if let Some(ref document_changes) = workspace_edit.document_changes {
+        // documentChanges is preferred over changes if the server sends both
+        match document_changes {
+            lsp::DocumentChanges::Edits(document_edits) => document_edits
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, document_edit)| {
+                    apply_document_edit(editor, document_edit, offset_encoding).map_err(|e| (i, e))
+                }),
+            lsp::DocumentChanges::Operations(operations) => operations
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, operation)| {
+                    match operation {
+                        lsp::DocumentChangeOperation::Edit(document_edit) => {
+                            apply_document_edit(editor, document_edit, offset_encoding)
+                        }
+                        lsp::DocumentChangeOperation::Op(op) => apply_resource_op(editor, op),
+                    }
+                    .map_err(|e| (i, e))
+                }),
+        }
+    } else if let Some(ref changes) = workspace_edit.changes {
+        log::debug!("workspace changes: {:?}", changes);
+        // sorted so that the index of a failed change means the same thing every time
+        let mut changes: Vec<_> = changes.iter().collect();
+        changes.sort_by_key(|(uri, _)| uri.as_str());
+        changes
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(i, (uri, text_edits))| {
+                apply_text_edits(editor, uri, text_edits.clone(), offset_encoding)
+                    .map_err(|e| (i, e))
+            })
+    } else {
+        Ok(())
+    };
+
+    result.map_err(|(failed_change, error)| ApplyWorkspaceEditError {
+        failed_change,
+        error,
+    })
+}
+
+impl ui::menu::Item for lsp::MessageActionItem {
+    fn sort_text(&self) -> &str {
+        &self.title
+    }
+
+    fn filter_text(&self) -> &str {
+        &self.title
+    }
+
+    fn label(&self) -> &str {
+        &self.title
+    }
+
+    fn row(&self) -> ui::menu::Row<'_> {
+        ui::menu::Row::new(vec![ui::menu::Cell::from(self.title.as_str())])
+    }
+}
+
+/// Show the message of a `window/showMessageRequest` along with a menu of its actions. The picked
+/// action is sent back to the server, or null if the menu is dismissed.
+pub fn show_message_request(
+    editor: &mut Editor,
+    compositor: &mut Compositor,
+    server_id: usize,
+    id: helix_lsp::jsonrpc::Id,
+    params: lsp::ShowMessageRequestParams,
+) {
+    if params.typ == lsp::MessageType::ERROR {
+        editor.set_error(params.message);
+    } else {
+        editor.set_status(params.message);
+    }
+
+    let actions = params.actions.unwrap_or_default();
+    if actions.is_empty() {
+        if let Some(language_server) = editor.language_servers.get_by_id(server_id) {
+            tokio::spawn(language_server.reply(id, Ok(serde_json::Value::Null)));
+        }
+        return;
+    }
+
+    let mut menu = ui::Menu::new(
+        actions,
+        move |editor: &mut Editor, action: Option<&lsp::MessageActionItem>, event| {
+            let result = match event {
+                PromptEvent::Validate => serde_json::to_value(action).unwrap_or_default(),
+                PromptEvent::Abort => serde_json::Value::Null,
+                PromptEvent::Update => return,
+            };
+            if let Some(language_server) = editor.language_servers.get_by_id(server_id) {
+                tokio::spawn(language_server.reply(id.clone(), Ok(result)));
             }
+        },
+    );
+    // the menu only validates a selected option, so select the first action to make sure
+    // confirming right away still answers the server
+    menu.move_down();
+    compositor.push(Box::new(Popup::new(menu)));
+}
+
+/// Handle a `window/showDocument` request, returning whether the document could be shown. Files
+/// are opened in the editor unless the server asks for an external program, which is also used
+/// for URIs that aren't files.
+pub fn show_document(
+    editor: &mut Editor,
+    params: &lsp::ShowDocumentParams,
+    offset_encoding: OffsetEncoding,
+) -> bool {
+    let path = match params.uri.to_file_path() {
+        Ok(path) if params.external != Some(true) => path,
+        _ => {
+            return match open_external(params.uri.as_str()) {
+                Ok(()) => true,
+                Err(err) => {
+                    editor.set_error(format!("Failed to open {}: {}", params.uri, err));
+                    false
+                }
+            };
+        }
+    };
+
+    // documents that shouldn't take focus are only loaded
+    let take_focus = params.take_focus != Some(false);
+    let action = if take_focus {
+        push_jump(editor);
+        Action::Replace
+    } else {
+        Action::Load
+    };
+    if let Err(err) = editor.open(path, action) {
+        editor.set_error(format!("Failed to open {}: {}", params.uri, err));
+        return false;
+    }
+
+    if let (true, Some(selection)) = (take_focus, params.selection) {
+        let (view, doc) = current!(editor);
+        if let Some(range) = lsp_range_to_range(doc.text(), selection, offset_encoding) {
+            doc.set_selection(view.id, Selection::single(range.anchor, range.head));
+            align_view(doc, view, Align::Center);
+        }
+    }
+    true
+}
+
+/// Open `target`, a path or URI, with the default program of the system.
+fn open_external(target: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = tokio::process::Command::new("open");
+    // not `cmd /C start`, which would run whatever follows a `&` in the URI
+    #[cfg(windows)]
+    let mut command = tokio::process::Command::new("explorer");
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = tokio::process::Command::new("xdg-open");
+
+    command
+        .arg(target)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Run a command on the language server that provided it, reporting failures in the status line.
+fn execute_lsp_command(
+    cx: &mut compositor::Context,
+    language_server_id: usize,
+    command: lsp::Command,
+) {
+    let language_server = match cx.editor.language_servers.get_by_id(language_server_id) {
+        Some(language_server) => language_server,
+        None => return,
+    };
+
+    let future = match language_server.command(command, None) {
+        Some(future) => future,
+        None => {
+            cx.editor
+                .set_error("Language server does not support executing commands".to_string());
+            return;
         }
+    };
+
+    cx.jobs.callback(async move {
+        let call: job::Callback = match future.await {
+            Ok(_) => Box::new(|_: &mut Editor, _: &mut Compositor| {}),
+            Err(err) => Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                editor.set_error(format!(
+                    "Failed to execute language server command: {}",
+                    err
+                ));
+            }),
+        };
+        Ok(call)
+    });
+}
+
+pub fn rename_symbol(cx: &mut Context) {
+    fn create_rename_prompt(prefill: String) -> Prompt {
+        Prompt::new(
+            "rename-to:".into(),
+            None,
+            |_input: &str| Vec::new(),
+            move |cx: &mut compositor::Context, input: &str, event: PromptEvent| {
+                if event != PromptEvent::Validate || input.is_empty() {
+                    return;
+                }
+
+                let (view, doc) = current!(cx.editor);
+                let language_server =
+                    match doc.language_server_with_feature(LanguageServerFeature::RenameSymbol) {
+                        Some(language_server) => language_server,
+                        None => return,
+                    };
+
+                let offset_encoding = language_server.offset_encoding();
+                let pos = pos_to_lsp_pos(
+                    doc.text(),
+                    doc.selection(view.id)
+                        .primary()
+                        .cursor(doc.text().slice(..)),
+                    offset_encoding,
+                );
+
+                let future =
+                    language_server.rename_symbol(doc.identifier(), pos, input.to_string());
+                cx.jobs.callback(make_job_callback(
+                    future,
+                    move |editor: &mut Editor,
+                          _compositor: &mut Compositor,
+                          response: Option<lsp::WorkspaceEdit>| {
+                        match response {
+                            Some(edit) => {
+                                if let Err(err) =
+                                    apply_workspace_edit(editor, offset_encoding, &edit)
+                                {
+                                    editor.set_error(err.to_string());
+                                }
+                            }
+                            None => editor.set_error("Symbol can't be renamed".to_string()),
+                        }
+                    },
+                ));
+            },
+        )
+        .with_line(prefill)
+    }
+
+    let (view, doc) = current!(cx.editor);
+
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::RenameSymbol) {
+            Some(language_server) => language_server,
+            None => return,
+        };
+
+    // the word under the cursor, used when the server can't tell us the symbol's range
+    let text = doc.text().slice(..);
+    let range = doc.selection(view.id).primary();
+    let word = textobject::textobject_word(text, range, textobject::TextObject::Inside, 1)
+        .fragment(text)
+        .to_string();
+
+    if !language_server.supports_prepare_rename() {
+        cx.push_layer(Box::new(create_rename_prompt(word)));
+        return;
     }
+
+    let offset_encoding = language_server.offset_encoding();
+    let pos = pos_to_lsp_pos(doc.text(), range.cursor(text), offset_encoding);
+    let future = language_server.prepare_rename(doc.identifier(), pos);
+    let (doc_id, view_id, version) = (doc.id(), view.id, doc.version());
+
+    cx.callback(
+        future,
+        move |editor: &mut Editor,
+              compositor: &mut Compositor,
+              response: Option<lsp::PrepareRenameResponse>| {
+            // the prompt renames the symbol under the cursor of the focused view, so it must still
+            // be the one the request was made for
+            let (view, doc) = current!(editor);
+            if (view.id, doc.id(), doc.version()) != (view_id, doc_id, version) {
+                return;
+            }
+
+            let prefill = match response {
+                Some(lsp::PrepareRenameResponse::Range(range)) => {
+                    let doc = doc_mut!(editor);
+                    match lsp_range_to_range(doc.text(), range, offset_encoding) {
+                        Some(range) => range.fragment(doc.text().slice(..)).to_string(),
+                        None => word,
+                    }
+                }
+                Some(lsp::PrepareRenameResponse::RangeWithPlaceholder { placeholder, .. }) => {
+                    placeholder
+                }
+                Some(lsp::PrepareRenameResponse::DefaultBehavior { .. }) => word,
+                None => {
+                    editor.set_error("Symbol can't be renamed".to_string());
+                    return;
+                }
+            };
+
+            compositor.push(Box::new(create_rename_prompt(prefill)));
+        },
+    );
 }
 
 fn last_picker(cx: &mut Context) {
     // TODO: last picker does not seem to work well with buffer_picker
-    cx.callback = Some(Box::new(|compositor: &mut Compositor| {
+    cx.callback = Some(Box::new(|compositor: &mut Compositor, _| {
         if let Some(picker) = compositor.last_picker.take() {
             compositor.push(picker);
         }
@@ -2821,6 +3810,7 @@ fn normal_mode(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
 
     doc.mode = Mode::Normal;
+    doc.active_snippet = None;
 
     doc.append_changes_to_history(view.id);
 
@@ -2978,9 +3968,7 @@ fn goto_impl(
                     let line = location.range.start.line;
                     format!("{}:{}", file, line).into()
                 },
-                move |editor: &mut Editor, location, action| {
-                    jump_to(editor, location, offset_encoding, action)
-                },
+                move |cx, location, action| jump_to(cx.editor, location, offset_encoding, action),
                 |_editor, location| {
                     let path = location.uri.to_file_path().unwrap();
                     let line = Some((
@@ -2997,10 +3985,11 @@ fn goto_impl(
 
 fn goto_definition(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
-    let language_server = match doc.language_server() {
-        Some(language_server) => language_server,
-        None => return,
-    };
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::GotoDefinition) {
+            Some(language_server) => language_server,
+            None => return,
+        };
 
     let offset_encoding = language_server.offset_encoding();
 
@@ -3039,10 +4028,11 @@ fn goto_definition(cx: &mut Context) {
 
 fn goto_type_definition(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
-    let language_server = match doc.language_server() {
-        Some(language_server) => language_server,
-        None => return,
-    };
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::GotoTypeDefinition) {
+            Some(language_server) => language_server,
+            None => return,
+        };
 
     let offset_encoding = language_server.offset_encoding();
 
@@ -3081,10 +4071,11 @@ fn goto_type_definition(cx: &mut Context) {
 
 fn goto_implementation(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
-    let language_server = match doc.language_server() {
-        Some(language_server) => language_server,
-        None => return,
-    };
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::GotoImplementation) {
+            Some(language_server) => language_server,
+            None => return,
+        };
 
     let offset_encoding = language_server.offset_encoding();
 
@@ -3123,10 +4114,11 @@ fn goto_implementation(cx: &mut Context) {
 
 fn goto_reference(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
-    let language_server = match doc.language_server() {
-        Some(language_server) => language_server,
-        None => return,
-    };
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::GotoReference) {
+            Some(language_server) => language_server,
+            None => return,
+        };
 
     let offset_encoding = language_server.offset_encoding();
 
@@ -3155,60 +4147,397 @@ fn goto_reference(cx: &mut Context) {
     );
 }
 
-fn goto_pos(editor: &mut Editor, pos: usize) {
-    push_jump(editor);
-
-    let (view, doc) = current!(editor);
-
-    doc.set_selection(view.id, Selection::point(pos));
-    align_view(doc, view, Align::Center);
+#[derive(Debug, Clone, PartialEq)]
+enum HierarchyItem {
+    Call(lsp::CallHierarchyItem),
+    Type(lsp::TypeHierarchyItem),
 }
 
-fn goto_first_diag(cx: &mut Context) {
-    let editor = &mut cx.editor;
-    let (_, doc) = current!(editor);
-
-    let diag = if let Some(diag) = doc.diagnostics().first() {
-        diag.range.start
-    } else {
-        return;
-    };
+impl HierarchyItem {
+    fn name(&self) -> &str {
+        match self {
+            Self::Call(item) => &item.name,
+            Self::Type(item) => &item.name,
+        }
+    }
 
-    goto_pos(editor, diag);
+    fn location(&self) -> lsp::Location {
+        match self {
+            Self::Call(item) => lsp::Location::new(item.uri.clone(), item.selection_range),
+            Self::Type(item) => lsp::Location::new(item.uri.clone(), item.selection_range),
+        }
+    }
 }
 
-fn goto_last_diag(cx: &mut Context) {
-    let editor = &mut cx.editor;
-    let (_, doc) = current!(editor);
-
-    let diag = if let Some(diag) = doc.diagnostics().last() {
-        diag.range.start
-    } else {
-        return;
-    };
-
-    goto_pos(editor, diag);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HierarchyDirection {
+    IncomingCalls,
+    OutgoingCalls,
+    Supertypes,
+    Subtypes,
+}
+
+/// A node of the call or type hierarchy picker.
+struct HierarchyEntry {
+    item: HierarchyItem,
+    /// Where to jump to: the call site for incoming calls, the item itself otherwise.
+    location: lsp::Location,
+    depth: usize,
+    /// Whether the children were requested already.
+    expanded: Cell<bool>,
+}
+
+/// Request the items related to `item` in `direction`, along with the location to jump to for
+/// each of them.
+fn hierarchy_children(
+    language_server: &helix_lsp::Client,
+    item: &HierarchyItem,
+    direction: HierarchyDirection,
+) -> BoxFuture<'static, helix_lsp::Result<Vec<(HierarchyItem, lsp::Location)>>> {
+    match (direction, item) {
+        (HierarchyDirection::IncomingCalls, HierarchyItem::Call(item)) => {
+            let future = language_server.call_hierarchy_incoming_calls(item.clone());
+            async move {
+                let calls: Option<Vec<lsp::CallHierarchyIncomingCall>> =
+                    serde_json::from_value(future.await?)?;
+                Ok(calls
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|call| {
+                        // jump to the call itself rather than to the caller
+                        let range = call
+                            .from_ranges
+                            .first()
+                            .copied()
+                            .unwrap_or(call.from.selection_range);
+                        let location = lsp::Location::new(call.from.uri.clone(), range);
+                        (HierarchyItem::Call(call.from), location)
+                    })
+                    .collect())
+            }
+            .boxed()
+        }
+        (HierarchyDirection::OutgoingCalls, HierarchyItem::Call(item)) => {
+            let future = language_server.call_hierarchy_outgoing_calls(item.clone());
+            async move {
+                let calls: Option<Vec<lsp::CallHierarchyOutgoingCall>> =
+                    serde_json::from_value(future.await?)?;
+                Ok(calls
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|call| {
+                        let item = HierarchyItem::Call(call.to);
+                        (item.clone(), item.location())
+                    })
+                    .collect())
+            }
+            .boxed()
+        }
+        (HierarchyDirection::Supertypes, HierarchyItem::Type(item))
+        | (HierarchyDirection::Subtypes, HierarchyItem::Type(item)) => {
+            let future = if direction == HierarchyDirection::Supertypes {
+                language_server
+                    .type_hierarchy_supertypes(item.clone())
+                    .boxed()
+            } else {
+                language_server
+                    .type_hierarchy_subtypes(item.clone())
+                    .boxed()
+            };
+            async move {
+                let types: Option<Vec<lsp::TypeHierarchyItem>> =
+                    serde_json::from_value(future.await?)?;
+                Ok(types
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|item| {
+                        let item = HierarchyItem::Type(item);
+                        (item.clone(), item.location())
+                    })
+                    .collect())
+            }
+            .boxed()
+        }
+        _ => future::ready(Ok(Vec::new())).boxed(),
+    }
 }
 
-fn goto_next_diag(cx: &mut Context) {
-    let editor = &mut cx.editor;
-    let (view, doc) = current!(editor);
+fn hierarchy_picker(
+    entries: Vec<HierarchyEntry>,
+    direction: HierarchyDirection,
+    language_server_id: usize,
+    offset_encoding: OffsetEncoding,
+) -> FilePicker<HierarchyEntry> {
+    let cwdir = std::env::current_dir().expect("couldn't determine current directory");
 
-    let cursor_pos = doc
-        .selection(view.id)
-        .primary()
-        .cursor(doc.text().slice(..));
-    let diag = if let Some(diag) = doc
-        .diagnostics()
-        .iter()
-        .map(|diag| diag.range.start)
-        .find(|&pos| pos > cursor_pos)
-    {
-        diag
-    } else if let Some(diag) = doc.diagnostics().first() {
-        diag.range.start
-    } else {
-        return;
+    FilePicker::new(
+        entries,
+        move |entry| {
+            let marker = if entry.expanded.get() { "-" } else { "+" };
+            let path = entry
+                .location
+                .uri
+                .to_file_path()
+                .map(|path| {
+                    path.strip_prefix(&cwdir)
+                        .map(|path| path.to_path_buf())
+                        .unwrap_or(path)
+                })
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| entry.location.uri.to_string());
+            format!(
+                "{}{} {}  {}:{}",
+                "  ".repeat(entry.depth),
+                marker,
+                entry.item.name(),
+                path,
+                entry.location.range.start.line + 1
+            )
+            .into()
+        },
+        move |cx, entry, action| {
+            let path = match entry.location.uri.to_file_path() {
+                Ok(path) => path,
+                Err(_) => return,
+            };
+            push_jump(cx.editor);
+            if let Err(err) = cx.editor.open(path, action) {
+                cx.editor.set_error(format!("failed to open file: {}", err));
+                return;
+            }
+            let (view, doc) = current!(cx.editor);
+            if let Some(range) =
+                lsp_range_to_range(doc.text(), entry.location.range, offset_encoding)
+            {
+                doc.set_selection(view.id, Selection::single(range.anchor, range.head));
+                align_view(doc, view, Align::Center);
+            }
+        },
+        |_editor, entry| {
+            let path = entry.location.uri.to_file_path().ok()?;
+            let line = Some((
+                entry.location.range.start.line as usize,
+                entry.location.range.end.line as usize,
+            ));
+            Some((path, line))
+        },
+    )
+    .with_expand_fn(move |cx, entry, index| {
+        if entry.expanded.replace(true) {
+            return;
+        }
+        let language_server = match cx.editor.language_servers.get_by_id(language_server_id) {
+            Some(language_server) => language_server,
+            None => return,
+        };
+        let future = hierarchy_children(language_server, &entry.item, direction);
+        let parent = entry.item.clone();
+        let depth = entry.depth + 1;
+
+        cx.jobs.callback(async move {
+            let children = future.await;
+            let call: job::Callback = Box::new(move |editor, compositor: &mut Compositor| {
+                let picker = compositor
+                    .find(std::any::type_name::<FilePicker<HierarchyEntry>>())
+                    .and_then(|picker| picker.as_any_mut().downcast_mut());
+                let picker: &mut FilePicker<HierarchyEntry> = match picker {
+                    Some(picker) => picker,
+                    None => return,
+                };
+                // the parent moves if the children of an entry above it were inserted meanwhile
+                let index = match picker.options().get(index) {
+                    Some(entry) if entry.item == parent => index,
+                    _ => match picker
+                        .options()
+                        .iter()
+                        .position(|entry| entry.item == parent && entry.expanded.get())
+                    {
+                        Some(index) => index,
+                        None => return,
+                    },
+                };
+                let children = match children {
+                    Ok(children) => children,
+                    Err(err) => {
+                        // let the entry be expanded again
+                        picker.options()[index].expanded.set(false);
+                        editor.set_error(format!("Failed to expand {}: {}", parent.name(), err));
+                        return;
+                    }
+                };
+                let children = children
+                    .into_iter()
+                    .map(|(item, location)| HierarchyEntry {
+                        item,
+                        location,
+                        depth,
+                        expanded: Cell::new(false),
+                    })
+                    .collect();
+                picker.insert_options(index, children);
+            });
+            Ok(call)
+        });
+    })
+}
+
+fn hierarchy(cx: &mut Context, direction: HierarchyDirection) {
+    let feature = match direction {
+        HierarchyDirection::IncomingCalls | HierarchyDirection::OutgoingCalls => {
+            LanguageServerFeature::CallHierarchy
+        }
+        HierarchyDirection::Supertypes | HierarchyDirection::Subtypes => {
+            LanguageServerFeature::TypeHierarchy
+        }
+    };
+
+    let (view, doc) = current!(cx.editor);
+    let language_server = match doc.language_server_with_feature(feature) {
+        Some(language_server) => language_server,
+        None => return,
+    };
+    let language_server_id = language_server.id();
+    let offset_encoding = language_server.offset_encoding();
+    let pos = pos_to_lsp_pos(
+        doc.text(),
+        doc.selection(view.id)
+            .primary()
+            .cursor(doc.text().slice(..)),
+        offset_encoding,
+    );
+    let prepare = if feature == LanguageServerFeature::CallHierarchy {
+        language_server
+            .prepare_call_hierarchy(doc.identifier(), pos, None)
+            .boxed()
+    } else {
+        language_server
+            .prepare_type_hierarchy(doc.identifier(), pos, None)
+            .boxed()
+    };
+    let language_server = match cx.editor.language_servers.get_by_id(language_server_id) {
+        Some(language_server) => language_server.clone(),
+        None => return,
+    };
+
+    // resolve the item under the cursor and load its first level right away
+    let callback = async move {
+        let response = prepare.await?;
+        let root = if feature == LanguageServerFeature::CallHierarchy {
+            let items: Option<Vec<lsp::CallHierarchyItem>> = serde_json::from_value(response)?;
+            items
+                .and_then(|items| items.into_iter().next())
+                .map(HierarchyItem::Call)
+        } else {
+            let items: Option<Vec<lsp::TypeHierarchyItem>> = serde_json::from_value(response)?;
+            items
+                .and_then(|items| items.into_iter().next())
+                .map(HierarchyItem::Type)
+        };
+        let children = match &root {
+            Some(root) => hierarchy_children(&language_server, root, direction).await?,
+            None => Vec::new(),
+        };
+
+        let call: job::Callback =
+            Box::new(move |editor: &mut Editor, compositor: &mut Compositor| {
+                let root = match root {
+                    Some(root) => root,
+                    None => {
+                        editor.set_error("No symbol found under the cursor.".to_string());
+                        return;
+                    }
+                };
+                let mut entries = vec![HierarchyEntry {
+                    location: root.location(),
+                    item: root,
+                    depth: 0,
+                    expanded: Cell::new(true),
+                }];
+                entries.extend(children.into_iter().map(|(item, location)| HierarchyEntry {
+                    item,
+                    location,
+                    depth: 1,
+                    expanded: Cell::new(false),
+                }));
+                let picker =
+                    hierarchy_picker(entries, direction, language_server_id, offset_encoding);
+                compositor.push(Box::new(picker));
+            });
+        Ok(call)
+    };
+    cx.jobs.callback(callback);
+}
+
+fn incoming_calls(cx: &mut Context) {
+    hierarchy(cx, HierarchyDirection::IncomingCalls)
+}
+
+fn outgoing_calls(cx: &mut Context) {
+    hierarchy(cx, HierarchyDirection::OutgoingCalls)
+}
+
+fn supertypes(cx: &mut Context) {
+    hierarchy(cx, HierarchyDirection::Supertypes)
+}
+
+fn subtypes(cx: &mut Context) {
+    hierarchy(cx, HierarchyDirection::Subtypes)
+}
+
+fn goto_pos(editor: &mut Editor, pos: usize) {
+    push_jump(editor);
+
+    let (view, doc) = current!(editor);
+
+    doc.set_selection(view.id, Selection::point(pos));
+    align_view(doc, view, Align::Center);
+}
+
+fn goto_first_diag(cx: &mut Context) {
+    let editor = &mut cx.editor;
+    let (_, doc) = current!(editor);
+
+    let diag = if let Some(diag) = doc.diagnostics().first() {
+        diag.range.start
+    } else {
+        return;
+    };
+
+    goto_pos(editor, diag);
+}
+
+fn goto_last_diag(cx: &mut Context) {
+    let editor = &mut cx.editor;
+    let (_, doc) = current!(editor);
+
+    let diag = if let Some(diag) = doc.diagnostics().last() {
+        diag.range.start
+    } else {
+        return;
+    };
+
+    goto_pos(editor, diag);
+}
+
+fn goto_next_diag(cx: &mut Context) {
+    let editor = &mut cx.editor;
+    let (view, doc) = current!(editor);
+
+    let cursor_pos = doc
+        .selection(view.id)
+        .primary()
+        .cursor(doc.text().slice(..));
+    let diag = if let Some(diag) = doc
+        .diagnostics()
+        .iter()
+        .map(|diag| diag.range.start)
+        .find(|&pos| pos > cursor_pos)
+    {
+        diag
+    } else if let Some(diag) = doc.diagnostics().first() {
+        diag.range.start
+    } else {
+        return;
     };
 
     goto_pos(editor, diag);
@@ -3239,13 +4568,839 @@ fn goto_prev_diag(cx: &mut Context) {
     goto_pos(editor, diag);
 }
 
-fn signature_help(cx: &mut Context) {
+fn goto_treesitter_object(cx: &mut Context, object: &str, direction: Direction) {
+    let count = cx.count();
     let (view, doc) = current!(cx.editor);
 
-    let language_server = match doc.language_server() {
+    let (lang_config, syntax) = match doc.language_config().zip(doc.syntax()) {
+        Some(t) => t,
+        None => return,
+    };
+    let behaviour = if doc.mode == Mode::Select {
+        Movement::Extend
+    } else {
+        Movement::Move
+    };
+    let text = doc.text().slice(..);
+    let root = syntax.tree().root_node();
+
+    let selection = doc.selection(view.id).clone().transform(|range| {
+        movement::goto_treesitter_object(
+            text,
+            range,
+            object,
+            direction,
+            root,
+            lang_config,
+            count,
+            behaviour,
+        )
+    });
+
+    push_jump(cx.editor);
+    let (view, doc) = current!(cx.editor);
+    doc.set_selection(view.id, selection);
+}
+
+fn goto_next_function(cx: &mut Context) {
+    goto_treesitter_object(cx, "function", Direction::Forward)
+}
+
+fn goto_prev_function(cx: &mut Context) {
+    goto_treesitter_object(cx, "function", Direction::Backward)
+}
+
+fn goto_next_class(cx: &mut Context) {
+    goto_treesitter_object(cx, "class", Direction::Forward)
+}
+
+fn goto_prev_class(cx: &mut Context) {
+    goto_treesitter_object(cx, "class", Direction::Backward)
+}
+
+/// Request inlay hints for every view whose hints are outdated, either because the document
+/// changed or because the view scrolled past the lines they cover.
+pub fn compute_inlay_hints_for_all_views(editor: &mut Editor, jobs: &mut Jobs) {
+    if !editor.config.inlay_hints {
+        return;
+    }
+
+    for (view, _) in editor.tree.views() {
+        if let Some(doc) = editor.documents.get_mut(view.doc) {
+            compute_inlay_hints_for_view(view, doc, jobs);
+        }
+    }
+}
+
+fn compute_inlay_hints_for_view(view: &View, doc: &mut Document, jobs: &mut Jobs) {
+    use helix_view::document::{DocumentInlayHints, InlayHint, InlayHintKind};
+
+    let language_server = match doc.language_server_with_feature(LanguageServerFeature::InlayHints)
+    {
         Some(language_server) => language_server,
         None => return,
     };
+    let offset_encoding = language_server.offset_encoding();
+
+    let first_visible = view.offset.row;
+    let last_visible = view.last_line(doc);
+    let version = doc.version();
+    let previous = doc.inlay_hints(view.id);
+    if let Some(previous) = previous {
+        if previous.version == version
+            && previous.first_line <= first_visible
+            && last_visible <= previous.last_line
+        {
+            return;
+        }
+    }
+
+    // also cover a page above and below so that scrolling a little doesn't need a new request
+    let text = doc.text();
+    let height = last_visible - first_visible + 1;
+    let first_line = first_visible.saturating_sub(height);
+    let last_line = (last_visible + height).min(text.len_lines().saturating_sub(1));
+    let range = lsp::Range::new(
+        pos_to_lsp_pos(text, text.line_to_char(first_line), offset_encoding),
+        pos_to_lsp_pos(text, text.line_to_char(last_line + 1), offset_encoding),
+    );
+    let future = language_server.text_document_inlay_hints(doc.identifier(), range, None);
+
+    // keep showing the old hints until the new ones arrive, but remember what was requested so
+    // the request isn't repeated meanwhile
+    let hints = previous.map(|previous| previous.hints.clone());
+    doc.set_inlay_hints(
+        view.id,
+        DocumentInlayHints {
+            version,
+            first_line,
+            last_line,
+            hints: hints.unwrap_or_default(),
+        },
+    );
+
+    let doc_id = doc.id();
+    let view_id = view.id;
+    jobs.callback(make_job_callback(
+        future,
+        move |editor: &mut Editor,
+              _compositor: &mut Compositor,
+              response: Option<Vec<lsp::InlayHint>>| {
+            let doc = match editor.documents.get_mut(doc_id) {
+                Some(doc) => doc,
+                None => return,
+            };
+            // drop responses that were superseded by a newer request
+            match doc.inlay_hints(view_id) {
+                Some(current)
+                    if current.version == version
+                        && current.first_line == first_line
+                        && current.last_line == last_line
+                        && doc.version() == version => {}
+                _ => return,
+            }
+
+            let text = doc.text();
+            let mut hints: Vec<_> = response
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|hint| {
+                    let char_idx = lsp_pos_to_pos(text, hint.position, offset_encoding)?;
+                    let text = match hint.label {
+                        lsp::InlayHintLabel::String(label) => label,
+                        lsp::InlayHintLabel::LabelParts(parts) => {
+                            parts.into_iter().map(|part| part.value).collect()
+                        }
+                    };
+                    let kind = match hint.kind {
+                        Some(lsp::InlayHintKind::TYPE) => InlayHintKind::Type,
+                        Some(lsp::InlayHintKind::PARAMETER) => InlayHintKind::Parameter,
+                        _ => InlayHintKind::Other,
+                    };
+                    Some(InlayHint {
+                        char_idx,
+                        text,
+                        kind,
+                        padding_left: hint.padding_left.unwrap_or(false),
+                        padding_right: hint.padding_right.unwrap_or(false),
+                    })
+                })
+                .collect();
+            hints.sort_by_key(|hint| hint.char_idx);
+
+            doc.set_inlay_hints(
+                view_id,
+                DocumentInlayHints {
+                    version,
+                    first_line,
+                    last_line,
+                    hints,
+                },
+            );
+        },
+    ));
+}
+
+fn document_highlights_from_lsp(
+    text: &Rope,
+    highlights: Vec<lsp::DocumentHighlight>,
+    offset_encoding: OffsetEncoding,
+) -> Vec<helix_view::document::DocumentHighlight> {
+    use helix_view::document::{DocumentHighlight, DocumentHighlightKind};
+
+    let mut highlights: Vec<_> = highlights
+        .into_iter()
+        .filter_map(|highlight| {
+            let range = lsp_range_to_range(text, highlight.range, offset_encoding)?;
+            let kind = match highlight.kind {
+                Some(lsp::DocumentHighlightKind::READ) => DocumentHighlightKind::Read,
+                Some(lsp::DocumentHighlightKind::WRITE) => DocumentHighlightKind::Write,
+                _ => DocumentHighlightKind::Text,
+            };
+            Some(DocumentHighlight {
+                start: range.from(),
+                end: range.to(),
+                kind,
+            })
+        })
+        .collect();
+    highlights.sort_by_key(|highlight| highlight.start);
+    highlights
+}
+
+/// Request the references to the symbol under the primary cursor of the focused view so that
+/// they can be highlighted.
+pub fn compute_document_highlights(editor: &mut Editor, jobs: &mut Jobs) {
+    let (view, doc) = current!(editor);
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::DocumentHighlight) {
+            Some(language_server) => language_server,
+            None => return,
+        };
+    let offset_encoding = language_server.offset_encoding();
+
+    let pos = doc
+        .selection(view.id)
+        .primary()
+        .cursor(doc.text().slice(..));
+    let pos = pos_to_lsp_pos(doc.text(), pos, offset_encoding);
+    let future = language_server.text_document_document_highlight(doc.identifier(), pos, None);
+
+    let doc_id = doc.id();
+    let view_id = view.id;
+    let version = doc.version();
+    jobs.callback(make_job_callback(
+        future,
+        move |editor: &mut Editor,
+              _compositor: &mut Compositor,
+              response: Option<Vec<lsp::DocumentHighlight>>| {
+            let doc = match editor.documents.get_mut(doc_id) {
+                Some(doc) if doc.version() == version => doc,
+                _ => return,
+            };
+            let highlights = document_highlights_from_lsp(
+                doc.text(),
+                response.unwrap_or_default(),
+                offset_encoding,
+            );
+            doc.set_document_highlights(view_id, highlights);
+        },
+    ));
+}
+
+/// Request semantic tokens for every visible document whose tokens are out of date. Servers that
+/// support it are asked for the changes since their previous result instead of all tokens.
+pub fn compute_semantic_tokens(editor: &mut Editor, jobs: &mut Jobs) {
+    use helix_view::document::{DocumentSemanticTokens, SemanticToken};
+
+    let mut doc_ids = Vec::new();
+    for (view, _) in editor.tree.views() {
+        if !doc_ids.contains(&view.doc) {
+            doc_ids.push(view.doc);
+        }
+    }
+
+    for doc_id in doc_ids {
+        let doc = match editor.documents.get_mut(doc_id) {
+            Some(doc) => doc,
+            None => continue,
+        };
+        let language_server =
+            match doc.language_server_with_feature(LanguageServerFeature::SemanticTokens) {
+                Some(language_server) => language_server,
+                None => continue,
+            };
+        let language_server_id = language_server.id();
+        let offset_encoding = language_server.offset_encoding();
+        let version = doc.version();
+
+        let previous = doc
+            .semantic_tokens()
+            .filter(|previous| previous.language_server_id == language_server_id);
+        if matches!(previous, Some(previous) if previous.version == version) {
+            continue;
+        }
+        let has_previous = previous.is_some();
+        let previous_result_id = previous
+            .and_then(|previous| previous.result_id.clone())
+            .filter(|_| language_server.supports_semantic_tokens_delta());
+
+        let future = match previous_result_id {
+            Some(previous_result_id) => language_server
+                .text_document_semantic_tokens_full_delta(
+                    doc.identifier(),
+                    previous_result_id,
+                    None,
+                )
+                .boxed(),
+            None => language_server
+                .text_document_semantic_tokens_full(doc.identifier(), None)
+                .boxed(),
+        };
+
+        // mark the request as pending so it isn't sent again before the response arrives
+        match doc.semantic_tokens_mut() {
+            Some(semantic_tokens) if has_previous => semantic_tokens.version = version,
+            _ => doc.set_semantic_tokens(Some(DocumentSemanticTokens {
+                language_server_id,
+                version,
+                ..Default::default()
+            })),
+        }
+
+        jobs.callback(make_job_callback(
+            future,
+            move |editor: &mut Editor,
+                  _compositor: &mut Compositor,
+                  response: Option<lsp::SemanticTokensFullDeltaResult>| {
+                let doc = match editor.documents.get_mut(doc_id) {
+                    Some(doc) => doc,
+                    None => return,
+                };
+                let text = doc.text().clone();
+                let doc_version = doc.version();
+                let semantic_tokens = match doc.semantic_tokens_mut() {
+                    Some(semantic_tokens)
+                        if semantic_tokens.language_server_id == language_server_id
+                            && semantic_tokens.version == version =>
+                    {
+                        semantic_tokens
+                    }
+                    // a newer request is pending
+                    _ => return,
+                };
+
+                match response {
+                    Some(lsp::SemanticTokensFullDeltaResult::Tokens(tokens)) => {
+                        semantic_tokens.result_id = tokens.result_id;
+                        semantic_tokens.data = tokens.data;
+                    }
+                    Some(lsp::SemanticTokensFullDeltaResult::TokensDelta(delta)) => {
+                        semantic_tokens.result_id = delta.result_id;
+                        if !apply_semantic_tokens_edits(&mut semantic_tokens.data, delta.edits) {
+                            // request all tokens next time instead of building on broken ones
+                            semantic_tokens.result_id = None;
+                        }
+                    }
+                    Some(lsp::SemanticTokensFullDeltaResult::PartialTokensDelta { edits }) => {
+                        if !apply_semantic_tokens_edits(&mut semantic_tokens.data, edits) {
+                            semantic_tokens.result_id = None;
+                        }
+                    }
+                    None => {
+                        semantic_tokens.result_id = None;
+                        semantic_tokens.data.clear();
+                    }
+                }
+
+                // the result describes an older text, keep mapping the current tokens until the
+                // next request returns
+                if version != doc_version {
+                    return;
+                }
+                semantic_tokens.tokens =
+                    decode_semantic_tokens(&text, &semantic_tokens.data, offset_encoding)
+                        .into_iter()
+                        .map(|(start, end, token_type, token_modifiers)| SemanticToken {
+                            start,
+                            end,
+                            token_type,
+                            token_modifiers,
+                        })
+                        .collect();
+            },
+        ));
+    }
+}
+
+/// Pull diagnostics with `textDocument/diagnostic` for every open document that changed since
+/// they were last pulled, from each server that supports it. Workspace diagnostics are pulled
+/// again from those servers as well, since the edits may affect other files.
+pub fn pull_diagnostics(editor: &mut Editor, jobs: &mut Jobs) {
+    let mut server_ids = Vec::new();
+    for doc in editor.documents.values_mut() {
+        let url = match doc.url() {
+            Some(url) => url,
+            None => continue,
+        };
+        let version = doc.version();
+
+        let mut requests = Vec::new();
+        for language_server in doc.language_servers() {
+            let server_id = language_server.id();
+            if !language_server.supports_pull_diagnostics()
+                || doc.diagnostics_pulled_version(server_id) == Some(version)
+            {
+                continue;
+            }
+            let previous_result_id = editor
+                .diagnostic_result_ids
+                .get(&(server_id, url.clone()))
+                .cloned();
+            let future =
+                language_server.text_document_diagnostic(doc.identifier(), previous_result_id);
+            requests.push((server_id, language_server.offset_encoding(), future));
+        }
+
+        let doc_id = doc.id();
+        for (server_id, offset_encoding, future) in requests {
+            doc.set_diagnostics_pulled_version(server_id, version);
+            if !server_ids.contains(&server_id) {
+                server_ids.push(server_id);
+            }
+
+            let url = url.clone();
+            jobs.callback(async move {
+                let report: lsp::DocumentDiagnosticReportResult = match future
+                    .await
+                    .and_then(|json| Ok(serde_json::from_value(json)?))
+                {
+                    Ok(report) => report,
+                    Err(err) => {
+                        log::warn!("failed to pull diagnostics: {}", err);
+                        // pull them again at the next idle timeout
+                        let call: job::Callback =
+                            Box::new(move |editor: &mut Editor, _: &mut Compositor| {
+                                if let Some(doc) = editor.documents.get_mut(doc_id) {
+                                    if doc.diagnostics_pulled_version(server_id) == Some(version) {
+                                        doc.clear_diagnostics_pulled_version(server_id);
+                                    }
+                                }
+                            });
+                        return Ok(call);
+                    }
+                };
+                let call: job::Callback =
+                    Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                        match editor.documents.get(doc_id) {
+                            Some(doc) if doc.version() == version => (),
+                            // the diagnostics describe an older text, they're pulled again
+                            _ => return,
+                        }
+
+                        let (report, related_documents) = match report {
+                            lsp::DocumentDiagnosticReportResult::Report(
+                                lsp::DocumentDiagnosticReport::Full(report),
+                            ) => (
+                                Some(lsp::DocumentDiagnosticReportKind::Full(
+                                    report.full_document_diagnostic_report,
+                                )),
+                                report.related_documents,
+                            ),
+                            lsp::DocumentDiagnosticReportResult::Report(
+                                lsp::DocumentDiagnosticReport::Unchanged(report),
+                            ) => (
+                                Some(lsp::DocumentDiagnosticReportKind::Unchanged(
+                                    report.unchanged_document_diagnostic_report,
+                                )),
+                                report.related_documents,
+                            ),
+                            lsp::DocumentDiagnosticReportResult::Partial(partial) => {
+                                (None, partial.related_documents)
+                            }
+                        };
+
+                        if let Some(report) = report {
+                            apply_diagnostic_report(
+                                editor,
+                                server_id,
+                                offset_encoding,
+                                url,
+                                report,
+                            );
+                        }
+                        for (url, report) in related_documents.unwrap_or_default() {
+                            apply_diagnostic_report(
+                                editor,
+                                server_id,
+                                offset_encoding,
+                                url,
+                                report,
+                            );
+                        }
+                    });
+                Ok(call)
+            });
+        }
+    }
+
+    for server_id in server_ids {
+        pull_workspace_diagnostics(editor, jobs, server_id);
+    }
+}
+
+/// Pull the diagnostics of the whole workspace with `workspace/diagnostic`, if the server
+/// supports it.
+pub fn pull_workspace_diagnostics(editor: &mut Editor, jobs: &mut Jobs, server_id: usize) {
+    let language_server = match editor.language_servers.get_by_id(server_id) {
+        Some(language_server) if language_server.supports_workspace_diagnostics() => {
+            language_server
+        }
+        _ => return,
+    };
+    let offset_encoding = language_server.offset_encoding();
+
+    let previous_result_ids = editor
+        .diagnostic_result_ids
+        .iter()
+        .filter(|((id, _), _)| *id == server_id)
+        .map(|((_, uri), value)| lsp::PreviousResultId {
+            uri: uri.clone(),
+            value: value.clone(),
+        })
+        .collect();
+    let future = language_server.workspace_diagnostic(previous_result_ids);
+
+    jobs.callback(async move {
+        let report: lsp::WorkspaceDiagnosticReportResult = match future
+            .await
+            .and_then(|json| Ok(serde_json::from_value(json)?))
+        {
+            Ok(report) => report,
+            Err(err) => {
+                log::warn!("failed to pull workspace diagnostics: {}", err);
+                return Ok(Box::new(|_: &mut Editor, _: &mut Compositor| {}) as job::Callback);
+            }
+        };
+        let call: job::Callback =
+            Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                let items = match report {
+                    lsp::WorkspaceDiagnosticReportResult::Report(report) => report.items,
+                    lsp::WorkspaceDiagnosticReportResult::Partial(partial) => partial.items,
+                };
+                for item in items {
+                    let (url, version, report) = match item {
+                        lsp::WorkspaceDocumentDiagnosticReport::Full(item) => (
+                            item.uri,
+                            item.version,
+                            lsp::DocumentDiagnosticReportKind::Full(
+                                item.full_document_diagnostic_report,
+                            ),
+                        ),
+                        lsp::WorkspaceDocumentDiagnosticReport::Unchanged(item) => (
+                            item.uri,
+                            item.version,
+                            lsp::DocumentDiagnosticReportKind::Unchanged(
+                                item.unchanged_document_diagnostic_report,
+                            ),
+                        ),
+                    };
+
+                    // skip reports for other versions of open documents
+                    let doc = url
+                        .to_file_path()
+                        .ok()
+                        .and_then(|path| editor.document_by_path(&path));
+                    if let (Some(doc), Some(version)) = (doc, version) {
+                        if doc.version() as i64 != version {
+                            continue;
+                        }
+                    }
+
+                    apply_diagnostic_report(editor, server_id, offset_encoding, url, report);
+                }
+            });
+        Ok(call)
+    });
+}
+
+/// Store the result id of a pulled diagnostic report and apply its diagnostics if they changed.
+fn apply_diagnostic_report(
+    editor: &mut Editor,
+    server_id: usize,
+    offset_encoding: OffsetEncoding,
+    url: lsp::Url,
+    report: lsp::DocumentDiagnosticReportKind,
+) {
+    match report {
+        lsp::DocumentDiagnosticReportKind::Full(report) => {
+            match report.result_id {
+                Some(result_id) => {
+                    editor
+                        .diagnostic_result_ids
+                        .insert((server_id, url.clone()), result_id);
+                }
+                None => {
+                    editor
+                        .diagnostic_result_ids
+                        .remove(&(server_id, url.clone()));
+                }
+            }
+            editor.set_lsp_diagnostics(server_id, offset_encoding, url, report.items);
+        }
+        lsp::DocumentDiagnosticReportKind::Unchanged(report) => {
+            editor
+                .diagnostic_result_ids
+                .insert((server_id, url), report.result_id);
+        }
+    }
+}
+
+/// Request code lenses for every visible document whose lenses are out of date, and resolve the
+/// commands of those that come without one.
+pub fn compute_code_lenses(editor: &mut Editor, jobs: &mut Jobs) {
+    use helix_view::document::{CodeLens, DocumentCodeLenses};
+
+    let mut doc_ids = Vec::new();
+    for (view, _) in editor.tree.views() {
+        if !doc_ids.contains(&view.doc) {
+            doc_ids.push(view.doc);
+        }
+    }
+
+    for doc_id in doc_ids {
+        let doc = match editor.documents.get_mut(doc_id) {
+            Some(doc) => doc,
+            None => continue,
+        };
+        let language_server_id =
+            match doc.language_server_with_feature(LanguageServerFeature::CodeLens) {
+                Some(language_server) => language_server.id(),
+                None => continue,
+            };
+        let language_server = match editor.language_servers.get_by_id(language_server_id) {
+            Some(language_server) => language_server.clone(),
+            None => continue,
+        };
+        let offset_encoding = language_server.offset_encoding();
+        let version = doc.version();
+
+        match doc.code_lenses_mut() {
+            Some(code_lenses) if code_lenses.language_server_id == language_server_id => {
+                if code_lenses.version == version {
+                    continue;
+                }
+                // mark the request as pending so it isn't sent again before the response arrives
+                code_lenses.version = version;
+            }
+            _ => doc.set_code_lenses(Some(DocumentCodeLenses {
+                language_server_id,
+                version,
+                ..Default::default()
+            })),
+        }
+
+        let future = language_server.text_document_code_lens(doc.identifier(), None);
+        jobs.callback(async move {
+            let response = future.await?;
+            let lenses: Option<Vec<lsp::CodeLens>> = serde_json::from_value(response)?;
+
+            let lenses = lenses.unwrap_or_default().into_iter().map(|lens| {
+                let resolve = match lens.command {
+                    Some(_) => None,
+                    None => language_server.code_lens_resolve(lens.clone()),
+                };
+                async move {
+                    let resolve = match resolve {
+                        Some(resolve) => resolve,
+                        None => return lens,
+                    };
+                    match resolve
+                        .await
+                        .and_then(|response| Ok(serde_json::from_value(response)?))
+                    {
+                        Ok(resolved) => resolved,
+                        Err(err) => {
+                            log::warn!("failed to resolve code lens: {}", err);
+                            lens
+                        }
+                    }
+                }
+            });
+            let lenses: Vec<lsp::CodeLens> = future::join_all(lenses).await;
+
+            let call: job::Callback =
+                Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                    let doc = match editor.documents.get_mut(doc_id) {
+                        Some(doc) if doc.version() == version => doc,
+                        // the lenses describe an older text, keep the current ones that were
+                        // mapped over the edits until the next idle timeout requests them again
+                        _ => return,
+                    };
+                    let text = doc.text().clone();
+                    let code_lenses = match doc.code_lenses_mut() {
+                        Some(code_lenses)
+                            if code_lenses.language_server_id == language_server_id
+                                && code_lenses.version == version =>
+                        {
+                            code_lenses
+                        }
+                        _ => return,
+                    };
+
+                    let mut lenses: Vec<_> = lenses
+                        .into_iter()
+                        .filter_map(|lens| {
+                            let char_idx =
+                                lsp_pos_to_pos(&text, lens.range.start, offset_encoding)?;
+                            Some(CodeLens { char_idx, lens })
+                        })
+                        .collect();
+                    lenses.sort_by_key(|lens| lens.char_idx);
+                    code_lenses.lenses = lenses;
+                });
+            Ok(call)
+        });
+    }
+}
+
+/// Pick one of the code lenses on the line of the primary cursor and run its command.
+fn code_lens(cx: &mut Context) {
+    let (view, doc) = current!(cx.editor);
+    let language_server_id = match doc.code_lenses() {
+        Some(code_lenses) => code_lenses.language_server_id,
+        None => {
+            cx.editor.set_status("No code lenses available".to_string());
+            return;
+        }
+    };
+
+    let text = doc.text().slice(..);
+    let line = doc.selection(view.id).primary().cursor_line(text);
+    let commands: Vec<lsp::Command> = doc
+        .code_lenses_on_line(line)
+        .iter()
+        .filter_map(|lens| lens.lens.command.clone())
+        .collect();
+    if commands.is_empty() {
+        cx.editor
+            .set_status("No code lenses on this line".to_string());
+        return;
+    }
+
+    let picker = Picker::new(
+        true,
+        commands,
+        |command| command.title.as_str().into(),
+        move |cx, command, _action| {
+            run_code_lens_command(cx, language_server_id, command.clone());
+        },
+    );
+    cx.push_layer(Box::new(picker));
+}
+
+/// Run the command of a code lens in a job. The server can report progress on it, which shows up
+/// in the status line.
+fn run_code_lens_command(
+    cx: &mut compositor::Context,
+    language_server_id: usize,
+    command: lsp::Command,
+) {
+    let language_server = match cx.editor.language_servers.get_by_id(language_server_id) {
+        Some(language_server) => language_server,
+        None => return,
+    };
+
+    // tokens must not be reused while their progress is active, and titles aren't unique
+    static WORK_DONE_TOKENS: std::sync::atomic::AtomicUsize =
+        std::sync::atomic::AtomicUsize::new(0);
+    let token = WORK_DONE_TOKENS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let work_done_token = lsp::ProgressToken::String(format!("helix-code-lens-{}", token));
+
+    let title = command.title.clone();
+    let future = match language_server.command(command, Some(work_done_token)) {
+        Some(future) => future,
+        None => {
+            cx.editor
+                .set_error("Language server does not support executing commands".to_string());
+            return;
+        }
+    };
+
+    cx.jobs.callback(async move {
+        future.await?;
+        let call: job::Callback =
+            Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                editor.set_status(format!("Finished '{}'", title));
+            });
+        Ok(call)
+    });
+}
+
+/// Select every reference to the symbol under the primary cursor.
+fn select_references_to_symbol_under_cursor(cx: &mut Context) {
+    let (view, doc) = current!(cx.editor);
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::DocumentHighlight) {
+            Some(language_server) => language_server,
+            None => return,
+        };
+    let offset_encoding = language_server.offset_encoding();
+
+    let cursor = doc
+        .selection(view.id)
+        .primary()
+        .cursor(doc.text().slice(..));
+    let pos = pos_to_lsp_pos(doc.text(), cursor, offset_encoding);
+    let future = language_server.text_document_document_highlight(doc.identifier(), pos, None);
+    let (doc_id, view_id, version) = (doc.id(), view.id, doc.version());
+
+    cx.callback(
+        future,
+        move |editor: &mut Editor,
+              _compositor: &mut Compositor,
+              response: Option<Vec<lsp::DocumentHighlight>>| {
+            // drop the response if the document changed or the view no longer shows it
+            let doc = match editor.documents.get_mut(doc_id) {
+                Some(doc)
+                    if doc.version() == version && doc.selections().contains_key(&view_id) =>
+                {
+                    doc
+                }
+                _ => return,
+            };
+            let highlights = document_highlights_from_lsp(
+                doc.text(),
+                response.unwrap_or_default(),
+                offset_encoding,
+            );
+            if highlights.is_empty() {
+                return;
+            }
+
+            // keep the reference under the cursor as the primary selection
+            let primary_index = highlights
+                .iter()
+                .position(|highlight| highlight.start <= cursor && cursor < highlight.end)
+                .unwrap_or(0);
+            let ranges = highlights
+                .iter()
+                .map(|highlight| Range::new(highlight.start, highlight.end))
+                .collect();
+            doc.set_selection(view_id, Selection::new(ranges, primary_index));
+            doc.set_document_highlights(view_id, highlights);
+        },
+    );
+}
+
+fn signature_help(cx: &mut Context) {
+    let (view, doc) = current!(cx.editor);
+
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::SignatureHelp) {
+            Some(language_server) => language_server,
+            None => return,
+        };
 
     let pos = pos_to_lsp_pos(
         doc.text(),
@@ -3259,21 +5414,63 @@ fn signature_help(cx: &mut Context) {
 
     cx.callback(
         future,
-        move |_editor: &mut Editor,
-              _compositor: &mut Compositor,
+        move |editor: &mut Editor,
+              compositor: &mut Compositor,
               response: Option<lsp::SignatureHelp>| {
-            if let Some(signature_help) = response {
-                log::info!("{:?}", signature_help);
-                // signatures
-                // active_signature
-                // active_parameter
-                // render as:
-
-                // signature
-                // ----------
-                // doc
-
-                // with active param highlighted
+            let (_, doc) = current!(editor);
+            let mode = doc.mode();
+            let language = doc
+                .language()
+                .and_then(|scope| scope.strip_prefix("source."))
+                .unwrap_or("")
+                .to_string();
+
+            let editor_view = compositor
+                .find(std::any::type_name::<ui::EditorView>())
+                .and_then(|ui| ui.as_any_mut().downcast_mut::<ui::EditorView>());
+
+            let signature_help = match response {
+                Some(signature_help) if !signature_help.signatures.is_empty() => signature_help,
+                _ => {
+                    if let Some(editor_view) = editor_view {
+                        editor_view.clear_signature_help();
+                    }
+                    return;
+                }
+            };
+
+            let index = signature_help
+                .active_signature
+                .map_or(0, |index| index as usize)
+                .min(signature_help.signatures.len() - 1);
+            let signature = &signature_help.signatures[index];
+
+            // render as:
+            //
+            // signature
+            // ----------
+            // doc
+            let mut contents = format!("```{}\n{}\n```", language, signature.label);
+            match &signature.documentation {
+                Some(lsp::Documentation::String(documentation))
+                | Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+                    value: documentation,
+                    ..
+                })) if !documentation.is_empty() => {
+                    contents.push('\n');
+                    contents.push_str(documentation);
+                }
+                _ => (),
+            }
+
+            let contents = ui::Markdown::new(contents, editor.syn_loader.clone());
+            let mut popup = Popup::new(contents);
+            popup.set_prefer_above(true);
+
+            match editor_view {
+                // while typing, keep the popup open until insert mode is left
+                Some(editor_view) if mode == Mode::Insert => editor_view.set_signature_help(popup),
+                _ => compositor.push(Box::new(popup)),
             }
         },
     );
@@ -3288,10 +5485,11 @@ pub mod insert {
     fn completion(cx: &mut Context, ch: char) {
         // if ch matches completion char, trigger completion
         let doc = doc_mut!(cx.editor);
-        let language_server = match doc.language_server() {
-            Some(language_server) => language_server,
-            None => return,
-        };
+        let language_server =
+            match doc.language_server_with_feature(LanguageServerFeature::Completion) {
+                Some(language_server) => language_server,
+                None => return,
+            };
 
         let capabilities = language_server.capabilities();
 
@@ -3308,7 +5506,7 @@ pub mod insert {
             let is_trigger = triggers.iter().any(|trigger| trigger.contains(ch));
 
             if is_trigger {
-                super::completion(cx);
+                super::request_completion(cx, false);
             }
         }
     }
@@ -3316,25 +5514,30 @@ pub mod insert {
     fn signature_help(cx: &mut Context, ch: char) {
         // if ch matches signature_help char, trigger
         let doc = doc_mut!(cx.editor);
-        let language_server = match doc.language_server() {
-            Some(language_server) => language_server,
-            None => return,
-        };
+        let language_server =
+            match doc.language_server_with_feature(LanguageServerFeature::SignatureHelp) {
+                Some(language_server) => language_server,
+                None => return,
+            };
 
         let capabilities = language_server.capabilities();
 
         if let lsp::ServerCapabilities {
             signature_help_provider:
                 Some(lsp::SignatureHelpOptions {
-                    trigger_characters: Some(triggers),
-                    // TODO: retrigger_characters
+                    trigger_characters,
+                    retrigger_characters,
                     ..
                 }),
             ..
         } = capabilities
         {
             // TODO: what if trigger is multiple chars long
-            let is_trigger = triggers.iter().any(|trigger| trigger.contains(ch));
+            let is_trigger = trigger_characters
+                .iter()
+                .chain(retrigger_characters)
+                .flatten()
+                .any(|trigger| trigger.contains(ch));
 
             if is_trigger {
                 super::signature_help(cx);
@@ -3369,6 +5572,7 @@ pub mod insert {
 
     pub fn insert_char(cx: &mut Context, c: char) {
         let (view, doc) = current!(cx.editor);
+        replace_placeholder(doc, view.id);
 
         let hooks: &[Hook] = match cx.editor.config.auto_pairs {
             true => &[auto_pairs::hook, insert],
@@ -3394,7 +5598,110 @@ pub mod insert {
         }
     }
 
+    /// Delete the text of the snippet placeholder that is selected, so that typing replaces it.
+    fn replace_placeholder(doc: &mut Document, view_id: ViewId) {
+        let text = doc.text().slice(..);
+        let selection = doc.selection(view_id);
+        let is_placeholder = match &doc.active_snippet {
+            Some(snippet) => {
+                snippet.current().iter().any(|range| !range.is_empty())
+                    && snippet.current().clone().ensure_invariants(text) == *selection
+            }
+            None => false,
+        };
+        if !is_placeholder {
+            return;
+        }
+
+        let transaction = Transaction::change_by_selection(doc.text(), selection, |range| {
+            (range.from(), range.to(), None)
+        });
+        doc.apply(&transaction, view_id);
+    }
+
+    /// Ask the language server to format the document after `ch` was typed, if it's one of its
+    /// trigger characters. Every cursor is formatted, and the edits are merged so each cursor only
+    /// moves along with the text around it.
+    pub fn format_on_type(cx: &mut Context, ch: char) {
+        let (view, doc) = current!(cx.editor);
+        let language_server =
+            match doc.language_server_with_feature(LanguageServerFeature::OnTypeFormatting) {
+                Some(language_server) => language_server,
+                None => return,
+            };
+        if !language_server.is_on_type_formatting_trigger(ch) {
+            return;
+        }
+        let offset_encoding = language_server.offset_encoding();
+
+        let text = doc.text().clone();
+        let requests: Vec<_> = doc
+            .selection(view.id)
+            .iter()
+            .filter_map(|range| {
+                let pos = pos_to_lsp_pos(&text, range.cursor(text.slice(..)), offset_encoding);
+                language_server.text_document_on_type_formatting(
+                    doc.identifier(),
+                    pos,
+                    ch,
+                    doc.formatting_options(),
+                )
+            })
+            .collect();
+
+        let (doc_id, view_id) = (doc.id(), view.id);
+        let doc_version = doc.version();
+        cx.jobs.callback(async move {
+            let edits = futures_util::future::join_all(requests)
+                .await
+                .into_iter()
+                .filter_map(|edits| {
+                    edits
+                        .map_err(|e| log::warn!("LSP on type formatting failed: {}", e))
+                        .ok()
+                })
+                .collect();
+            let edits = helix_lsp::util::merge_text_edits(edits);
+
+            let call: job::Callback =
+                Box::new(move |editor: &mut Editor, _compositor: &mut Compositor| {
+                    if edits.is_empty() {
+                        return;
+                    }
+                    let doc = match editor.document_mut(doc_id) {
+                        Some(doc)
+                            if doc.version() == doc_version
+                                && doc.selections().contains_key(&view_id) =>
+                        {
+                            doc
+                        }
+                        _ => {
+                            log::info!("discarded on type formatting because the document changed");
+                            return;
+                        }
+                    };
+                    let transaction = helix_lsp::util::generate_transaction_from_edits(
+                        &text,
+                        edits,
+                        offset_encoding,
+                    );
+                    doc.apply(&transaction, view_id);
+                    // part of the ongoing insert, so it's undone along with it, unless insert
+                    // mode was left before the response arrived
+                    if doc.mode() != Mode::Insert {
+                        doc.append_changes_to_history(view_id);
+                    }
+                });
+            Ok(call)
+        });
+    }
+
     pub fn insert_tab(cx: &mut Context) {
+        // inside a snippet, tab jumps to the next tabstop instead
+        if goto_tabstop(cx, Direction::Forward) {
+            return;
+        }
+
         let (view, doc) = current!(cx.editor);
         // TODO: round out to nearest indentation level (for example a line with 3 spaces should
         // indent by one to reach 4 spaces).
@@ -3408,6 +5715,33 @@ pub mod insert {
         doc.apply(&transaction, view.id);
     }
 
+    pub fn goto_prev_tabstop(cx: &mut Context) {
+        goto_tabstop(cx, Direction::Backward);
+    }
+
+    /// Select the next or previous tabstop of the active snippet. Returns false if there is no
+    /// snippet being expanded.
+    fn goto_tabstop(cx: &mut Context, direction: Direction) -> bool {
+        let (view, doc) = current!(cx.editor);
+        let snippet = match &mut doc.active_snippet {
+            Some(snippet) => snippet,
+            None => return false,
+        };
+
+        let selection = match direction {
+            Direction::Forward => snippet.next_tabstop(),
+            Direction::Backward => snippet.prev_tabstop(),
+        };
+        // the snippet is finished once the final tabstop is reached
+        if snippet.is_done() {
+            doc.active_snippet = None;
+        }
+        if let Some(selection) = selection {
+            doc.set_selection(view.id, selection);
+        }
+        true
+    }
+
     pub fn insert_newline(cx: &mut Context) {
         let (view, doc) = current!(cx.editor);
         let text = doc.text().slice(..);
@@ -3896,48 +6230,49 @@ fn format_selections(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
 
     // via lsp if available
-    // else via tree-sitter indentation calculations
-
-    let language_server = match doc.language_server() {
-        Some(language_server) => language_server,
-        None => return,
-    };
-
-    let ranges: Vec<lsp::Range> = doc
-        .selection(view.id)
-        .iter()
-        .map(|range| range_to_lsp_range(doc.text(), *range, language_server.offset_encoding()))
-        .collect();
+    // TODO: else via tree-sitter indentation calculations
 
-    // TODO: all of the TODO's and commented code inside the loop,
-    // to make this actually work.
-    for _range in ranges {
-        let _language_server = match doc.language_server() {
+    let language_server =
+        match doc.language_server_with_feature(LanguageServerFeature::FormatSelections) {
             Some(language_server) => language_server,
             None => return,
         };
-        // TODO: handle fails
-        // TODO: concurrent map
-
-        // TODO: need to block to get the formatting
-
-        // let edits = block_on(language_server.text_document_range_formatting(
-        //     doc.identifier(),
-        //     range,
-        //     lsp::FormattingOptions::default(),
-        // ))
-        // .unwrap_or_default();
+    let offset_encoding = language_server.offset_encoding();
 
-        // let transaction = helix_lsp::util::generate_transaction_from_edits(
-        //     doc.text(),
-        //     edits,
-        //     language_server.offset_encoding(),
-        // );
+    let requests: Vec<_> = doc
+        .selection(view.id)
+        .iter()
+        .filter_map(|range| {
+            language_server.text_document_range_formatting(
+                doc.identifier(),
+                range_to_lsp_range(doc.text(), *range, offset_encoding),
+                doc.formatting_options(),
+                None,
+            )
+        })
+        .collect();
 
-        // doc.apply(&transaction, view.id);
-    }
+    // format all selections at once, so the edits can be merged into a single transaction
+    let text = doc.text().clone();
+    let format = async move {
+        let edits = futures_util::future::join_all(requests)
+            .await
+            .into_iter()
+            .filter_map(|edits| {
+                edits
+                    .map_err(|e| log::warn!("LSP range formatting failed: {}", e))
+                    .ok()
+            })
+            .collect();
+        helix_lsp::util::LspFormatting {
+            doc: text,
+            edits: helix_lsp::util::merge_text_edits(edits),
+            offset_encoding,
+        }
+    };
 
-    doc.append_changes_to_history(view.id);
+    let callback = make_format_callback(doc.id(), doc.version(), Modified::LeaveModified, format);
+    cx.jobs.callback(callback);
 }
 
 fn join_selections(cx: &mut Context) {
@@ -4013,6 +6348,37 @@ fn keep_primary_selection(cx: &mut Context) {
 }
 
 fn completion(cx: &mut Context) {
+    request_completion(cx, true)
+}
+
+/// Requests completion at the cursor once a long enough word was typed.
+pub fn idle_completion(cx: &mut Context) {
+    let (view, doc) = current!(cx.editor);
+    let text = doc.text().slice(..);
+    let cursor = doc.selection(view.id).primary().cursor(text);
+
+    let prefix_len = cursor - completion_start(text, cursor);
+    if prefix_len >= cx.editor.config.completion_trigger_len as usize {
+        request_completion(cx, false);
+    }
+}
+
+/// Start of the word before `cursor` that completion items will replace.
+fn completion_start(text: RopeSlice, cursor: usize) -> usize {
+    let mut chars = text.chars_at(cursor);
+    let mut start = cursor;
+    while let Some(ch) = chars.prev() {
+        if !char_is_word(ch) {
+            break;
+        }
+        start -= 1;
+    }
+    start
+}
+
+/// Requests completion from the language server. When `manual` is false (as-you-type
+/// completion), an empty response silently closes the menu instead of reporting an error.
+pub fn request_completion(cx: &mut Context, manual: bool) {
     // trigger on trigger char, or if user calls it
     // (or on word char typing??)
     // after it's triggered, if response marked is_incomplete, update on every subsequent keypress
@@ -4034,7 +6400,6 @@ fn completion(cx: &mut Context) {
     //
     // company-mode does this by matching the prefix of the completion and removing it.
 
-    // ignore isIncomplete for now
     // keep state while typing
     // the behavior should be, filter the menu based on input
     // if items returns empty at any point, remove the popup
@@ -4051,7 +6416,8 @@ fn completion(cx: &mut Context) {
 
     let (view, doc) = current!(cx.editor);
 
-    let language_server = match doc.language_server() {
+    let language_server = match doc.language_server_with_feature(LanguageServerFeature::Completion)
+    {
         Some(language_server) => language_server,
         None => return,
     };
@@ -4066,6 +6432,7 @@ fn completion(cx: &mut Context) {
 
     let future = language_server.completion(doc.identifier(), pos, None);
 
+    let start_offset = completion_start(doc.text().slice(..), cursor);
     let trigger_offset = cursor;
 
     cx.callback(
@@ -4079,27 +6446,40 @@ fn completion(cx: &mut Context) {
                 return;
             }
 
-            let items = match response {
-                Some(lsp::CompletionResponse::Array(items)) => items,
-                // TODO: do something with is_incomplete
+            let (items, is_incomplete) = match response {
+                Some(lsp::CompletionResponse::Array(items)) => (items, false),
                 Some(lsp::CompletionResponse::List(lsp::CompletionList {
-                    is_incomplete: _is_incomplete,
+                    is_incomplete,
                     items,
-                })) => items,
-                None => Vec::new(),
+                })) => (items, is_incomplete),
+                None => (Vec::new(), false),
             };
 
-            if items.is_empty() {
-                editor.set_error("No completion available".to_string());
-                return;
-            }
             let size = compositor.size();
             let ui = compositor
                 .find(std::any::type_name::<ui::EditorView>())
                 .unwrap();
-            if let Some(ui) = ui.as_any_mut().downcast_mut::<ui::EditorView>() {
-                ui.set_completion(items, offset_encoding, trigger_offset, size);
+            let ui = match ui.as_any_mut().downcast_mut::<ui::EditorView>() {
+                Some(ui) => ui,
+                None => return,
             };
+
+            if items.is_empty() {
+                ui.clear_completion();
+                if manual {
+                    editor.set_error("No completion available".to_string());
+                }
+                return;
+            }
+
+            let completion = ui::Completion::new(
+                items,
+                offset_encoding,
+                start_offset,
+                trigger_offset,
+                is_incomplete,
+            );
+            ui.set_completion(editor, completion, size);
         },
     );
 }
@@ -4107,7 +6487,7 @@ fn completion(cx: &mut Context) {
 fn hover(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
 
-    let language_server = match doc.language_server() {
+    let language_server = match doc.language_server_with_feature(LanguageServerFeature::Hover) {
         Some(language_server) => language_server,
         None => return,
     };
@@ -4237,11 +6617,65 @@ fn expand_selection(cx: &mut Context) {
 
     if let Some(syntax) = doc.syntax() {
         let text = doc.text().slice(..);
-        let selection = object::expand_selection(syntax, text, doc.selection(view.id));
+        let current_selection = doc.selection(view.id);
+        let selection = object::expand_selection(syntax, text, current_selection);
+
+        if *current_selection != selection {
+            // remember the current selection so shrink_selection can restore it
+            view.push_object_selection(doc, current_selection.clone());
+            doc.set_selection(view.id, selection);
+        }
+    }
+}
+
+fn shrink_selection(cx: &mut Context) {
+    let (view, doc) = current!(cx.editor);
+    let current_selection = doc.selection(view.id);
+
+    // restore the selection from before the last expansion, as long as it still fits inside the
+    // current one
+    if let Some(prev_selection) = view.pop_object_selection(doc) {
+        if current_selection.contains(&prev_selection) {
+            doc.set_selection(view.id, prev_selection);
+            return;
+        }
+        // the selection changed since expanding, the history no longer applies
+        view.clear_object_selections();
+    }
+
+    // no history, shrink to the first child node
+    if let Some(syntax) = doc.syntax() {
+        let text = doc.text().slice(..);
+        let selection = object::shrink_selection(syntax, text, current_selection);
+        doc.set_selection(view.id, selection);
+    }
+}
+
+fn select_sibling_impl(cx: &mut Context, dir: Direction) {
+    let count = cx.count();
+    let (view, doc) = current!(cx.editor);
+
+    if let Some(syntax) = doc.syntax() {
+        let text = doc.text().slice(..);
+        let behaviour = if doc.mode == Mode::Select {
+            Movement::Extend
+        } else {
+            Movement::Move
+        };
+        let selection =
+            object::select_sibling(syntax, text, doc.selection(view.id), dir, count, behaviour);
         doc.set_selection(view.id, selection);
     }
 }
 
+fn select_next_sibling(cx: &mut Context) {
+    select_sibling_impl(cx, Direction::Forward)
+}
+
+fn select_prev_sibling(cx: &mut Context) {
+    select_sibling_impl(cx, Direction::Backward)
+}
+
 fn match_brackets(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
 
@@ -4338,6 +6772,87 @@ fn select_register(cx: &mut Context) {
     })
 }
 
+fn record_macro(cx: &mut Context) {
+    if let Some(recording) = cx.editor.macro_recording.take() {
+        let reg = recording.register;
+        // leaves out the keys that ended the recording
+        let keys = helix_view::input::format_macro(&recording.finish());
+        cx.editor.registers.write(reg, vec![keys]);
+        cx.editor
+            .set_status(format!("Recorded to register {}", reg));
+    } else {
+        let reg = cx.register.take().unwrap_or('@');
+        cx.editor.macro_recording = Some(MacroRecording::new(reg));
+        cx.editor
+            .set_status(format!("Recording to register {}", reg));
+    }
+}
+
+fn replay_macro(cx: &mut Context) {
+    let reg = cx.register.unwrap_or('@');
+    if cx.editor.macro_replaying.contains(&reg) {
+        cx.editor
+            .set_error(format!("Cannot replay register {} recursively", reg));
+        return;
+    }
+    let keys = match cx.editor.registers.read(reg) {
+        Some([keys]) => match helix_view::input::parse_macro(keys) {
+            Ok(keys) => keys,
+            Err(err) => {
+                cx.editor.set_error(format!("Invalid macro: {}", err));
+                return;
+            }
+        },
+        _ => {
+            cx.editor.set_error(format!("Register {} is empty", reg));
+            return;
+        }
+    };
+    let count = cx.count();
+
+    // hold back history commits so that the whole replay is undone in one step
+    let (view, doc) = current!(cx.editor);
+    let (view_id, doc_id) = (view.id, doc.id());
+    doc.hold_history();
+
+    cx.callback = Some(Box::new(move |compositor: &mut Compositor, cx| {
+        replay_keys(cx, reg, (doc_id, view_id), &keys, count, |cx, key| {
+            compositor.handle_event(crossterm::event::Event::Key(key.into()), cx);
+        });
+    }));
+}
+
+/// Feed `keys` of the macro in register `reg` to `handle_key` `count` times, then commit the
+/// changes made to the document of `(doc_id, view_id)` as a single history step.
+fn replay_keys(
+    cx: &mut compositor::Context,
+    reg: char,
+    (doc_id, view_id): (DocumentId, ViewId),
+    keys: &[KeyEvent],
+    count: usize,
+    mut handle_key: impl FnMut(&mut compositor::Context, KeyEvent),
+) {
+    cx.editor.macro_replaying.push(reg);
+    for _ in 0..count {
+        for &key in keys {
+            handle_key(cx, key);
+        }
+    }
+    cx.editor.macro_replaying.pop();
+
+    // the macro might have closed the view or the document
+    if let Some(doc) = cx.editor.document_mut(doc_id) {
+        let view_id = if doc.selections().contains_key(&view_id) {
+            Some(view_id)
+        } else {
+            doc.selections().keys().next().copied()
+        };
+        if let Some(view_id) = view_id {
+            doc.release_history(view_id);
+        }
+    }
+}
+
 fn align_view_top(cx: &mut Context) {
     let (view, doc) = current!(cx.editor);
     align_view(doc, view, Align::Top);
@@ -4387,9 +6902,29 @@ fn select_textobject(cx: &mut Context, objtype: textobject::TextObject) {
             let (view, doc) = current!(cx.editor);
             let text = doc.text().slice(..);
 
+            let textobject_treesitter = |obj_name: &str, range: Range| -> Range {
+                let (lang_config, syntax) = match doc.language_config().zip(doc.syntax()) {
+                    Some(t) => t,
+                    None => return range,
+                };
+                textobject::textobject_treesitter(
+                    text,
+                    range,
+                    objtype,
+                    obj_name,
+                    syntax.tree().root_node(),
+                    lang_config,
+                    count,
+                )
+            };
+
             let selection = doc.selection(view.id).clone().transform(|range| {
                 match ch {
                     'w' => textobject::textobject_word(text, range, objtype, count),
+                    'f' => textobject_treesitter("function", range),
+                    'c' => textobject_treesitter("class", range),
+                    'a' => textobject_treesitter("parameter", range),
+                    'C' => textobject_treesitter("comment", range),
                     // TODO: cancel new ranges if inconsistent surround matches across lines
                     ch if !ch.is_ascii_alphanumeric() => {
                         textobject::textobject_surround(text, range, objtype, ch, count)
@@ -4692,3 +7227,223 @@ fn add_newline_impl(cx: &mut Context, open: Open) {
     doc.apply(&transaction, view.id);
     doc.append_changes_to_history(view.id);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{keymap::Keymaps, ui::EditorView};
+    use crossterm::event::Event;
+    use helix_core::syntax;
+    use helix_view::{graphics::Rect, input::parse_macro, theme};
+    use std::sync::Arc;
+
+    fn editor() -> Editor {
+        // set runtime path so we can find the queries
+        let mut runtime = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        runtime.push("../runtime");
+        std::env::set_var("HELIX_RUNTIME", runtime.to_str().unwrap());
+
+        let themes = theme::Loader::new("", "");
+        let syntax =
+            syntax::Loader::new(toml::from_slice(include_bytes!("../../languages.toml")).unwrap());
+        let mut editor = Editor::new(
+            Rect::new(0, 0, 80, 24),
+            Arc::new(themes),
+            Arc::new(syntax),
+            Default::default(),
+        );
+        editor.new_file(Action::VerticalSplit);
+        editor
+    }
+
+    #[tokio::test]
+    async fn macro_replay_is_undone_in_one_step() {
+        let mut editor = editor();
+        let mut jobs = Jobs::new();
+        let mut editor_view = EditorView::new(Keymaps::default());
+        let mut cx = compositor::Context {
+            editor: &mut editor,
+            jobs: &mut jobs,
+            scroll: None,
+        };
+
+        // record like the application does, pushing each key before handling it
+        for key in parse_macro("\"aqixy<esc>\"aq").unwrap() {
+            if let Some(recording) = &mut cx.editor.macro_recording {
+                recording.push(key);
+            }
+            editor_view.handle_event(Event::Key(key.into()), &mut cx);
+        }
+        assert!(cx.editor.macro_recording.is_none());
+        let recorded = cx.editor.registers.read('a').unwrap()[0].clone();
+        assert_eq!(recorded, "ixy<esc>");
+
+        let (view, doc) = current!(cx.editor);
+        let (view_id, doc_id) = (view.id, doc.id());
+        assert_eq!(doc.text(), "xy\n");
+        doc.append_changes_to_history(view_id);
+
+        doc.hold_history();
+        let keys = parse_macro(&recorded).unwrap();
+        replay_keys(&mut cx, 'a', (doc_id, view_id), &keys, 2, |cx, key| {
+            editor_view.handle_event(Event::Key(key.into()), cx);
+        });
+        let doc = cx.editor.document_mut(doc_id).unwrap();
+        assert_eq!(doc.text(), "xyxyxy\n");
+
+        doc.undo(view_id);
+        assert_eq!(doc.text(), "xy\n");
+    }
+
+    #[tokio::test]
+    async fn macro_cannot_replay_itself() {
+        let mut editor = editor();
+        let mut jobs = Jobs::new();
+        editor.registers.write('a', vec!["\"aQ".to_string()]);
+        let mut cx = compositor::Context {
+            editor: &mut editor,
+            jobs: &mut jobs,
+            scroll: None,
+        };
+        let replay = |cx: &mut compositor::Context| {
+            let mut cx = Context {
+                register: Some('a'),
+                count: None,
+                editor: cx.editor,
+                callback: None,
+                on_next_key_callback: None,
+                jobs: cx.jobs,
+            };
+            replay_macro(&mut cx);
+            cx.callback.is_some()
+        };
+
+        let (view, doc) = current!(cx.editor);
+        let ids = (doc.id(), view.id);
+        let mut replayed = Vec::new();
+        // stands in for the `Q` of the macro reaching `replay_macro` again
+        let keys = parse_macro("Q").unwrap();
+        replay_keys(&mut cx, 'a', ids, &keys, 1, |cx, _| {
+            replayed.push(replay(cx));
+        });
+        assert_eq!(replayed, [false]);
+        assert_eq!(
+            cx.editor.status_msg.as_ref().unwrap().0,
+            "Cannot replay register a recursively"
+        );
+
+        assert!(cx.editor.macro_replaying.is_empty());
+        assert!(replay(&mut cx));
+    }
+
+    #[tokio::test]
+    async fn shrink_selection_undoes_expand_selection() {
+        let source = "fn main() {\n    let x = 1 + 2;\n}\n\nfn foo(a: u8) {}\n";
+        let mut editor = editor();
+        let mut doc = Document::from(Rope::from(source), None);
+        doc.set_language2("source.rust", None, editor.syn_loader.clone());
+        editor.new_file_from_document(Action::VerticalSplit, doc);
+
+        let mut jobs = Jobs::new();
+        let mut cx = Context {
+            register: None,
+            count: None,
+            editor: &mut editor,
+            callback: None,
+            on_next_key_callback: None,
+            jobs: &mut jobs,
+        };
+        let range = |cx: &mut Context| {
+            let (view, doc) = current!(cx.editor);
+            doc.selection(view.id).primary()
+        };
+        let select = |cx: &mut Context, from: usize, to: usize| {
+            let (view, doc) = current!(cx.editor);
+            doc.set_selection(view.id, Selection::single(from, to));
+        };
+
+        let one = source.find('1').unwrap();
+        select(&mut cx, one, one + 1);
+        let mut expanded = vec![range(&mut cx)];
+        for _ in 0..3 {
+            expand_selection(&mut cx);
+            expanded.push(range(&mut cx));
+        }
+        assert_eq!(expanded[1], Range::new(one, one + "1 + 2".len()));
+        assert_eq!(
+            expanded[2].fragment(Rope::from(source).slice(..)),
+            "let x = 1 + 2;"
+        );
+
+        // shrinking walks back through the expanded selections
+        for expected in expanded.iter().rev().skip(1).take(2) {
+            shrink_selection(&mut cx);
+            assert_eq!(range(&mut cx), *expected);
+        }
+
+        // a selection outside of the expanded ones shrinks to its first child instead
+        expand_selection(&mut cx);
+        let params = source.find("(a: u8)").unwrap();
+        select(&mut cx, params, params + "(a: u8)".len());
+        shrink_selection(&mut cx);
+        assert_eq!(
+            range(&mut cx),
+            Range::new(params + 1, params + "(a: u8".len())
+        );
+        shrink_selection(&mut cx);
+        assert_eq!(range(&mut cx), Range::new(params + 1, params + 2));
+
+        // edits make the expanded selections stale, even if they'd still fit
+        select(&mut cx, one, one + 1);
+        expand_selection(&mut cx);
+        expand_selection(&mut cx);
+        let (view, doc) = current!(cx.editor);
+        let end = doc.text().len_chars();
+        let transaction =
+            Transaction::change(doc.text(), vec![(end, end, Some("\n".into()))].into_iter());
+        doc.apply(&transaction, view.id);
+        shrink_selection(&mut cx);
+        let (view, doc) = current!(cx.editor);
+        let text = doc.text().slice(..);
+        assert_eq!(doc.selection(view.id).primary().fragment(text), "x");
+    }
+
+    #[tokio::test]
+    async fn diagnostic_reports_keep_result_ids() {
+        let mut editor = editor();
+        let url = lsp::Url::parse("file:///tmp/a.rs").unwrap();
+        let key = (1, url.clone());
+        let diagnostic = lsp::Diagnostic {
+            message: "a".to_string(),
+            ..Default::default()
+        };
+        let full = |result_id: Option<&str>, items| {
+            lsp::DocumentDiagnosticReportKind::Full(lsp::FullDocumentDiagnosticReport {
+                result_id: result_id.map(String::from),
+                items,
+            })
+        };
+        let apply = |editor: &mut Editor, report| {
+            apply_diagnostic_report(editor, 1, OffsetEncoding::Utf8, url.clone(), report)
+        };
+
+        apply(&mut editor, full(Some("1"), vec![diagnostic.clone()]));
+        assert_eq!(editor.diagnostic_result_ids.get(&key).unwrap(), "1");
+        assert_eq!(editor.diagnostics[&url].len(), 1);
+
+        // unchanged reports only move the result id forward
+        apply(
+            &mut editor,
+            lsp::DocumentDiagnosticReportKind::Unchanged(lsp::UnchangedDocumentDiagnosticReport {
+                result_id: "2".to_string(),
+            }),
+        );
+        assert_eq!(editor.diagnostic_result_ids.get(&key).unwrap(), "2");
+        assert_eq!(editor.diagnostics[&url].len(), 1);
+
+        // a report without a result id can't be referred to in the next pull
+        apply(&mut editor, full(None, Vec::new()));
+        assert!(!editor.diagnostic_result_ids.contains_key(&key));
+        assert!(!editor.diagnostics.contains_key(&url));
+    }
+}
@@ -456,6 +456,10 @@ impl Default for Keymaps {
             "x" => extend_line,
             "X" => extend_to_line_bounds,
             // crop_to_whole_line
+            "A-o" => expand_selection,
+            "A-i" => shrink_selection,
+            "A-n" => select_next_sibling,
+            "A-p" => select_prev_sibling,
 
             "m" => { "Match"
                 "m" => match_brackets,
@@ -468,11 +472,15 @@ impl Default for Keymaps {
             "[" => { "Left bracket"
                 "d" => goto_prev_diag,
                 "D" => goto_first_diag,
+                "f" => goto_prev_function,
+                "c" => goto_prev_class,
                 "space" => add_newline_above,
             },
             "]" => { "Right bracket"
                 "d" => goto_next_diag,
                 "D" => goto_last_diag,
+                "f" => goto_next_function,
+                "c" => goto_next_class,
                 "space" => add_newline_below,
             },
 
@@ -503,8 +511,8 @@ impl Default for Keymaps {
             // TODO: clashes with space mode
             "space" => keep_primary_selection,
 
-            // "q" => record_macro,
-            // "Q" => replay_macro,
+            "q" => record_macro,
+            "Q" => replay_macro,
 
             // & align selections
             // _ trim selections
@@ -541,7 +549,16 @@ impl Default for Keymaps {
                 "f" => file_picker,
                 "b" => buffer_picker,
                 "s" => symbol_picker,
+                "d" => diagnostics_picker,
+                "D" => workspace_diagnostics_picker,
+                "h" => select_references_to_symbol_under_cursor,
+                "c" => incoming_calls,
+                "C" => outgoing_calls,
+                "t" => subtypes,
+                "T" => supertypes,
+                "l" => code_lens,
                 "a" => code_action,
+                "r" => rename_symbol,
                 "'" => last_picker,
                 "w" => { "Window"
                     "C-w" | "w" => rotate_view,
@@ -622,6 +639,7 @@ impl Default for Keymaps {
             "del" => delete_char_forward,
             "ret" => insert_newline,
             "tab" => insert_tab,
+            "backtab" => goto_prev_tabstop,
             "C-w" => delete_word_backward,
 
             "left" => move_char_left,
@@ -4,7 +4,10 @@ use tui::buffer::Buffer as Surface;
 
 use std::borrow::Cow;
 
-use helix_core::Transaction;
+use helix_core::{
+    snippet::{self, ActiveSnippet},
+    Selection, Transaction,
+};
 use helix_view::{graphics::Rect, Document, Editor, View};
 
 use crate::commands;
@@ -30,32 +33,32 @@ impl menu::Item for CompletionItem {
         menu::Row::new(vec![
             menu::Cell::from(self.label.as_str()),
             menu::Cell::from(match self.kind {
-                Some(lsp::CompletionItemKind::Text) => "text",
-                Some(lsp::CompletionItemKind::Method) => "method",
-                Some(lsp::CompletionItemKind::Function) => "function",
-                Some(lsp::CompletionItemKind::Constructor) => "constructor",
-                Some(lsp::CompletionItemKind::Field) => "field",
-                Some(lsp::CompletionItemKind::Variable) => "variable",
-                Some(lsp::CompletionItemKind::Class) => "class",
-                Some(lsp::CompletionItemKind::Interface) => "interface",
-                Some(lsp::CompletionItemKind::Module) => "module",
-                Some(lsp::CompletionItemKind::Property) => "property",
-                Some(lsp::CompletionItemKind::Unit) => "unit",
-                Some(lsp::CompletionItemKind::Value) => "value",
-                Some(lsp::CompletionItemKind::Enum) => "enum",
-                Some(lsp::CompletionItemKind::Keyword) => "keyword",
-                Some(lsp::CompletionItemKind::Snippet) => "snippet",
-                Some(lsp::CompletionItemKind::Color) => "color",
-                Some(lsp::CompletionItemKind::File) => "file",
-                Some(lsp::CompletionItemKind::Reference) => "reference",
-                Some(lsp::CompletionItemKind::Folder) => "folder",
-                Some(lsp::CompletionItemKind::EnumMember) => "enum_member",
-                Some(lsp::CompletionItemKind::Constant) => "constant",
-                Some(lsp::CompletionItemKind::Struct) => "struct",
-                Some(lsp::CompletionItemKind::Event) => "event",
-                Some(lsp::CompletionItemKind::Operator) => "operator",
-                Some(lsp::CompletionItemKind::TypeParameter) => "type_param",
-                None => "",
+                Some(lsp::CompletionItemKind::TEXT) => "text",
+                Some(lsp::CompletionItemKind::METHOD) => "method",
+                Some(lsp::CompletionItemKind::FUNCTION) => "function",
+                Some(lsp::CompletionItemKind::CONSTRUCTOR) => "constructor",
+                Some(lsp::CompletionItemKind::FIELD) => "field",
+                Some(lsp::CompletionItemKind::VARIABLE) => "variable",
+                Some(lsp::CompletionItemKind::CLASS) => "class",
+                Some(lsp::CompletionItemKind::INTERFACE) => "interface",
+                Some(lsp::CompletionItemKind::MODULE) => "module",
+                Some(lsp::CompletionItemKind::PROPERTY) => "property",
+                Some(lsp::CompletionItemKind::UNIT) => "unit",
+                Some(lsp::CompletionItemKind::VALUE) => "value",
+                Some(lsp::CompletionItemKind::ENUM) => "enum",
+                Some(lsp::CompletionItemKind::KEYWORD) => "keyword",
+                Some(lsp::CompletionItemKind::SNIPPET) => "snippet",
+                Some(lsp::CompletionItemKind::COLOR) => "color",
+                Some(lsp::CompletionItemKind::FILE) => "file",
+                Some(lsp::CompletionItemKind::REFERENCE) => "reference",
+                Some(lsp::CompletionItemKind::FOLDER) => "folder",
+                Some(lsp::CompletionItemKind::ENUM_MEMBER) => "enum_member",
+                Some(lsp::CompletionItemKind::CONSTANT) => "constant",
+                Some(lsp::CompletionItemKind::STRUCT) => "struct",
+                Some(lsp::CompletionItemKind::EVENT) => "event",
+                Some(lsp::CompletionItemKind::OPERATOR) => "operator",
+                Some(lsp::CompletionItemKind::TYPE_PARAMETER) => "type_param",
+                _ => "",
             }),
             // self.detail.as_deref().unwrap_or("")
             // self.label_details
@@ -66,10 +69,35 @@ impl menu::Item for CompletionItem {
     }
 }
 
+/// Value of a snippet variable for a snippet inserted at `pos`, or `None` for unknown variables and
+/// ones that don't apply, like the file name of a scratch buffer.
+fn snippet_variable(doc: &Document, pos: usize, name: &str) -> Option<String> {
+    let text = doc.text();
+    let line = text.char_to_line(pos);
+    let path = doc.path();
+    let value = match name {
+        "TM_CURRENT_LINE" => {
+            let line = text.line(line).to_string();
+            line.trim_end_matches(['\n', '\r']).to_string()
+        }
+        "TM_LINE_INDEX" => line.to_string(),
+        "TM_LINE_NUMBER" => (line + 1).to_string(),
+        "TM_FILENAME" => path?.file_name()?.to_string_lossy().into_owned(),
+        "TM_FILENAME_BASE" => path?.file_stem()?.to_string_lossy().into_owned(),
+        "TM_DIRECTORY" => path?.parent()?.to_string_lossy().into_owned(),
+        "TM_FILEPATH" => path?.to_string_lossy().into_owned(),
+        _ => return None,
+    };
+    Some(value)
+}
+
 /// Wraps a Menu.
 pub struct Completion {
     popup: Popup<Menu<CompletionItem>>,
-    trigger_offset: usize,
+    /// Start of the word that was being typed when completion was requested.
+    start_offset: usize,
+    /// The server didn't send all items, so it has to be asked again as typing continues.
+    is_incomplete: bool,
     // TODO: maintain a completioncontext with trigger kind & trigger char
 }
 
@@ -77,7 +105,9 @@ impl Completion {
     pub fn new(
         items: Vec<CompletionItem>,
         offset_encoding: helix_lsp::OffsetEncoding,
+        start_offset: usize,
         trigger_offset: usize,
+        is_incomplete: bool,
     ) -> Self {
         // let items: Vec<CompletionItem> = Vec::new();
         let menu = Menu::new(items, move |editor: &mut Editor, item, event| {
@@ -86,14 +116,49 @@ impl Completion {
                 view: &View,
                 item: &CompletionItem,
                 offset_encoding: helix_lsp::OffsetEncoding,
-            ) -> Transaction {
-                if let Some(edit) = &item.text_edit {
-                    let edit = match edit {
-                        lsp::CompletionTextEdit::Edit(edit) => edit.clone(),
-                        lsp::CompletionTextEdit::InsertAndReplace(item) => {
-                            unimplemented!("completion: insert_and_replace {:?}", item)
+                start_offset: usize,
+            ) -> (Transaction, Vec<Selection>) {
+                let cursor = doc
+                    .selection(view.id)
+                    .primary()
+                    .cursor(doc.text().slice(..));
+
+                // only the text before the cursor is replaced when a server offers both ranges
+                let edit = item.text_edit.as_ref().map(|edit| match edit {
+                    lsp::CompletionTextEdit::Edit(edit) => edit.clone(),
+                    lsp::CompletionTextEdit::InsertAndReplace(edit) => lsp::TextEdit {
+                        range: edit.insert,
+                        new_text: edit.new_text.clone(),
+                    },
+                });
+
+                if item.insert_text_format == Some(lsp::InsertTextFormat::SNIPPET) {
+                    let (start, end, text) = match &edit {
+                        Some(edit) => {
+                            match util::lsp_range_to_range(doc.text(), edit.range, offset_encoding)
+                            {
+                                Some(range) => (range.from(), range.to(), edit.new_text.as_str()),
+                                None => (start_offset, cursor, edit.new_text.as_str()),
+                            }
                         }
+                        None => (
+                            start_offset,
+                            cursor,
+                            item.insert_text.as_ref().unwrap_or(&item.label).as_str(),
+                        ),
                     };
+                    let snippet = snippet::parse(text);
+                    return snippet::into_transaction(
+                        &snippet,
+                        doc.text(),
+                        start,
+                        end,
+                        doc.line_ending,
+                        |name| snippet_variable(doc, start, name),
+                    );
+                }
+
+                let transaction = if let Some(edit) = edit {
                     util::generate_transaction_from_edits(
                         doc.text(),
                         vec![edit],
@@ -101,15 +166,13 @@ impl Completion {
                     )
                 } else {
                     let text = item.insert_text.as_ref().unwrap_or(&item.label);
-                    let cursor = doc
-                        .selection(view.id)
-                        .primary()
-                        .cursor(doc.text().slice(..));
+                    // replace the part of the word typed before completion was requested
                     Transaction::change(
                         doc.text(),
-                        vec![(cursor, cursor, Some(text.as_str().into()))].into_iter(),
+                        vec![(start_offset, cursor, Some(text.as_str().into()))].into_iter(),
                     )
-                }
+                };
+                (transaction, Vec::new())
             }
 
             match event {
@@ -134,7 +197,8 @@ impl Completion {
                         doc.apply(&remove, view.id);
                     }
 
-                    let transaction = item_to_transaction(doc, view, item, offset_encoding);
+                    let (transaction, _) =
+                        item_to_transaction(doc, view, item, offset_encoding, start_offset);
                     doc.apply(&transaction, view.id);
                 }
                 PromptEvent::Validate => {
@@ -157,9 +221,16 @@ impl Completion {
                         doc.apply(&remove, view.id);
                     }
 
-                    let transaction = item_to_transaction(doc, view, item, offset_encoding);
+                    let (transaction, tabstops) =
+                        item_to_transaction(doc, view, item, offset_encoding, start_offset);
                     doc.apply(&transaction, view.id);
 
+                    // select the first tabstop of a snippet, tab moves on to the others
+                    if let Some(selection) = tabstops.first() {
+                        doc.set_selection(view.id, selection.clone());
+                        doc.active_snippet = ActiveSnippet::new(tabstops);
+                    }
+
                     if let Some(additional_edits) = &item.additional_text_edits {
                         // gopls uses this to add extra imports
                         if !additional_edits.is_empty() {
@@ -177,14 +248,21 @@ impl Completion {
         let popup = Popup::new(menu);
         Self {
             popup,
-            trigger_offset,
+            start_offset,
+            is_incomplete,
         }
     }
 
     pub fn update(&mut self, cx: &mut commands::Context) {
+        self.recompute_filter(cx.editor)
+    }
+
+    /// Filter the menu by the text typed since the start of the completed word.
+    pub fn recompute_filter(&mut self, editor: &Editor) {
         // recompute menu based on matches
         let menu = self.popup.contents_mut();
-        let (view, doc) = current!(cx.editor);
+        let view = editor.tree.get(editor.tree.focus);
+        let doc = &editor.documents[view.doc];
 
         // cx.hooks()
         // cx.add_hook(enum type,  ||)
@@ -200,8 +278,8 @@ impl Completion {
             .selection(view.id)
             .primary()
             .cursor(doc.text().slice(..));
-        if self.trigger_offset <= cursor {
-            let fragment = doc.text().slice(self.trigger_offset..cursor);
+        if self.start_offset <= cursor {
+            let fragment = doc.text().slice(self.start_offset..cursor);
             let text = Cow::from(fragment);
             // TODO: logic is same as ui/picker
             menu.score(&text);
@@ -211,6 +289,14 @@ impl Completion {
     pub fn is_empty(&self) -> bool {
         self.popup.contents().is_empty()
     }
+
+    pub fn is_incomplete(&self) -> bool {
+        self.is_incomplete
+    }
+
+    pub fn start_offset(&self) -> usize {
+        self.start_offset
+    }
 }
 
 // need to:
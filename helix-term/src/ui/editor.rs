@@ -3,7 +3,7 @@ use crate::{
     compositor::{Component, Context, EventResult},
     key,
     keymap::{KeymapResult, KeymapResultKind, Keymaps},
-    ui::{Completion, ProgressSpinners},
+    ui::{Completion, Markdown, Popup, ProgressSpinners},
 };
 
 use helix_core::{
@@ -16,7 +16,7 @@ use helix_core::{
     LineEnding, Position, Range, Selection,
 };
 use helix_view::{
-    document::Mode,
+    document::{DocumentHighlightKind, InlayHint, InlayHintKind, Mode},
     editor::LineNumber,
     graphics::{CursorKind, Modifier, Rect, Style},
     info::Info,
@@ -24,7 +24,7 @@ use helix_view::{
     keyboard::{KeyCode, KeyModifiers},
     Document, Editor, Theme, View,
 };
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
 use crossterm::event::{Event, MouseButton, MouseEvent, MouseEventKind};
 use tui::buffer::Buffer as Surface;
@@ -34,6 +34,7 @@ pub struct EditorView {
     on_next_key: Option<Box<dyn FnOnce(&mut commands::Context, KeyEvent)>>,
     last_insert: (commands::Command, Vec<KeyEvent>),
     completion: Option<Completion>,
+    signature_help: Option<Popup<Markdown>>,
     spinners: ProgressSpinners,
     autoinfo: Option<Info>,
 }
@@ -51,6 +52,7 @@ impl EditorView {
             on_next_key: None,
             last_insert: (commands::Command::normal_mode, Vec::new()),
             completion: None,
+            signature_help: None,
             spinners: ProgressSpinners::default(),
             autoinfo: None,
         }
@@ -76,7 +78,12 @@ impl EditorView {
         let area = view.area;
 
         let highlights = Self::doc_syntax_highlights(doc, view.offset, inner.height, theme, loader);
+        let highlights = syntax::merge(
+            highlights,
+            Self::doc_semantic_highlights(doc, view.offset, inner.height, theme),
+        );
         let highlights = syntax::merge(highlights, Self::doc_diagnostics_highlights(doc, theme));
+        let highlights = syntax::merge(highlights, Self::doc_document_highlights(doc, view, theme));
         let highlights: Box<dyn Iterator<Item = HighlightEvent>> = if is_focused {
             Box::new(syntax::merge(
                 highlights,
@@ -86,7 +93,21 @@ impl EditorView {
             Box::new(highlights)
         };
 
-        Self::render_text_highlights(doc, view.offset, inner, surface, theme, highlights);
+        let inlay_hints = match doc.inlay_hints(view.id) {
+            Some(inlay_hints) if config.inlay_hints => inlay_hints.hints.as_slice(),
+            _ => &[],
+        };
+
+        Self::render_text_highlights(
+            doc,
+            view.offset,
+            inner,
+            surface,
+            theme,
+            highlights,
+            inlay_hints,
+            true,
+        );
         Self::render_gutter(doc, view, view.area, surface, theme, is_focused, config);
 
         if is_focused {
@@ -185,6 +206,70 @@ impl EditorView {
         Box::new(highlights)
     }
 
+    /// Get highlight spans for the visible semantic tokens of a document. Tokens are mapped to the
+    /// `lsp.<type>.<modifier>` scope of their first modifier that the theme styles, or else to
+    /// `lsp.<type>`. Tokens without a scope in the theme keep their syntax highlighting.
+    pub fn doc_semantic_highlights(
+        doc: &Document,
+        offset: Position,
+        height: u16,
+        theme: &Theme,
+    ) -> Vec<(usize, std::ops::Range<usize>)> {
+        let semantic_tokens = match doc.semantic_tokens() {
+            Some(semantic_tokens) => semantic_tokens,
+            None => return Vec::new(),
+        };
+        let legend = match doc
+            .language_servers()
+            .find(|server| server.id() == semantic_tokens.language_server_id)
+            .and_then(|server| server.semantic_tokens_legend())
+        {
+            Some(legend) => legend,
+            None => return Vec::new(),
+        };
+
+        let text = doc.text().slice(..);
+        let last_line = std::cmp::min(
+            (offset.row + height as usize).saturating_sub(1),
+            text.len_lines().saturating_sub(1),
+        );
+        let start = text.line_to_char(offset.row.min(last_line));
+        let end = text.line_to_char(last_line + 1);
+
+        let mut scopes = HashMap::new();
+        let mut find_scope = |token_type: u32, token_modifiers: u32| {
+            *scopes
+                .entry((token_type, token_modifiers))
+                .or_insert_with(|| {
+                    let token_type = legend.token_types.get(token_type as usize)?.as_str();
+                    legend
+                        .token_modifiers
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| *i < 32 && token_modifiers & (1 << i) != 0)
+                        .find_map(|(_, modifier)| {
+                            theme.find_scope_index(&format!(
+                                "lsp.{}.{}",
+                                token_type,
+                                modifier.as_str()
+                            ))
+                        })
+                        .or_else(|| theme.find_scope_index(&format!("lsp.{}", token_type)))
+                })
+        };
+
+        semantic_tokens
+            .tokens
+            .iter()
+            .skip_while(|token| token.end <= start)
+            .take_while(|token| token.start < end)
+            .filter_map(|token| {
+                let scope = find_scope(token.token_type, token.token_modifiers)?;
+                Some((scope, token.start..token.end))
+            })
+            .collect()
+    }
+
     /// Get highlight spans for document diagnostics
     pub fn doc_diagnostics_highlights(
         doc: &Document,
@@ -209,6 +294,39 @@ impl EditorView {
             .collect()
     }
 
+    /// Get highlight spans for references to the symbol under the cursor in a document view.
+    pub fn doc_document_highlights(
+        doc: &Document,
+        view: &View,
+        theme: &Theme,
+    ) -> Vec<(usize, std::ops::Range<usize>)> {
+        let text_scope = match theme
+            .find_scope_index("ui.reference")
+            .or_else(|| theme.find_scope_index("ui.highlight"))
+        {
+            Some(scope) => scope,
+            None => return Vec::new(),
+        };
+        let read_scope = theme
+            .find_scope_index("ui.reference.read")
+            .unwrap_or(text_scope);
+        let write_scope = theme
+            .find_scope_index("ui.reference.write")
+            .unwrap_or(text_scope);
+
+        doc.document_highlights(view.id)
+            .iter()
+            .map(|highlight| {
+                let scope = match highlight.kind {
+                    DocumentHighlightKind::Text => text_scope,
+                    DocumentHighlightKind::Read => read_scope,
+                    DocumentHighlightKind::Write => write_scope,
+                };
+                (scope, highlight.start..highlight.end)
+            })
+            .collect()
+    }
+
     /// Get highlight spans for selections in a document view.
     pub fn doc_selection_highlights(
         doc: &Document,
@@ -271,6 +389,9 @@ impl EditorView {
         spans
     }
 
+    /// Render the text of `doc` with `highlights`. `inlay_hints` are drawn in between as virtual
+    /// text and shift the rest of their line to the right.
+    #[allow(clippy::too_many_arguments)]
     pub fn render_text_highlights<H: Iterator<Item = HighlightEvent>>(
         doc: &Document,
         offset: Position,
@@ -278,17 +399,51 @@ impl EditorView {
         surface: &mut Surface,
         theme: &Theme,
         highlights: H,
+        inlay_hints: &[InlayHint],
+        code_lenses: bool,
     ) {
+        use helix_core::graphemes::{grapheme_width, RopeGraphemes};
+
         let text = doc.text().slice(..);
 
         let mut spans = Vec::new();
         let mut visual_x = 0u16;
         let mut line = 0u16;
+        let mut doc_line = offset.row;
         let tab_width = doc.tab_width();
         let tab = " ".repeat(tab_width);
 
         let text_style = theme.get("ui.text");
 
+        let inlay_hint_style = theme
+            .try_get("ui.virtual.inlay-hint")
+            .unwrap_or_else(|| Style::default().add_modifier(Modifier::DIM));
+        let inlay_hint_type_style = theme
+            .try_get("ui.virtual.inlay-hint.type")
+            .unwrap_or(inlay_hint_style);
+        let inlay_hint_parameter_style = theme
+            .try_get("ui.virtual.inlay-hint.parameter")
+            .unwrap_or(inlay_hint_style);
+        let mut inlay_hints = inlay_hints.iter().peekable();
+
+        let code_lens_style = text_style.patch(
+            theme
+                .try_get("ui.virtual.code-lens")
+                .unwrap_or_else(|| Style::default().add_modifier(Modifier::DIM)),
+        );
+        if code_lenses && doc.has_code_lens_line(doc_line) {
+            Self::render_code_lenses(
+                doc,
+                doc_line,
+                offset,
+                viewport,
+                line,
+                surface,
+                code_lens_style,
+            );
+            line += 1;
+        }
+
         'outer: for event in highlights {
             match event {
                 HighlightEvent::HighlightStart(span) => {
@@ -303,14 +458,49 @@ impl EditorView {
                     // of the rope.
                     let text = text.get_slice(start..end).unwrap_or_else(|| " ".into());
 
-                    use helix_core::graphemes::{grapheme_width, RopeGraphemes};
-
                     let style = spans.iter().fold(text_style, |acc, span| {
                         let style = theme.get(theme.scopes()[span.0].as_str());
                         acc.patch(style)
                     });
 
+                    let mut char_pos = start;
                     for grapheme in RopeGraphemes::new(text) {
+                        // draw the inlay hints placed before this grapheme, skipping those that
+                        // are above the viewport
+                        while let Some(hint) = inlay_hints.next_if(|hint| hint.char_idx <= char_pos)
+                        {
+                            if hint.char_idx < char_pos {
+                                continue;
+                            }
+                            let hint_style = match hint.kind {
+                                InlayHintKind::Type => inlay_hint_type_style,
+                                InlayHintKind::Parameter => inlay_hint_parameter_style,
+                                InlayHintKind::Other => inlay_hint_style,
+                            };
+                            let parts = [
+                                (if hint.padding_left { " " } else { "" }, text_style),
+                                (hint.text.as_str(), text_style.patch(hint_style)),
+                                (if hint.padding_right { " " } else { "" }, text_style),
+                            ];
+                            for (part, style) in parts {
+                                for grapheme in part.graphemes(true) {
+                                    let width = grapheme_width(grapheme) as u16;
+                                    if visual_x >= offset.col as u16
+                                        && visual_x + width <= viewport.width + offset.col as u16
+                                    {
+                                        surface.set_string(
+                                            viewport.x + visual_x - offset.col as u16,
+                                            viewport.y + line,
+                                            grapheme,
+                                            style,
+                                        );
+                                    }
+                                    visual_x = visual_x.saturating_add(width);
+                                }
+                            }
+                        }
+                        char_pos += grapheme.len_chars();
+
                         let out_of_bounds = visual_x < offset.col as u16
                             || visual_x >= viewport.width + offset.col as u16;
 
@@ -327,11 +517,28 @@ impl EditorView {
 
                             visual_x = 0;
                             line += 1;
+                            doc_line += 1;
 
                             // TODO: with proper iter this shouldn't be necessary
                             if line >= viewport.height {
                                 break 'outer;
                             }
+
+                            if code_lenses && doc.has_code_lens_line(doc_line) {
+                                Self::render_code_lenses(
+                                    doc,
+                                    doc_line,
+                                    offset,
+                                    viewport,
+                                    line,
+                                    surface,
+                                    code_lens_style,
+                                );
+                                line += 1;
+                                if line >= viewport.height {
+                                    break 'outer;
+                                }
+                            }
                         } else {
                             let grapheme = Cow::from(grapheme);
 
@@ -363,6 +570,50 @@ impl EditorView {
         }
     }
 
+    /// Render the titles of the code lenses of `doc_line` on the virtual line `row` above it,
+    /// indented like the line itself.
+    fn render_code_lenses(
+        doc: &Document,
+        doc_line: usize,
+        offset: Position,
+        viewport: Rect,
+        row: u16,
+        surface: &mut Surface,
+        style: Style,
+    ) {
+        use helix_core::graphemes::grapheme_width;
+
+        let mut visual_x = 0u16;
+        for ch in doc.text().line(doc_line).chars() {
+            match ch {
+                ' ' => visual_x += 1,
+                '\t' => visual_x += doc.tab_width() as u16,
+                _ => break,
+            }
+        }
+
+        let titles: Vec<_> = doc
+            .code_lenses_on_line(doc_line)
+            .iter()
+            .filter_map(|lens| lens.lens.command.as_ref())
+            .map(|command| command.title.as_str())
+            .collect();
+        for grapheme in titles.join(" | ").graphemes(true) {
+            let width = grapheme_width(grapheme) as u16;
+            if visual_x >= offset.col as u16
+                && visual_x + width <= viewport.width + offset.col as u16
+            {
+                surface.set_string(
+                    viewport.x + visual_x - offset.col as u16,
+                    viewport.y + row,
+                    grapheme,
+                    style,
+                );
+            }
+            visual_x = visual_x.saturating_add(width);
+        }
+    }
+
     /// Render brace match, etc (meant for the focused view only)
     pub fn render_focused_view_elements(
         view: &View,
@@ -437,8 +688,13 @@ impl EditorView {
             .map(|range| range.cursor_line(text))
             .collect();
 
-        for (i, line) in (view.offset.row..(last_line + 1)).enumerate() {
+        let mut i = 0;
+        for line in view.offset.row..(last_line + 1) {
             use helix_core::diagnostic::Severity;
+            // leave the rows of code lens lines empty
+            if doc.has_code_lens_line(line) {
+                i += 1;
+            }
             if let Some(diagnostic) = doc.diagnostics().iter().find(|d| d.line == line) {
                 surface.set_stringn(
                     viewport.x,
@@ -482,6 +738,7 @@ impl EditorView {
                     linenr
                 },
             );
+            i += 1;
         }
     }
 
@@ -557,9 +814,10 @@ impl EditorView {
             Mode::Select => "SEL",
             Mode::Normal => "NOR",
         };
+        // show the spinner of the first language server that is busy
         let progress = doc
-            .language_server()
-            .and_then(|srv| {
+            .language_servers()
+            .find_map(|srv| {
                 self.spinners
                     .get(srv.id())
                     .and_then(|spinner| spinner.frame())
@@ -581,7 +839,18 @@ impl EditorView {
         if let Some(path) = doc.relative_path() {
             let path = path.to_string_lossy();
 
-            let title = format!("{}{}", path, if doc.is_modified() { "[+]" } else { "" });
+            let title = format!(
+                "{}{}{}",
+                path,
+                if doc.is_modified() { "[+]" } else { "" },
+                if doc.deleted_on_disk() {
+                    "[deleted on disk]"
+                } else if doc.changed_on_disk() {
+                    "[changed on disk]"
+                } else {
+                    ""
+                }
+            );
             surface.set_stringn(
                 viewport.x + 8,
                 viewport.y,
@@ -596,7 +865,15 @@ impl EditorView {
         //-------------------------------
 
         // Compute the individual info strings.
-        let diag_count = format!("{}", doc.diagnostics().len());
+        use helix_core::diagnostic::Severity;
+        let (warnings, errors) =
+            doc.diagnostics()
+                .iter()
+                .fold((0, 0), |(warnings, errors), diag| match diag.severity {
+                    Some(Severity::Error) => (warnings, errors + 1),
+                    Some(Severity::Warning) | None => (warnings + 1, errors),
+                    _ => (warnings, errors),
+                });
         // let indent_info = match doc.indent_style {
         //     IndentStyle::Tabs => "tabs",
         //     IndentStyle::Spaces(1) => "spaces:1",
@@ -619,20 +896,23 @@ impl EditorView {
             format!("{}:{}", pos.row + 1, pos.col + 1) // convert to 1-indexing
         };
 
-        // Render them to the status line together.
-        let right_side_text = format!(
-            "{}    {} ",
-            &diag_count[..diag_count.len().min(4)],
-            // indent_info,
-            position_info
-        );
-        let text_len = right_side_text.len() as u16;
-        surface.set_string(
-            viewport.x + viewport.width.saturating_sub(text_len),
-            viewport.y,
-            right_side_text,
-            style,
-        );
+        // Render them to the status line together, right to left.
+        let mut x = viewport.x + viewport.width;
+        let mut draw_right = |text: String, style: Style| {
+            x = x.saturating_sub(text.width() as u16);
+            surface.set_string(x, viewport.y, text, style);
+        };
+
+        draw_right(format!("   {} ", position_info), style);
+        // indent_info
+        if errors > 0 {
+            draw_right(format!(" {}", errors), style);
+            draw_right("●".to_string(), style.patch(theme.get("error")));
+        }
+        if warnings > 0 {
+            draw_right(format!(" {}", warnings), style);
+            draw_right("●".to_string(), style.patch(theme.get("warning")));
+        }
     }
 
     /// Handle events by looking them up in `self.keymaps`. Returns None
@@ -658,11 +938,14 @@ impl EditorView {
     }
 
     fn insert_mode(&mut self, cx: &mut commands::Context, event: KeyEvent) {
+        // the character that went into the document, for on type formatting
+        let mut typed = None;
         if let Some(keyresult) = self.handle_keymap_event(Mode::Insert, cx, event) {
             match keyresult.kind {
                 KeymapResultKind::NotFound => {
                     if let Some(ch) = event.char() {
-                        commands::insert::insert_char(cx, ch)
+                        commands::insert::insert_char(cx, ch);
+                        typed = Some(ch);
                     }
                 }
                 KeymapResultKind::Cancelled(pending) => {
@@ -681,10 +964,27 @@ impl EditorView {
                 }
                 _ => unreachable!(),
             }
+        } else if event == key!(Enter) {
+            typed = Some('\n');
+        }
+
+        if let Some(ch) = typed {
+            commands::insert::format_on_type(cx, ch);
         }
     }
 
     fn command_mode(&mut self, mode: Mode, cxt: &mut commands::Context, event: KeyEvent) {
+        // the key was already recorded, remember where its command starts so stopping a
+        // recording can leave out all of its keys
+        if self.keymaps.pending().is_empty()
+            && cxt.editor.count.is_none()
+            && cxt.editor.selected_register.is_none()
+        {
+            if let Some(recording) = &mut cxt.editor.macro_recording {
+                recording.start_command();
+            }
+        }
+
         match event {
             // count handling
             key!(i @ '0'..='9') => {
@@ -719,18 +1019,63 @@ impl EditorView {
         }
     }
 
-    pub fn set_completion(
-        &mut self,
-        items: Vec<helix_lsp::lsp::CompletionItem>,
-        offset_encoding: helix_lsp::OffsetEncoding,
-        trigger_offset: usize,
-        size: Rect,
-    ) {
-        let mut completion = Completion::new(items, offset_encoding, trigger_offset);
+    pub fn set_completion(&mut self, editor: &Editor, mut completion: Completion, size: Rect) {
+        // filter by whatever was typed while the request was in flight
+        completion.recompute_filter(editor);
+        if completion.is_empty() {
+            self.completion = None;
+            return;
+        }
         // TODO : propagate required size on resize to completion too
         completion.required_size((size.width, size.height));
         self.completion = Some(completion);
     }
+
+    pub fn clear_completion(&mut self) {
+        self.completion = None;
+    }
+
+    pub fn set_signature_help(&mut self, popup: Popup<Markdown>) {
+        self.signature_help = Some(popup);
+    }
+
+    pub fn clear_signature_help(&mut self) {
+        self.signature_help = None;
+    }
+
+    /// Called by the application once no key has been pressed for `idle_timeout`. Requests
+    /// completion if a long enough word is being typed in insert mode.
+    pub fn handle_idle_timeout(&mut self, cx: &mut Context) -> EventResult {
+        if self.completion.is_some() {
+            return EventResult::Ignored;
+        }
+
+        let is_word_key = matches!(
+            self.last_insert.1.last(),
+            Some(KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers,
+            }) if (modifiers.is_empty() || *modifiers == KeyModifiers::SHIFT)
+                && helix_core::chars::char_is_word(*ch)
+        );
+
+        let (_, doc) = current!(cx.editor);
+        if doc.mode() != Mode::Insert || !is_word_key {
+            return EventResult::Ignored;
+        }
+
+        let mut cxt = commands::Context {
+            editor: cx.editor,
+            count: None,
+            register: None,
+            callback: None,
+            on_next_key_callback: None,
+            jobs: cx.jobs,
+        };
+        commands::idle_completion(&mut cxt);
+
+        EventResult::Consumed(None)
+    }
 }
 
 impl EditorView {
@@ -946,7 +1291,20 @@ impl Component for EditorView {
                                 // lastly we recalculate completion
                                 if let Some(completion) = &mut self.completion {
                                     completion.update(&mut cxt);
-                                    if completion.is_empty() {
+
+                                    let (view, doc) = current!(cxt.editor);
+                                    let cursor = doc
+                                        .selection(view.id)
+                                        .primary()
+                                        .cursor(doc.text().slice(..));
+
+                                    if cursor < completion.start_offset() {
+                                        // deleted past the start of the completed word
+                                        self.completion = None;
+                                    } else if completion.is_incomplete() {
+                                        // the server only sent part of the items, ask again
+                                        commands::request_completion(&mut cxt, false);
+                                    } else if completion.is_empty() {
                                         self.completion = None;
                                     }
                                 }
@@ -987,8 +1345,9 @@ impl Component for EditorView {
                         self.last_insert.1.clear();
                     }
                     (Mode::Insert, Mode::Normal) => {
-                        // if exiting insert mode, remove completion
+                        // if exiting insert mode, remove completion and signature help
                         self.completion = None;
+                        self.signature_help = None;
                     }
                     _ => (),
                 }
@@ -1069,6 +1428,10 @@ impl Component for EditorView {
             );
         }
 
+        if let Some(signature_help) = self.signature_help.as_mut() {
+            signature_help.render(area, surface, cx);
+        }
+
         if let Some(completion) = self.completion.as_mut() {
             completion.render(area, surface, cx);
         }
@@ -1083,7 +1446,7 @@ impl Component for EditorView {
     }
 }
 
-fn canonicalize_key(key: &mut KeyEvent) {
+pub(crate) fn canonicalize_key(key: &mut KeyEvent) {
     if let KeyEvent {
         code: KeyCode::Char(_),
         modifiers: _,
@@ -2,7 +2,7 @@ mod completion;
 pub(crate) mod editor;
 mod info;
 mod markdown;
-mod menu;
+pub mod menu;
 mod picker;
 mod popup;
 mod prompt;
@@ -21,7 +21,7 @@ pub use text::Text;
 
 use helix_core::regex::Regex;
 use helix_core::regex::RegexBuilder;
-use helix_view::{Document, Editor, View};
+use helix_view::{Document, View};
 
 use std::path::PathBuf;
 
@@ -133,8 +133,8 @@ pub fn file_picker(root: PathBuf) -> FilePicker<PathBuf> {
                 .unwrap()
                 .into()
         },
-        move |editor: &mut Editor, path: &PathBuf, action| {
-            editor
+        move |cx: &mut crate::compositor::Context, path: &PathBuf, action| {
+            cx.editor
                 .open(path.into(), action)
                 .expect("editor.open failed");
         },
@@ -179,6 +179,14 @@ pub mod completers {
         names
     }
 
+    pub fn diagnostic_severity(input: &str) -> Vec<Completion> {
+        ["error", "warning", "info", "hint"]
+            .iter()
+            .filter(|severity| severity.starts_with(input))
+            .map(|severity| ((0..), Cow::from(*severity)))
+            .collect()
+    }
+
     pub fn filename(input: &str) -> Vec<Completion> {
         filename_impl(input, |entry| {
             let is_dir = entry.file_type().map_or(false, |entry| entry.is_dir());
@@ -39,7 +39,7 @@ impl<T> FilePicker<T> {
     pub fn new(
         options: Vec<T>,
         format_fn: impl Fn(&T) -> Cow<str> + 'static,
-        callback_fn: impl Fn(&mut Editor, &T, Action) + 'static,
+        callback_fn: impl Fn(&mut Context, &T, Action) + 'static,
         preview_fn: impl Fn(&Editor, &T) -> Option<FileLocation> + 'static,
     ) -> Self {
         Self {
@@ -49,6 +49,20 @@ impl<T> FilePicker<T> {
         }
     }
 
+    /// See [`Picker::set_expand_fn`].
+    pub fn with_expand_fn(mut self, expand_fn: impl Fn(&mut Context, &T, usize) + 'static) -> Self {
+        self.picker.set_expand_fn(expand_fn);
+        self
+    }
+
+    pub fn options(&self) -> &[T] {
+        self.picker.options()
+    }
+
+    pub fn insert_options(&mut self, index: usize, options: Vec<T>) {
+        self.picker.insert_options(index, options)
+    }
+
     fn current_file(&self, editor: &Editor) -> Option<FileLocation> {
         self.picker
             .selection()
@@ -147,6 +161,8 @@ impl<T: 'static> Component for FilePicker<T> {
                 surface,
                 &cx.editor.theme,
                 highlights,
+                &[],
+                false,
             );
 
             // highlight the line
@@ -195,7 +211,11 @@ pub struct Picker<T> {
     render_centered: bool,
 
     format_fn: Box<dyn Fn(&T) -> Cow<str>>,
-    callback_fn: Box<dyn Fn(&mut Editor, &T, Action)>,
+    callback_fn: Box<dyn Fn(&mut Context, &T, Action)>,
+    /// Called with the selected option and its index to load the option's children, which
+    /// makes the picker a tree.
+    #[allow(clippy::type_complexity)]
+    expand_fn: Option<Box<dyn Fn(&mut Context, &T, usize)>>,
 }
 
 impl<T> Picker<T> {
@@ -203,7 +223,7 @@ impl<T> Picker<T> {
         render_centered: bool,
         options: Vec<T>,
         format_fn: impl Fn(&T) -> Cow<str> + 'static,
-        callback_fn: impl Fn(&mut Editor, &T, Action) + 'static,
+        callback_fn: impl Fn(&mut Context, &T, Action) + 'static,
     ) -> Self {
         let prompt = Prompt::new(
             "".into(),
@@ -224,6 +244,7 @@ impl<T> Picker<T> {
             render_centered,
             format_fn: Box::new(format_fn),
             callback_fn: Box::new(callback_fn),
+            expand_fn: None,
         };
 
         // TODO: scoring on empty input should just use a fastpath
@@ -263,12 +284,56 @@ impl<T> Picker<T> {
                         .map(|score| (index, score))
                 }),
         );
-        matches.sort_unstable_by_key(|(_, score)| -score);
+        // stable: with an empty query every option scores the same, and the children inserted
+        // by `expand_fn` have to stay right below their parent instead of being shuffled around
+        matches.sort_by_key(|(_, score)| -score);
 
         // reset cursor position
         self.cursor = 0;
     }
 
+    pub fn set_expand_fn(&mut self, expand_fn: impl Fn(&mut Context, &T, usize) + 'static) {
+        self.expand_fn = Some(Box::new(expand_fn));
+    }
+
+    pub fn options(&self) -> &[T] {
+        &self.options
+    }
+
+    /// Insert `options` right after the option at `index`, e.g. the children of a tree node. The
+    /// cursor stays on the option it was on.
+    pub fn insert_options(&mut self, index: usize, options: Vec<T>) {
+        let selected = self.matches.get(self.cursor).map(|(selected, _)| *selected);
+        let count = options.len();
+        let index = (index + 1).min(self.options.len());
+        self.options.splice(index..index, options);
+
+        // shift the saved filter past the new options and let them through it
+        if !self.filters.is_empty() {
+            for filter in &mut self.filters {
+                if *filter >= index {
+                    *filter += count;
+                }
+            }
+            self.filters.extend(index..index + count);
+            self.filters.sort_unstable();
+        }
+
+        self.score();
+        if let Some(selected) = selected {
+            let selected = if selected >= index {
+                selected + count
+            } else {
+                selected
+            };
+            self.cursor = self
+                .matches
+                .iter()
+                .position(|(index, _)| *index == selected)
+                .unwrap_or(0);
+        }
+    }
+
     pub fn move_up(&mut self) {
         let len = self.matches.len();
         let pos = ((self.cursor + len.saturating_sub(1)) % len) % len;
@@ -317,7 +382,7 @@ impl<T: 'static> Component for Picker<T> {
             _ => return EventResult::Ignored,
         };
 
-        let close_fn = EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor| {
+        let close_fn = EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _| {
             // remove the layer
             compositor.last_picker = compositor.pop();
         })));
@@ -363,7 +428,7 @@ impl<T: 'static> Component for Picker<T> {
                 ..
             } => {
                 if let Some(option) = self.selection() {
-                    (self.callback_fn)(&mut cx.editor, option, Action::Replace);
+                    (self.callback_fn)(cx, option, Action::Replace);
                 }
                 return close_fn;
             }
@@ -372,7 +437,7 @@ impl<T: 'static> Component for Picker<T> {
                 modifiers: KeyModifiers::CONTROL,
             } => {
                 if let Some(option) = self.selection() {
-                    (self.callback_fn)(&mut cx.editor, option, Action::HorizontalSplit);
+                    (self.callback_fn)(cx, option, Action::HorizontalSplit);
                 }
                 return close_fn;
             }
@@ -381,7 +446,7 @@ impl<T: 'static> Component for Picker<T> {
                 modifiers: KeyModifiers::CONTROL,
             } => {
                 if let Some(option) = self.selection() {
-                    (self.callback_fn)(&mut cx.editor, option, Action::VerticalSplit);
+                    (self.callback_fn)(cx, option, Action::VerticalSplit);
                 }
                 return close_fn;
             }
@@ -391,6 +456,16 @@ impl<T: 'static> Component for Picker<T> {
             } => {
                 self.save_filter();
             }
+            KeyEvent {
+                code: KeyCode::Char('l'),
+                modifiers: KeyModifiers::CONTROL,
+            } => {
+                if let (Some(expand_fn), Some((index, _))) =
+                    (&self.expand_fn, self.matches.get(self.cursor))
+                {
+                    expand_fn(cx, &self.options[*index], *index);
+                }
+            }
             _ => {
                 if let EventResult::Consumed(_) = self.prompt.handle_event(event, cx) {
                     // TODO: recalculate only if pattern changed
@@ -11,6 +11,8 @@ use helix_view::graphics::Rect;
 pub struct Popup<T: Component> {
     contents: T,
     position: Option<Position>,
+    /// Place the popup above the cursor if there's enough room, instead of below.
+    prefer_above: bool,
     size: (u16, u16),
     scroll: usize,
 }
@@ -20,6 +22,7 @@ impl<T: Component> Popup<T> {
         Self {
             contents,
             position: None,
+            prefer_above: false,
             size: (0, 0),
             scroll: 0,
         }
@@ -29,6 +32,10 @@ impl<T: Component> Popup<T> {
         self.position = pos;
     }
 
+    pub fn set_prefer_above(&mut self, prefer_above: bool) {
+        self.prefer_above = prefer_above;
+    }
+
     pub fn get_rel_position(&mut self, viewport: Rect, cx: &Context) -> (u16, u16) {
         let position = self
             .position
@@ -47,9 +54,9 @@ impl<T: Component> Popup<T> {
             rel_x = rel_x.saturating_sub((rel_x + width).saturating_sub(viewport.width));
         }
 
-        // TODO: be able to specify orientation preference. We want above for most popups, below
-        // for menus/autocomplete.
-        if viewport.height > rel_y + height {
+        if self.prefer_above && rel_y >= height {
+            rel_y -= height // position above point
+        } else if viewport.height > rel_y + height {
             rel_y += 1 // position below point
         } else {
             rel_y = rel_y.saturating_sub(height) // position above point
@@ -90,20 +97,24 @@ impl<T: Component> Component for Popup<T> {
             _ => return EventResult::Ignored,
         };
 
-        let close_fn = EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor| {
+        let close_fn = EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _| {
             // remove the layer
             compositor.pop();
         })));
 
         match key {
-            // esc or ctrl-c aborts the completion and closes the menu
+            // esc or ctrl-c aborts the completion and closes the menu. The contents still see the
+            // key, so a menu can report the abort.
             KeyEvent {
                 code: KeyCode::Esc, ..
             }
             | KeyEvent {
                 code: KeyCode::Char('c'),
                 modifiers: KeyModifiers::CONTROL,
-            } => close_fn,
+            } => {
+                self.contents.handle_event(event, cx);
+                close_fn
+            }
 
             KeyEvent {
                 code: KeyCode::Char('d'),
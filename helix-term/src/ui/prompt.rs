@@ -74,6 +74,14 @@ impl Prompt {
         }
     }
 
+    /// Pre-fill the prompt input, placing the cursor at the end of it.
+    pub fn with_line(mut self, line: String) -> Self {
+        self.cursor = line.len();
+        self.completion = (self.completion_fn)(&line);
+        self.line = line;
+        self
+    }
+
     /// Compute the cursor position after applying movement
     /// Taken from: https://github.com/wez/wezterm/blob/e0b62d07ca9bf8ce69a61e30a3c20e7abc48ce7e/termwiz/src/lineedit/mod.rs#L516-L611
     fn eval_movement(&self, movement: Movement) -> usize {
@@ -394,7 +402,7 @@ impl Component for Prompt {
             _ => return EventResult::Ignored,
         };
 
-        let close_fn = EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor| {
+        let close_fn = EventResult::Consumed(Some(Box::new(|compositor: &mut Compositor, _| {
             // remove the layer
             compositor.pop();
         })));
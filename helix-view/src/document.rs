@@ -12,7 +12,8 @@ use helix_core::{
     history::History,
     indent::{auto_detect_indent_style, IndentStyle},
     line_ending::auto_detect_line_ending,
-    syntax::{self, LanguageConfiguration},
+    snippet::ActiveSnippet,
+    syntax::{self, LanguageConfiguration, LanguageServerFeature},
     ChangeSet, Diagnostic, LineEnding, Rope, RopeBuilder, Selection, State, Syntax, Transaction,
     DEFAULT_LINE_ENDING,
 };
@@ -53,6 +54,105 @@ impl FromStr for Mode {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InlayHintKind {
+    Type,
+    Parameter,
+    Other,
+}
+
+/// Virtual text shown before the char at `char_idx`. It is only rendered and never becomes part of
+/// the document text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlayHint {
+    pub char_idx: usize,
+    pub text: String,
+    pub kind: InlayHintKind,
+    /// Whether to leave a blank cell before and after the hint.
+    pub padding_left: bool,
+    pub padding_right: bool,
+}
+
+impl InlayHint {
+    /// Number of cells the hint takes up when rendered, including its padding.
+    pub fn width(&self) -> usize {
+        use helix_core::{graphemes::grapheme_width, unicode::segmentation::UnicodeSegmentation};
+
+        let text: usize = self.text.graphemes(true).map(grapheme_width).sum();
+        text + self.padding_left as usize + self.padding_right as usize
+    }
+}
+
+/// Inlay hints of one view, along with what they were requested for.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentInlayHints {
+    /// Document version the latest request was made for.
+    pub version: i32,
+    /// Inclusive line range of the latest request.
+    pub first_line: usize,
+    pub last_line: usize,
+    /// Sorted by `char_idx`.
+    pub hints: Vec<InlayHint>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DocumentHighlightKind {
+    Text,
+    Read,
+    Write,
+}
+
+/// A reference to the symbol under the cursor, as reported by `textDocument/documentHighlight`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentHighlight {
+    pub start: usize,
+    pub end: usize,
+    pub kind: DocumentHighlightKind,
+}
+
+/// A token reported by `textDocument/semanticTokens`. `token_type` and `token_modifiers` index
+/// into the legend of the language server that sent it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub start: usize,
+    pub end: usize,
+    pub token_type: u32,
+    pub token_modifiers: u32,
+}
+
+/// Semantic tokens of the document, along with the raw result they were decoded from.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentSemanticTokens {
+    pub language_server_id: usize,
+    /// Document version the latest request was made for.
+    pub version: i32,
+    /// Identifies the result for `textDocument/semanticTokens/full/delta` requests.
+    pub result_id: Option<String>,
+    /// The encoded tokens as sent by the server, which deltas apply to.
+    pub data: Vec<lsp::SemanticToken>,
+    /// Sorted by `start`.
+    pub tokens: Vec<SemanticToken>,
+}
+
+/// A code lens, shown on a virtual line above the line it starts on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeLens {
+    pub char_idx: usize,
+    /// The lens as sent by the server, to be passed back when resolving it. Only `command` is
+    /// kept up to date.
+    pub lens: lsp::CodeLens,
+}
+
+/// Code lenses of the document, along with what they were requested for.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentCodeLenses {
+    pub language_server_id: usize,
+    /// Document version the latest request was made for.
+    pub version: i32,
+    /// Sorted by `char_idx`.
+    pub lenses: Vec<CodeLens>,
+}
+
 // toml deserializer doesn't seem to recognize string as enum
 impl<'de> Deserialize<'de> for Mode {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -75,6 +175,8 @@ pub struct Document {
     /// Current editing mode.
     pub mode: Mode,
     pub restore_cursor: bool,
+    /// Snippet inserted by a completion whose tabstops can still be jumped to.
+    pub active_snippet: Option<ActiveSnippet>,
 
     /// Current indent style.
     pub indent_style: IndentStyle,
@@ -95,11 +197,30 @@ pub struct Document {
     // it back as it separated from the edits. We could split out the parts manually but that will
     // be more troublesome.
     history: Cell<History>,
+    /// While set, changes keep composing into `changes` instead of being committed to history.
+    history_held: bool,
     last_saved_revision: usize,
+    /// Contents of the file as last read or written, to tell our own saves apart from changes
+    /// made by other programs.
+    saved_text: Rope,
+    /// Set when the file changed on disk while the buffer had unsaved changes.
+    changed_on_disk: bool,
+    /// Set when the file was deleted on disk, until it's written again or shows up again.
+    deleted_on_disk: bool,
     version: i32, // should be usize?
 
     diagnostics: Vec<Diagnostic>,
-    language_server: Option<Arc<helix_lsp::Client>>,
+    /// Document version diagnostics were last pulled for, by language server.
+    diagnostics_pulled_versions: HashMap<usize, i32>,
+    language_servers: Vec<Arc<helix_lsp::Client>>,
+
+    /// Inlay hints of each view the document is shown in.
+    pub(crate) inlay_hints: HashMap<ViewId, DocumentInlayHints>,
+    /// References to the symbol under the primary cursor of each view, sorted by `start`.
+    pub(crate) document_highlights: HashMap<ViewId, Vec<DocumentHighlight>>,
+    /// Highlighting provided by a language server on top of the syntax tree.
+    pub(crate) semantic_tokens: Option<DocumentSemanticTokens>,
+    pub(crate) code_lenses: Option<DocumentCodeLenses>,
 }
 
 use std::{fmt, mem};
@@ -323,20 +444,30 @@ impl Document {
             id: DocumentId::default(),
             path: None,
             encoding,
+            saved_text: text.clone(),
+            changed_on_disk: false,
+            deleted_on_disk: false,
             text,
             selections: HashMap::default(),
             indent_style: IndentStyle::Spaces(4),
             mode: Mode::Normal,
             restore_cursor: false,
+            active_snippet: None,
             syntax: None,
             language: None,
             changes,
             old_state,
             diagnostics: Vec::new(),
+            diagnostics_pulled_versions: HashMap::new(),
             version: 0,
             history: Cell::new(History::default()),
+            history_held: false,
             last_saved_revision: 0,
-            language_server: None,
+            language_servers: Vec::new(),
+            inlay_hints: HashMap::new(),
+            document_highlights: HashMap::new(),
+            semantic_tokens: None,
+            code_lenses: None,
             line_ending: DEFAULT_LINE_ENDING,
         }
     }
@@ -386,12 +517,14 @@ impl Document {
     /// If supported, returns the changes that should be applied to this document in order
     /// to format it nicely.
     pub fn format(&self) -> Option<impl Future<Output = LspFormatting> + 'static> {
-        if let Some(language_server) = self.language_server() {
+        if let Some(language_server) =
+            self.language_server_with_feature(LanguageServerFeature::Format)
+        {
             let text = self.text.clone();
             let offset_encoding = language_server.offset_encoding();
             let request = language_server.text_document_formatting(
                 self.identifier(),
-                lsp::FormattingOptions::default(),
+                self.formatting_options(),
                 None,
             )?;
 
@@ -440,10 +573,13 @@ impl Document {
         let path = self.path.clone().expect("Can't save with no path set!");
         let identifier = self.identifier();
 
-        let language_server = self.language_server.clone();
+        let language_servers = self.language_servers.clone();
 
         // mark changes up to now as saved
         self.reset_modified();
+        self.saved_text = text.clone();
+        self.changed_on_disk = false;
+        self.deleted_on_disk = false;
 
         let encoding = self.encoding;
 
@@ -471,12 +607,12 @@ impl Document {
             let mut file = File::create(path).await?;
             to_writer(&mut file, encoding, &text).await?;
 
-            if let Some(language_server) = language_server {
+            for language_server in language_servers {
                 if !language_server.is_initialized() {
-                    return Ok(());
+                    continue;
                 }
                 if let Some(notification) =
-                    language_server.text_document_did_save(identifier, &text)
+                    language_server.text_document_did_save(identifier.clone(), &text)
                 {
                     notification.await?;
                 }
@@ -523,6 +659,64 @@ impl Document {
         let mut file = std::fs::File::open(path.unwrap())?;
         let (rope, ..) = from_reader(&mut file, Some(encoding))?;
 
+        self.reload_from(rope, view_id);
+        Ok(())
+    }
+
+    /// Check the file after it changed on disk. Reloads the document if it has no unsaved changes,
+    /// and otherwise marks it as [changed on disk](Self::changed_on_disk), or as
+    /// [deleted on disk](Self::deleted_on_disk) if the file is gone. Returns whether it was
+    /// reloaded.
+    pub fn file_changed(&mut self, view_id: ViewId) -> Result<bool, Error> {
+        let path = match self.path() {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        if !path.exists() {
+            // there's nothing to reload from, the buffer is the only copy left
+            self.deleted_on_disk = true;
+            self.changed_on_disk = false;
+            return Ok(false);
+        }
+
+        let mut file = std::fs::File::open(path)?;
+        let (rope, ..) = from_reader(&mut file, Some(self.encoding))?;
+        self.deleted_on_disk = false;
+
+        // our own save, or the same contents written again
+        if rope == self.saved_text || rope == self.text {
+            self.saved_text = rope;
+            return Ok(false);
+        }
+
+        if self.is_modified() {
+            self.saved_text = rope;
+            self.changed_on_disk = true;
+            return Ok(false);
+        }
+
+        self.reload_from(rope, view_id);
+        Ok(true)
+    }
+
+    /// Whether the file changed on disk since it was last read or written while the buffer had
+    /// unsaved changes.
+    pub fn changed_on_disk(&self) -> bool {
+        self.changed_on_disk
+    }
+
+    /// Whether the file was deleted on disk since it was last read or written.
+    pub fn deleted_on_disk(&self) -> bool {
+        self.deleted_on_disk
+    }
+
+    /// Mark the file as deleted, for deletions the editor made itself.
+    pub fn mark_deleted_on_disk(&mut self) {
+        self.deleted_on_disk = true;
+        self.changed_on_disk = false;
+    }
+
+    fn reload_from(&mut self, rope: Rope, view_id: ViewId) {
         // Calculate the difference between the buffer and source text, and apply it.
         // This is not considered a modification of the contents of the file regardless
         // of the encoding.
@@ -530,10 +724,10 @@ impl Document {
         self.apply(&transaction, view_id);
         self.append_changes_to_history(view_id);
         self.reset_modified();
+        self.saved_text = rope;
+        self.changed_on_disk = false;
 
         self.detect_indent_and_line_ending();
-
-        Ok(())
     }
 
     /// Sets the [`Document`]'s encoding with the encoding correspondent to `label`.
@@ -595,9 +789,9 @@ impl Document {
         self.set_language(theme, language_config);
     }
 
-    /// Set the LSP.
-    pub fn set_language_server(&mut self, language_server: Option<Arc<helix_lsp::Client>>) {
-        self.language_server = language_server;
+    /// Set the language servers of the document.
+    pub fn set_language_servers(&mut self, language_servers: Vec<Arc<helix_lsp::Client>>) {
+        self.language_servers = language_servers;
     }
 
     /// Select text within the [`Document`].
@@ -623,6 +817,50 @@ impl Document {
                     .ensure_invariants(self.text.slice(..));
             }
 
+            if let Some(snippet) = &mut self.active_snippet {
+                snippet.map(transaction.changes());
+            }
+
+            // keep hints next to the text they annotate until fresh ones arrive
+            for inlay_hints in self.inlay_hints.values_mut() {
+                use helix_core::Assoc;
+                let changes = transaction.changes();
+                for hint in &mut inlay_hints.hints {
+                    hint.char_idx = changes.map_pos(hint.char_idx, Assoc::After);
+                }
+            }
+
+            for highlights in self.document_highlights.values_mut() {
+                use helix_core::Assoc;
+                let changes = transaction.changes();
+                for highlight in highlights {
+                    highlight.start = changes.map_pos(highlight.start, Assoc::After);
+                    highlight.end = changes.map_pos(highlight.end, Assoc::Before);
+                }
+            }
+
+            // keep the tokens on the text they color until the server sends an update
+            if let Some(semantic_tokens) = &mut self.semantic_tokens {
+                use helix_core::Assoc;
+                let changes = transaction.changes();
+                for token in &mut semantic_tokens.tokens {
+                    token.start = changes.map_pos(token.start, Assoc::After);
+                    token.end = changes.map_pos(token.end, Assoc::Before);
+                }
+                semantic_tokens
+                    .tokens
+                    .retain(|token| token.start < token.end);
+            }
+
+            // keep the lenses on the lines they annotate until the server sends new ones
+            if let Some(code_lenses) = &mut self.code_lenses {
+                use helix_core::Assoc;
+                let changes = transaction.changes();
+                for lens in &mut code_lenses.lenses {
+                    lens.char_idx = changes.map_pos(lens.char_idx, Assoc::After);
+                }
+            }
+
             // if specified, the current selection should instead be replaced by transaction.selection
             if let Some(selection) = transaction.selection() {
                 self.selections.insert(
@@ -653,18 +891,14 @@ impl Document {
             //     diagnostic.line = self.text.char_to_line(diagnostic.range.start);
             // }
 
-            // emit lsp notification
-            if let Some(language_server) = self.language_server() {
-                let notify = language_server.text_document_did_change(
+            // queue lsp notification, sent once per event loop tick
+            for language_server in self.language_servers() {
+                language_server.text_document_did_change(
                     self.versioned_identifier(),
                     &old_doc,
                     self.text(),
                     transaction.changes(),
                 );
-
-                if let Some(notify) = notify {
-                    tokio::spawn(notify);
-                } //.expect("failed to emit textDocument/didChange");
             }
         }
         success
@@ -742,7 +976,7 @@ impl Document {
 
     /// Commit pending changes to history
     pub fn append_changes_to_history(&mut self, view_id: ViewId) {
-        if self.changes.is_empty() {
+        if self.changes.is_empty() || self.history_held {
             return;
         }
 
@@ -761,6 +995,21 @@ impl Document {
         self.history.set(history);
     }
 
+    /// Stop committing changes to history until [`Self::release_history`] is called, so that
+    /// everything applied in between is undone as a single step.
+    pub fn hold_history(&mut self) {
+        self.history_held = true;
+    }
+
+    /// Resume committing changes to history. Changes held back so far are committed as one
+    /// revision, unless we're still in insert mode in which case leaving it will commit them.
+    pub fn release_history(&mut self, view_id: ViewId) {
+        self.history_held = false;
+        if self.mode != Mode::Insert {
+            self.append_changes_to_history(view_id);
+        }
+    }
+
     pub fn id(&self) -> DocumentId {
         self.id
     }
@@ -803,18 +1052,47 @@ impl Document {
         self.version
     }
 
-    pub fn language_server(&self) -> Option<&helix_lsp::Client> {
-        let server = self.language_server.as_deref();
-        let initialized = server
-            .map(|server| server.is_initialized())
-            .unwrap_or(false);
+    /// Language servers of the document that finished initializing.
+    pub fn language_servers(&self) -> impl Iterator<Item = &helix_lsp::Client> {
+        // only resolve language servers that are initialized
+        self.language_servers
+            .iter()
+            .map(|server| server.as_ref())
+            .filter(|server| server.is_initialized())
+    }
+
+    /// The first language server that supports `feature`.
+    pub fn language_server_with_feature(
+        &self,
+        feature: LanguageServerFeature,
+    ) -> Option<&helix_lsp::Client> {
+        self.language_servers()
+            .find(|server| server.supports_feature(feature))
+    }
 
-        // only resolve language_server if it's initialized
-        if initialized {
-            server
-        } else {
-            None
+    /// Detach a language server that was stopped, dropping everything it provided. Inlay hints
+    /// and highlights are dropped as well and requested again from the remaining servers.
+    pub fn detach_language_server(&mut self, language_server_id: usize) {
+        self.language_servers
+            .retain(|server| server.id() != language_server_id);
+        self.set_diagnostics(language_server_id, Vec::new());
+        self.clear_diagnostics_pulled_version(language_server_id);
+        self.inlay_hints.clear();
+        self.document_highlights.clear();
+
+        if matches!(&self.semantic_tokens, Some(tokens) if tokens.language_server_id == language_server_id)
+        {
+            self.semantic_tokens = None;
         }
+        if matches!(&self.code_lenses, Some(lenses) if lenses.language_server_id == language_server_id)
+        {
+            self.code_lenses = None;
+        }
+    }
+
+    /// Whether the language server with the given id is attached to the document.
+    pub fn supports_language_server(&self, id: usize) -> bool {
+        self.language_servers.iter().any(|server| server.id() == id)
     }
 
     #[inline]
@@ -831,6 +1109,19 @@ impl Document {
             .map_or(4, |config| config.tab_width) // fallback to 4 columns
     }
 
+    /// Options for formatting requests, following the document's indentation.
+    pub fn formatting_options(&self) -> lsp::FormattingOptions {
+        let (tab_size, insert_spaces) = match self.indent_style {
+            IndentStyle::Tabs => (self.tab_width() as u32, false),
+            IndentStyle::Spaces(width) => (width as u32, true),
+        };
+        lsp::FormattingOptions {
+            tab_size,
+            insert_spaces,
+            ..Default::default()
+        }
+    }
+
     /// Returns a string containing a single level of indentation.
     ///
     /// TODO: we might not need this function anymore, since the information
@@ -892,8 +1183,96 @@ impl Document {
         &self.diagnostics
     }
 
-    pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
-        self.diagnostics = diagnostics;
+    pub fn inlay_hints(&self, view_id: ViewId) -> Option<&DocumentInlayHints> {
+        self.inlay_hints.get(&view_id)
+    }
+
+    pub fn set_inlay_hints(&mut self, view_id: ViewId, inlay_hints: DocumentInlayHints) {
+        self.inlay_hints.insert(view_id, inlay_hints);
+    }
+
+    pub fn clear_inlay_hints(&mut self) {
+        self.inlay_hints.clear();
+    }
+
+    pub fn document_highlights(&self, view_id: ViewId) -> &[DocumentHighlight] {
+        self.document_highlights
+            .get(&view_id)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    pub fn set_document_highlights(&mut self, view_id: ViewId, highlights: Vec<DocumentHighlight>) {
+        self.document_highlights.insert(view_id, highlights);
+    }
+
+    pub fn semantic_tokens(&self) -> Option<&DocumentSemanticTokens> {
+        self.semantic_tokens.as_ref()
+    }
+
+    pub fn semantic_tokens_mut(&mut self) -> Option<&mut DocumentSemanticTokens> {
+        self.semantic_tokens.as_mut()
+    }
+
+    pub fn set_semantic_tokens(&mut self, semantic_tokens: Option<DocumentSemanticTokens>) {
+        self.semantic_tokens = semantic_tokens;
+    }
+
+    pub fn code_lenses(&self) -> Option<&DocumentCodeLenses> {
+        self.code_lenses.as_ref()
+    }
+
+    pub fn code_lenses_mut(&mut self) -> Option<&mut DocumentCodeLenses> {
+        self.code_lenses.as_mut()
+    }
+
+    pub fn set_code_lenses(&mut self, code_lenses: Option<DocumentCodeLenses>) {
+        self.code_lenses = code_lenses;
+    }
+
+    /// Code lenses that start on `line`.
+    pub fn code_lenses_on_line(&self, line: usize) -> &[CodeLens] {
+        let lenses = match &self.code_lenses {
+            Some(code_lenses) if line < self.text.len_lines() => code_lenses.lenses.as_slice(),
+            _ => return &[],
+        };
+        let start = self.text.line_to_char(line);
+        let end = self.text.line_to_char(line + 1);
+        let from = lenses.partition_point(|lens| lens.char_idx < start);
+        let to = lenses.partition_point(|lens| lens.char_idx < end);
+        &lenses[from..to]
+    }
+
+    /// Whether `line` has resolved code lenses, which take up a virtual line above it.
+    pub fn has_code_lens_line(&self, line: usize) -> bool {
+        self.code_lenses_on_line(line)
+            .iter()
+            .any(|lens| lens.lens.command.is_some())
+    }
+
+    pub fn diagnostics_pulled_version(&self, language_server_id: usize) -> Option<i32> {
+        self.diagnostics_pulled_versions
+            .get(&language_server_id)
+            .copied()
+    }
+
+    pub fn set_diagnostics_pulled_version(&mut self, language_server_id: usize, version: i32) {
+        self.diagnostics_pulled_versions
+            .insert(language_server_id, version);
+    }
+
+    /// Forget which version diagnostics were pulled for, so they're pulled again.
+    pub fn clear_diagnostics_pulled_version(&mut self, language_server_id: usize) {
+        self.diagnostics_pulled_versions.remove(&language_server_id);
+    }
+
+    /// Replace the diagnostics reported by a language server, keeping those of other servers.
+    pub fn set_diagnostics(&mut self, language_server_id: usize, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics
+            .retain(|diagnostic| diagnostic.language_server_id != language_server_id);
+        self.diagnostics.extend(diagnostics);
+        self.diagnostics
+            .sort_unstable_by_key(|diagnostic| diagnostic.range.start);
     }
 }
 
@@ -908,6 +1287,58 @@ impl Default for Document {
 mod test {
     use super::*;
 
+    #[test]
+    fn inlay_hints_follow_changes() {
+        let text = Rope::from("let x = 1;");
+        let mut doc = Document::from(text, None);
+        let view = ViewId::default();
+        doc.set_selection(view, Selection::single(0, 0));
+        let hint = InlayHint {
+            char_idx: 5,
+            text: ": i32".into(),
+            kind: InlayHintKind::Type,
+            padding_left: false,
+            padding_right: false,
+        };
+        doc.set_inlay_hints(
+            view,
+            DocumentInlayHints {
+                hints: vec![hint],
+                ..Default::default()
+            },
+        );
+
+        let transaction =
+            Transaction::change(doc.text(), vec![(0, 0, Some("    ".into()))].into_iter());
+        doc.apply(&transaction, view);
+
+        assert_eq!(doc.text(), "    let x = 1;");
+        assert_eq!(doc.inlay_hints(view).unwrap().hints[0].char_idx, 9);
+    }
+
+    #[test]
+    fn deleted_files_are_marked() {
+        let path = std::env::temp_dir().join(format!("helix-deleted-{}.txt", std::process::id()));
+        std::fs::write(&path, "a\n").unwrap();
+        let mut doc = Document::open(&path, None, None, None).unwrap();
+        let view = ViewId::default();
+        doc.set_selection(view, Selection::single(0, 0));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!doc.file_changed(view).unwrap());
+        assert!(doc.deleted_on_disk());
+        assert!(!doc.changed_on_disk());
+        assert_eq!(doc.text(), "a\n");
+
+        // the file showing up again reloads the unmodified buffer
+        std::fs::write(&path, "b\n").unwrap();
+        assert!(doc.file_changed(view).unwrap());
+        assert!(!doc.deleted_on_disk());
+        assert_eq!(doc.text(), "b\n");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn changeset_to_changes_ignore_line_endings() {
         use helix_lsp::{lsp, Client, OffsetEncoding};
@@ -1,6 +1,9 @@
 use crate::{
     clipboard::{get_clipboard_provider, ClipboardProvider},
+    file_watcher::{FileChange, FileEvent, FileWatcher, Watcher},
     graphics::{CursorKind, Rect},
+    input::KeyEvent,
+    session::{self, FileState, JumpState, Session},
     theme::{self, Theme},
     tree::Tree,
     Document, DocumentId, View, ViewId,
@@ -8,11 +11,15 @@ use crate::{
 
 use futures_util::future;
 use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
     path::{Path, PathBuf},
+    pin::Pin,
     sync::Arc,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use tokio::time::{sleep, Instant, Sleep};
+
 use slotmap::SlotMap;
 
 use anyhow::Error;
@@ -21,8 +28,17 @@ pub use helix_core::diagnostic::Severity;
 pub use helix_core::register::Registers;
 use helix_core::syntax;
 use helix_core::Position;
+use helix_lsp::lsp;
+
+use serde::{Deserialize, Deserializer};
 
-use serde::Deserialize;
+fn deserialize_duration_millis<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let millis = u64::deserialize(deserializer)?;
+    Ok(Duration::from_millis(millis))
+}
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "kebab-case", default)]
@@ -43,6 +59,17 @@ pub struct Config {
     pub smart_case: bool,
     /// Automatic insertion of pairs to parentheses, brackets, etc. Defaults to true.
     pub auto_pairs: bool,
+    /// Time in milliseconds since the last keypress before idle actions, like requesting
+    /// completion, are triggered. Defaults to 400ms.
+    #[serde(deserialize_with = "deserialize_duration_millis")]
+    pub idle_timeout: Duration,
+    /// Minimum length of the word under the cursor before completion is requested automatically.
+    /// Defaults to 2.
+    pub completion_trigger_len: u8,
+    /// Show inlay hints from language servers, like inferred types. Defaults to false.
+    pub inlay_hints: bool,
+    /// Only list diagnostics at least this severe in the diagnostic pickers. Defaults to all.
+    pub diagnostics_picker_severity: Option<Severity>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
@@ -70,10 +97,52 @@ impl Default for Config {
             middle_click_paste: true,
             smart_case: true,
             auto_pairs: true,
+            idle_timeout: Duration::from_millis(400),
+            completion_trigger_len: 2,
+            inlay_hints: false,
+            diagnostics_picker_severity: None,
         }
     }
 }
 
+/// Keys pressed while recording a macro into a register.
+#[derive(Debug)]
+pub struct MacroRecording {
+    pub register: char,
+    keys: Vec<KeyEvent>,
+    /// Index of the first key of the command being entered, like the `"` of `"aq`.
+    command_start: usize,
+}
+
+impl MacroRecording {
+    pub fn new(register: char) -> Self {
+        Self {
+            register,
+            keys: Vec::new(),
+            command_start: 0,
+        }
+    }
+
+    pub fn push(&mut self, key: KeyEvent) {
+        self.keys.push(key);
+    }
+
+    /// Mark the last recorded key as the first key of a new command, including any count or
+    /// register prefix.
+    pub fn start_command(&mut self) {
+        self.command_start = self.keys.len().saturating_sub(1);
+    }
+
+    /// The recorded keys without the ones of the command that stopped the recording.
+    pub fn finish(mut self) -> Vec<KeyEvent> {
+        self.keys.truncate(self.command_start);
+        self.keys
+    }
+}
+
+/// Number of language server log messages kept for `:lsp-log`.
+pub const MAX_LSP_LOG: usize = 1000;
+
 #[derive(Debug)]
 pub struct Editor {
     pub tree: Tree,
@@ -81,8 +150,28 @@ pub struct Editor {
     pub count: Option<std::num::NonZeroUsize>,
     pub selected_register: Option<char>,
     pub registers: Registers,
+    /// Macro being recorded, if any.
+    pub macro_recording: Option<MacroRecording>,
+    /// Registers of the macros being replayed, innermost last, so a macro can't replay itself.
+    pub macro_replaying: Vec<char>,
+    /// State restored from and persisted to the state file between restarts.
+    pub session: Session,
     pub theme: Theme,
     pub language_servers: helix_lsp::Registry,
+    /// Work done progress reported by each language server.
+    pub lsp_progress: helix_lsp::LspProgressMap,
+    /// Latest `window/logMessage` output of language servers, oldest first.
+    pub lsp_log: VecDeque<String>,
+    /// Latest diagnostics published for each file, including files that aren't open, along with
+    /// the id of the language server that published them.
+    pub diagnostics: BTreeMap<lsp::Url, Vec<(lsp::Diagnostic, usize)>>,
+    /// Result id of the latest pulled diagnostic report for each language server and file, sent
+    /// back so servers can answer that nothing changed.
+    pub diagnostic_result_ids: HashMap<(usize, lsp::Url), String>,
+    /// Watches open documents and the files language servers registered watchers for.
+    pub file_watcher: FileWatcher,
+    /// Watchers registered through `workspace/didChangeWatchedFiles` by each language server.
+    pub file_watchers: HashMap<usize, Vec<Watcher>>,
     pub clipboard_provider: Box<dyn ClipboardProvider>,
 
     pub syn_loader: Arc<syntax::Loader>,
@@ -91,6 +180,9 @@ pub struct Editor {
     pub status_msg: Option<(String, Severity)>,
 
     pub config: Config,
+
+    /// Fires after `config.idle_timeout` without any keypress.
+    pub idle_timer: Pin<Box<Sleep>>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -120,15 +212,116 @@ impl Editor {
             selected_register: None,
             theme: themes.default(),
             language_servers,
+            lsp_progress: helix_lsp::LspProgressMap::new(),
+            lsp_log: VecDeque::new(),
+            diagnostics: BTreeMap::new(),
+            diagnostic_result_ids: HashMap::new(),
+            file_watcher: FileWatcher::new(workspace_root()),
+            file_watchers: HashMap::new(),
             syn_loader: config_loader,
             theme_loader: themes,
             registers: Registers::default(),
+            macro_recording: None,
+            macro_replaying: Vec::new(),
+            session: Session::new(),
             clipboard_provider: get_clipboard_provider(),
             status_msg: None,
+            idle_timer: Box::pin(sleep(config.idle_timeout)),
             config,
         }
     }
 
+    pub fn clear_idle_timer(&mut self) {
+        // there's no way to disable a `Sleep`, so push the deadline far into the future
+        self.idle_timer
+            .as_mut()
+            .reset(Instant::now() + Duration::from_secs(86400 * 365 * 30));
+    }
+
+    pub fn reset_idle_timer(&mut self) {
+        self.idle_timer
+            .as_mut()
+            .reset(Instant::now() + self.config.idle_timeout);
+    }
+
+    /// Replace the diagnostics reported by `server_id` for `uri`, both on the document if it's open
+    /// and in the workspace diagnostics. Used for pushed as well as pulled diagnostics.
+    pub fn set_lsp_diagnostics(
+        &mut self,
+        server_id: usize,
+        offset_encoding: helix_lsp::OffsetEncoding,
+        uri: lsp::Url,
+        diagnostics: Vec<lsp::Diagnostic>,
+    ) {
+        use helix_core::diagnostic::{Diagnostic, Range};
+        use helix_lsp::util::lsp_pos_to_pos;
+
+        let doc = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| self.document_by_path_mut(&path));
+        if let Some(doc) = doc {
+            let text = doc.text();
+
+            let doc_diagnostics = diagnostics
+                .iter()
+                .filter_map(|diagnostic| {
+                    // TODO: convert inside server
+                    let start = lsp_pos_to_pos(text, diagnostic.range.start, offset_encoding);
+                    let end = lsp_pos_to_pos(text, diagnostic.range.end, offset_encoding);
+                    let (start, end) = match (start, end) {
+                        (Some(start), Some(end)) => (start, end),
+                        _ => {
+                            log::warn!("lsp position out of bounds - {:?}", diagnostic);
+                            return None;
+                        }
+                    };
+
+                    Some(Diagnostic {
+                        range: Range { start, end },
+                        line: diagnostic.range.start.line as usize,
+                        message: diagnostic.message.clone(),
+                        severity: diagnostic.severity.map(|severity| match severity {
+                            lsp::DiagnosticSeverity::ERROR => Severity::Error,
+                            lsp::DiagnosticSeverity::INFORMATION => Severity::Info,
+                            lsp::DiagnosticSeverity::HINT => Severity::Hint,
+                            // also covers severities unknown to us
+                            _ => Severity::Warning,
+                        }),
+                        language_server_id: server_id,
+                        // code
+                        // source
+                    })
+                })
+                .collect();
+
+            doc.set_diagnostics(server_id, doc_diagnostics);
+        }
+
+        // keep diagnostics of files that aren't open around for the pickers
+        self.set_workspace_diagnostics(server_id, uri, diagnostics);
+    }
+
+    /// Replace the diagnostics `server_id` published for `uri`, keeping those of other servers.
+    pub fn set_workspace_diagnostics(
+        &mut self,
+        server_id: usize,
+        uri: lsp::Url,
+        diagnostics: Vec<lsp::Diagnostic>,
+    ) {
+        let entry = self.diagnostics.entry(uri).or_default();
+        entry.retain(|(_, id)| *id != server_id);
+        entry.extend(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic, server_id)),
+        );
+        entry.sort_by_key(|(diagnostic, _)| diagnostic.range.start);
+
+        self.diagnostics
+            .retain(|_, diagnostics| !diagnostics.is_empty());
+    }
+
     pub fn clear_status(&mut self) {
         self.status_msg = None;
     }
@@ -181,8 +374,14 @@ impl Editor {
             return;
         }
 
+        // a document shown for the first time, possibly loaded in the background before,
+        // starts out where it was left in the last session
+        let first_view = self.documents[id].selections.is_empty();
+
         match action {
             Action::Replace => {
+                let view = view!(self);
+                self.save_view_state(view.id);
                 let view = view!(self);
                 let jump = (
                     view.doc,
@@ -194,6 +393,7 @@ impl Editor {
                 view.last_accessed_doc = Some(view.doc);
                 view.doc = id;
                 view.offset = Position::default();
+                view.clear_object_selections();
 
                 let (view, doc) = current!(self);
 
@@ -209,6 +409,9 @@ impl Editor {
                 let line = doc.text().char_to_line(pos);
                 view.offset.row = line.saturating_sub(view.inner_area().height as usize / 2);
 
+                if first_view {
+                    self.restore_view_state(view!(self).id);
+                }
                 return;
             }
             Action::Load => {
@@ -230,11 +433,18 @@ impl Editor {
             }
         }
 
+        if first_view {
+            self.restore_view_state(view!(self).id);
+        }
         self._refresh();
     }
 
     pub fn new_file(&mut self, action: Action) -> DocumentId {
-        let doc = Document::default();
+        self.new_file_from_document(action, Document::default())
+    }
+
+    /// Show `doc`, which has no path, like a new file.
+    pub fn new_file_from_document(&mut self, action: Action, doc: Document) -> DocumentId {
         let id = self.documents.insert(doc);
         self.documents[id].id = id;
         self.switch(id, action);
@@ -254,34 +464,38 @@ impl Editor {
         } else {
             let mut doc = Document::open(&path, None, Some(&self.theme), Some(&self.syn_loader))?;
 
-            // try to find a language server based on the language name
-            let language_server = doc.language.as_ref().and_then(|language| {
-                self.language_servers
-                    .get(language)
-                    .map_err(|e| {
-                        log::error!("Failed to get LSP, {}, for `{}`", e, language.scope())
-                    })
-                    .ok()
-            });
-
-            if let Some(language_server) = language_server {
-                let language_id = doc
-                    .language()
-                    .and_then(|s| s.split('.').last()) // source.rust
-                    .map(ToOwned::to_owned)
-                    .unwrap_or_default();
-
+            // try to find the language servers based on the language name
+            let language_servers = doc
+                .language
+                .as_ref()
+                .and_then(|language| {
+                    self.language_servers
+                        .get(language, Some(&path))
+                        .map_err(|e| {
+                            log::error!("Failed to get LSP, {}, for `{}`", e, language.scope())
+                        })
+                        .ok()
+                })
+                .unwrap_or_default();
+
+            let language_id = doc
+                .language()
+                .and_then(|s| s.split('.').last()) // source.rust
+                .map(ToOwned::to_owned)
+                .unwrap_or_default();
+
+            for language_server in &language_servers {
                 // TODO: this now races with on_init code if the init happens too quickly
                 tokio::spawn(language_server.text_document_did_open(
                     doc.url().unwrap(),
                     doc.version(),
                     doc.text(),
-                    language_id,
+                    language_id.clone(),
                 ));
-
-                doc.set_language_server(Some(language_server));
             }
 
+            doc.set_language_servers(language_servers);
+
             let id = self.documents.insert(doc);
             self.documents[id].id = id;
             id
@@ -292,15 +506,19 @@ impl Editor {
     }
 
     pub fn close(&mut self, id: ViewId, close_buffer: bool) {
+        self.save_view_state(id);
+
         let view = self.tree.get(self.tree.focus);
         // remove selection
         self.documents[view.doc].selections.remove(&id);
+        self.documents[view.doc].inlay_hints.remove(&id);
+        self.documents[view.doc].document_highlights.remove(&id);
 
         if close_buffer {
             // get around borrowck issues
             let doc = &self.documents[view.doc];
 
-            if let Some(language_server) = doc.language_server() {
+            for language_server in doc.language_servers() {
                 tokio::spawn(language_server.text_document_did_close(doc.identifier()));
             }
             self.documents.remove(view.doc);
@@ -310,6 +528,111 @@ impl Editor {
         self._refresh();
     }
 
+    /// Record the selection, offset and jumplist of a view in the session, keyed by the path of
+    /// the document it displays.
+    fn save_view_state(&mut self, id: ViewId) {
+        let view = self.tree.get(id);
+        let doc = &self.documents[view.doc];
+        let path = match doc.path() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+
+        let jumps = view
+            .jumps
+            .iter()
+            .filter_map(|(doc_id, selection)| {
+                let path = self.documents.get(*doc_id)?.path()?;
+                Some(JumpState {
+                    path: path.clone(),
+                    selection: selection.into(),
+                })
+            })
+            .collect();
+
+        let state = FileState {
+            offset: (view.offset.row, view.offset.col),
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+            selection: doc.selection(id).into(),
+            jumps,
+        };
+        self.session.set_file(path, state);
+    }
+
+    /// Restore the selection, offset and jumplist stored in the session for the document of a
+    /// view. Jumps to files that aren't currently open are dropped.
+    fn restore_view_state(&mut self, id: ViewId) {
+        let view = self.tree.get_mut(id);
+        let doc = &mut self.documents[view.doc];
+        let session = &self.session;
+        let state = match doc.path().and_then(|path| session.file(path)) {
+            Some(state) => state.clone(),
+            None => return,
+        };
+
+        let selection = state
+            .selection
+            .to_selection(doc.text())
+            .ensure_invariants(doc.text().slice(..));
+        doc.set_selection(id, selection);
+        view.offset = state.offset();
+
+        for jump in state.jumps {
+            let doc = self
+                .documents
+                .values()
+                .find(|doc| doc.path() == Some(&jump.path));
+            if let Some(doc) = doc {
+                let selection = jump
+                    .selection
+                    .to_selection(doc.text())
+                    .ensure_invariants(doc.text().slice(..));
+                view.jumps.push((doc.id, selection));
+            }
+        }
+
+        self.ensure_cursor_in_view(id);
+    }
+
+    /// Load the state file and restore registers and prompt history from it.
+    pub fn load_session(&mut self) {
+        self.session = match Session::load(&Session::path()) {
+            Ok(session) => session,
+            Err(err) => {
+                log::error!("failed to load editor state: {}", err);
+                Session::new()
+            }
+        };
+
+        for (name, values) in self.session.history().chain(self.session.registers()) {
+            self.registers.write(name, values.to_vec());
+        }
+    }
+
+    /// Capture the state of all open views and registers and write it to the state file.
+    pub fn save_session(&mut self) {
+        let views: Vec<_> = self.tree.views().map(|(view, _)| view.id).collect();
+        for id in views {
+            self.save_view_state(id);
+        }
+
+        for register in self.registers.iter() {
+            let name = register.name();
+            if session::HISTORY_REGISTERS.contains(&name) {
+                self.session.set_history(name, register.read());
+            } else if session::is_named_register(name) {
+                self.session.set_register(name, register.read());
+            }
+        }
+
+        if let Err(err) = self.session.save(&Session::path()) {
+            log::error!("failed to save editor state: {}", err);
+        }
+    }
+
     pub fn resize(&mut self, area: Rect) {
         if self.tree.resize(area) {
             self._refresh();
@@ -377,6 +700,189 @@ impl Editor {
         }
     }
 
+    /// Record a `window/logMessage` from a language server, dropping the oldest messages past
+    /// [`MAX_LSP_LOG`].
+    pub fn push_lsp_log(&mut self, server_name: &str, typ: lsp::MessageType, message: &str) {
+        let level = match typ {
+            lsp::MessageType::ERROR => "error",
+            lsp::MessageType::WARNING => "warning",
+            lsp::MessageType::INFO => "info",
+            _ => "log",
+        };
+        if self.lsp_log.len() == MAX_LSP_LOG {
+            self.lsp_log.pop_front();
+        }
+        self.lsp_log
+            .push_back(format!("[{}] {}: {}", server_name, level, message));
+    }
+
+    /// Stop the language servers of a language and detach them from its documents. They are
+    /// started again when a document of the language is opened.
+    pub fn stop_language_servers(&mut self, scope: &str) -> Vec<Arc<helix_lsp::Client>> {
+        let clients = self.language_servers.remove_language(scope);
+        for client in &clients {
+            self.detach_language_server(client.id());
+
+            let client = client.clone();
+            tokio::spawn(async move { client.force_shutdown().await });
+        }
+        clients
+    }
+
+    /// Restart the language servers of a language and attach the new ones to its documents,
+    /// which are opened on them once they finish initializing. The project roots of the
+    /// documents become the workspace folders of the new servers.
+    pub fn restart_language_servers(
+        &mut self,
+        language: &syntax::LanguageConfiguration,
+    ) -> helix_lsp::Result<Vec<Arc<helix_lsp::Client>>> {
+        self.stop_language_servers(&language.scope);
+
+        let paths: Vec<_> = self
+            .documents
+            .values()
+            .filter(|doc| doc.language() == Some(language.scope.as_str()))
+            .filter_map(|doc| doc.path().cloned())
+            .collect();
+        let mut clients = self
+            .language_servers
+            .get(language, paths.first().map(|p| p.as_path()))?;
+        for path in paths.iter().skip(1) {
+            clients = self.language_servers.get(language, Some(path))?;
+        }
+        for doc in self.documents.values_mut() {
+            if doc.language() == Some(language.scope.as_str()) {
+                doc.set_language_servers(clients.clone());
+            }
+        }
+        Ok(clients)
+    }
+
+    /// Forget a language server whose connection was lost. Returns it if it was still running,
+    /// as opposed to being stopped on purpose.
+    pub fn language_server_exited(&mut self, server_id: usize) -> Option<Arc<helix_lsp::Client>> {
+        let client = self.language_servers.remove(server_id)?;
+        self.detach_language_server(server_id);
+        Some(client)
+    }
+
+    /// Drop everything a language server provided, from documents as well as the workspace.
+    fn detach_language_server(&mut self, server_id: usize) {
+        for doc in self.documents.values_mut() {
+            doc.detach_language_server(server_id);
+        }
+        for diagnostics in self.diagnostics.values_mut() {
+            diagnostics.retain(|(_, id)| *id != server_id);
+        }
+        self.diagnostics
+            .retain(|_, diagnostics| !diagnostics.is_empty());
+        self.diagnostic_result_ids
+            .retain(|(id, _), _| *id != server_id);
+        self.lsp_progress.remove(server_id);
+        self.refresh_file_watchers();
+    }
+
+    /// Rebuild the file watchers from the `workspace/didChangeWatchedFiles` registrations of the
+    /// running language servers.
+    pub fn refresh_file_watchers(&mut self) {
+        let root = self.file_watcher.root().to_path_buf();
+        self.file_watchers = self
+            .language_servers
+            .iter_clients()
+            .map(|client| {
+                let watchers = client
+                    .dynamic_registrations(
+                        <lsp::notification::DidChangeWatchedFiles as lsp::notification::Notification>::METHOD,
+                    )
+                    .into_iter()
+                    .filter_map(|registration| {
+                        serde_json::from_value::<lsp::DidChangeWatchedFilesRegistrationOptions>(
+                            registration.register_options?,
+                        )
+                        .map_err(|err| log::warn!("invalid file watcher registration: {}", err))
+                        .ok()
+                    })
+                    .flat_map(|options| options.watchers)
+                    .filter_map(|watcher| Watcher::new(&watcher, &root))
+                    .collect();
+                (client.id(), watchers)
+            })
+            .collect();
+
+        self.file_watcher
+            .set_watchers(self.file_watchers.values().flatten().cloned().collect());
+    }
+
+    /// Let the file watcher know about documents that were opened, closed or renamed.
+    pub fn refresh_watched_documents(&self) {
+        let paths = self
+            .documents
+            .values()
+            .filter_map(|doc| doc.path().cloned())
+            .collect();
+        self.file_watcher.set_documents(paths);
+    }
+
+    /// Handle a batch of file changes: reload documents without unsaved changes, mark the others
+    /// and notify language servers watching the files.
+    pub fn handle_file_events(&mut self, events: Vec<FileEvent>) {
+        let mut changed_on_disk = Vec::new();
+        let mut deleted_on_disk = Vec::new();
+        for event in &events {
+            let doc = match self
+                .documents
+                .values_mut()
+                .find(|doc| doc.path() == Some(&event.path))
+            {
+                Some(doc) => doc,
+                None => continue,
+            };
+            let view_id = match doc.selections().keys().next() {
+                Some(view_id) => *view_id,
+                None => continue,
+            };
+            match doc.file_changed(view_id) {
+                Ok(_) if doc.deleted_on_disk() => deleted_on_disk.push(event.path.clone()),
+                Ok(_) if doc.changed_on_disk() => changed_on_disk.push(event.path.clone()),
+                Ok(_) => (),
+                Err(err) => log::error!("failed to check {}: {}", event.path.display(), err),
+            }
+        }
+        if let Some(path) = deleted_on_disk.first() {
+            self.set_error(format!(
+                "{} was deleted on disk, use :write to restore it",
+                path.display()
+            ));
+        } else if let Some(path) = changed_on_disk.first() {
+            self.set_error(format!(
+                "{} changed on disk, use :reload to discard your changes",
+                path.display()
+            ));
+        }
+
+        for (server_id, watchers) in &self.file_watchers {
+            let changes: Vec<_> = events
+                .iter()
+                .filter(|event| watchers.iter().any(|watcher| watcher.matches(event)))
+                .filter_map(|event| {
+                    let typ = match event.change {
+                        FileChange::Created => lsp::FileChangeType::CREATED,
+                        FileChange::Changed => lsp::FileChangeType::CHANGED,
+                        FileChange::Deleted => lsp::FileChangeType::DELETED,
+                    };
+                    let uri = lsp::Url::from_file_path(&event.path).ok()?;
+                    Some(lsp::FileEvent::new(uri, typ))
+                })
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+            if let Some(language_server) = self.language_servers.get_by_id(*server_id) {
+                tokio::spawn(language_server.did_change_watched_files(changes));
+            }
+        }
+    }
+
     /// Closes language servers with timeout. The default timeout is 500 ms, use
     /// `timeout` parameter to override this.
     pub async fn close_language_servers(
@@ -395,3 +901,76 @@ impl Editor {
         .map(|_| ())
     }
 }
+
+/// Directory containing `.git` above the working directory, or the working directory itself.
+fn workspace_root() -> PathBuf {
+    helix_core::find_root(None, &[])
+        .unwrap_or_else(|| std::env::current_dir().expect("unable to determine current directory"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diagnostic(line: u32, message: &str) -> lsp::Diagnostic {
+        let pos = lsp::Position::new(line, 0);
+        lsp::Diagnostic {
+            range: lsp::Range::new(pos, pos),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn messages(editor: &Editor, uri: &lsp::Url) -> Vec<(String, usize)> {
+        editor.diagnostics[uri]
+            .iter()
+            .map(|(diagnostic, server_id)| (diagnostic.message.clone(), *server_id))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn workspace_diagnostics_are_replaced_per_server() {
+        let mut editor = Editor::new(
+            Rect::new(0, 0, 80, 24),
+            Arc::new(theme::Loader::new("", "")),
+            Arc::new(syntax::Loader::new(syntax::Configuration {
+                language: vec![],
+            })),
+            Config::default(),
+        );
+        let uri = lsp::Url::parse("file:///tmp/main.rs").unwrap();
+        let other = lsp::Url::parse("file:///tmp/lib.rs").unwrap();
+
+        editor.set_workspace_diagnostics(
+            0,
+            uri.clone(),
+            vec![diagnostic(4, "a"), diagnostic(1, "b")],
+        );
+        editor.set_workspace_diagnostics(1, uri.clone(), vec![diagnostic(2, "c")]);
+        editor.set_workspace_diagnostics(1, other.clone(), vec![diagnostic(0, "d")]);
+        // the diagnostics of all servers are merged and sorted by position
+        assert_eq!(
+            messages(&editor, &uri),
+            vec![
+                ("b".to_string(), 0),
+                ("c".to_string(), 1),
+                ("a".to_string(), 0)
+            ]
+        );
+
+        // a new report replaces only the diagnostics of the server that sent it
+        editor.set_workspace_diagnostics(0, uri.clone(), vec![diagnostic(3, "e")]);
+        assert_eq!(
+            messages(&editor, &uri),
+            vec![("c".to_string(), 1), ("e".to_string(), 0)]
+        );
+        assert_eq!(messages(&editor, &other), vec![("d".to_string(), 1)]);
+
+        // files without diagnostics are dropped
+        editor.set_workspace_diagnostics(1, other.clone(), Vec::new());
+        assert!(!editor.diagnostics.contains_key(&other));
+        editor.set_lsp_diagnostics(0, helix_lsp::OffsetEncoding::Utf8, uri.clone(), Vec::new());
+        editor.set_lsp_diagnostics(1, helix_lsp::OffsetEncoding::Utf8, uri.clone(), Vec::new());
+        assert!(editor.diagnostics.is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+//! Polls the file system for changes made outside the editor, both to open documents and to the
+//! files language servers watch through `workspace/didChangeWatchedFiles` registrations.
+//!
+//! Changes are collected until a scan finds nothing new and then reported in one batch, so a
+//! `git checkout` touching many files results in a single batch.
+
+use globset::{GlobBuilder, GlobMatcher};
+use helix_lsp::lsp;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Time between two scans.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Changes are reported after this many scans even if files keep changing.
+const MAX_PENDING_SCANS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Created,
+    Changed,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEvent {
+    pub path: PathBuf,
+    pub change: FileChange,
+}
+
+/// A glob pattern registered by a language server, resolved against its base directory.
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    /// Directory relative patterns are matched from.
+    base: PathBuf,
+    glob: GlobMatcher,
+    kind: lsp::WatchKind,
+}
+
+impl Watcher {
+    /// Resolve a watcher of a `workspace/didChangeWatchedFiles` registration. Plain patterns are
+    /// relative to `root` unless they're absolute.
+    pub fn new(watcher: &lsp::FileSystemWatcher, root: &Path) -> Option<Self> {
+        let (base, pattern) = match &watcher.glob_pattern {
+            lsp::GlobPattern::String(pattern) => (root.to_path_buf(), pattern.as_str()),
+            lsp::GlobPattern::Relative(relative) => {
+                let base = match &relative.base_uri {
+                    lsp::OneOf::Left(folder) => &folder.uri,
+                    lsp::OneOf::Right(uri) => uri,
+                };
+                (base.to_file_path().ok()?, relative.pattern.as_str())
+            }
+        };
+
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| log::warn!("invalid glob pattern {:?}: {}", pattern, err))
+            .ok()?
+            .compile_matcher();
+
+        Some(Self {
+            base,
+            glob,
+            kind: watcher.kind.unwrap_or_else(lsp::WatchKind::all),
+        })
+    }
+
+    /// Whether `path` matches the pattern, regardless of the kind of change.
+    pub fn matches_path(&self, path: &Path) -> bool {
+        if self.glob.is_match(path) {
+            return true;
+        }
+        match path.strip_prefix(&self.base) {
+            Ok(relative) => self.glob.is_match(relative),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether the server asked to be told about `event`.
+    pub fn matches(&self, event: &FileEvent) -> bool {
+        let kind = match event.change {
+            FileChange::Created => lsp::WatchKind::Create,
+            FileChange::Changed => lsp::WatchKind::Change,
+            FileChange::Deleted => lsp::WatchKind::Delete,
+        };
+        self.kind.contains(kind) && self.matches_path(&event.path)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Watched {
+    watchers: Vec<Watcher>,
+    documents: Vec<PathBuf>,
+    /// Bumped whenever the watched set changes, so the next scan becomes the new baseline
+    /// instead of reporting newly watched files as created.
+    generation: usize,
+}
+
+#[derive(Debug)]
+pub struct FileWatcher {
+    /// Workspace root, which plain watcher patterns are relative to.
+    root: PathBuf,
+    watched: Arc<Mutex<Watched>>,
+    sender: Option<UnboundedSender<Vec<FileEvent>>>,
+    /// Batches of changes found by the background scans.
+    pub events: UnboundedReceiver<Vec<FileEvent>>,
+}
+
+impl FileWatcher {
+    pub fn new(root: PathBuf) -> Self {
+        let (sender, events) = unbounded_channel();
+        Self {
+            root,
+            watched: Arc::default(),
+            sender: Some(sender),
+            events,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Start scanning in the background. Does nothing if already started.
+    pub fn start(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            tokio::spawn(poll(self.watched.clone(), sender));
+        }
+    }
+
+    /// Replace the patterns registered by language servers.
+    pub fn set_watchers(&self, watchers: Vec<Watcher>) {
+        let mut watched = self.watched.lock().unwrap();
+        watched.watchers = watchers;
+        watched.generation += 1;
+    }
+
+    /// Replace the paths of open documents, doing nothing if they didn't change.
+    pub fn set_documents(&self, documents: Vec<PathBuf>) {
+        let mut watched = self.watched.lock().unwrap();
+        if watched.documents != documents {
+            watched.documents = documents;
+            watched.generation += 1;
+        }
+    }
+}
+
+async fn poll(watched: Arc<Mutex<Watched>>, sender: UnboundedSender<Vec<FileEvent>>) {
+    let mut snapshot = None;
+    let mut generation = 0;
+    let mut pending: HashMap<PathBuf, FileChange> = HashMap::new();
+    let mut pending_scans = 0;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        if sender.is_closed() {
+            break;
+        }
+
+        let current = watched.lock().unwrap().clone();
+        let current_generation = current.generation;
+        let files = match tokio::task::spawn_blocking(move || scan(&current)).await {
+            Ok(files) => files,
+            Err(err) => {
+                log::error!("failed to scan for file changes: {}", err);
+                continue;
+            }
+        };
+
+        let previous = snapshot.replace(files);
+        if generation != current_generation {
+            // the watched set changed, start over from this scan
+            generation = current_generation;
+            continue;
+        }
+        let changes = match (&previous, &snapshot) {
+            (Some(previous), Some(files)) => diff(previous, files),
+            _ => continue,
+        };
+
+        if changes.is_empty() && pending.is_empty() {
+            continue;
+        }
+        let quiet = changes.is_empty();
+        for (path, change) in changes {
+            merge(&mut pending, path, change);
+        }
+
+        pending_scans += 1;
+        if quiet || pending_scans >= MAX_PENDING_SCANS {
+            let events = pending
+                .drain()
+                .map(|(path, change)| FileEvent { path, change })
+                .collect();
+            pending_scans = 0;
+            if sender.send(events).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Modification times of the open documents and of the files matched by any watcher.
+fn scan(watched: &Watched) -> HashMap<PathBuf, SystemTime> {
+    fn modified(path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    let mut files: HashMap<_, _> = watched
+        .documents
+        .iter()
+        .filter_map(|path| Some((path.clone(), modified(path)?)))
+        .collect();
+
+    if watched.watchers.is_empty() {
+        return files;
+    }
+
+    // walk each watcher base once, skipping those inside another base
+    let mut bases: Vec<&Path> = watched.watchers.iter().map(|w| w.base.as_path()).collect();
+    bases.sort();
+    bases.dedup_by(|base, parent| base.starts_with(parent));
+
+    for base in bases {
+        // skip ignored files like build output, which would make every scan walk huge trees,
+        // but keep hidden ones such as `.config` files servers may watch
+        let walk = ignore::WalkBuilder::new(base)
+            .hidden(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        for entry in walk.flatten() {
+            let path = entry.path();
+            if !matches!(entry.file_type(), Some(ty) if ty.is_file())
+                || !watched.watchers.iter().any(|w| w.matches_path(path))
+            {
+                continue;
+            }
+            if let Some(time) = modified(path) {
+                files.insert(path.to_path_buf(), time);
+            }
+        }
+    }
+
+    files
+}
+
+fn diff(
+    previous: &HashMap<PathBuf, SystemTime>,
+    files: &HashMap<PathBuf, SystemTime>,
+) -> Vec<(PathBuf, FileChange)> {
+    let mut changes = Vec::new();
+    for (path, time) in files {
+        match previous.get(path) {
+            None => changes.push((path.clone(), FileChange::Created)),
+            Some(previous_time) if previous_time != time => {
+                changes.push((path.clone(), FileChange::Changed))
+            }
+            Some(_) => (),
+        }
+    }
+    for path in previous.keys() {
+        if !files.contains_key(path) {
+            changes.push((path.clone(), FileChange::Deleted));
+        }
+    }
+    changes
+}
+
+/// Combine a change with the one already pending for the same file.
+fn merge(pending: &mut HashMap<PathBuf, FileChange>, path: PathBuf, change: FileChange) {
+    use FileChange::*;
+
+    let merged = match (pending.get(&path), change) {
+        (None, change) => Some(change),
+        // the file came and went between two batches
+        (Some(Created), Deleted) => None,
+        (Some(Created), _) => Some(Created),
+        (Some(Deleted), Created) => Some(Changed),
+        (Some(_), change) => Some(change),
+    };
+    match merged {
+        Some(change) => pending.insert(path, change),
+        None => pending.remove(&path),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merges_pending_changes() {
+        let mut pending = HashMap::new();
+        let path = PathBuf::from("/tmp/a.rs");
+
+        merge(&mut pending, path.clone(), FileChange::Created);
+        merge(&mut pending, path.clone(), FileChange::Changed);
+        assert_eq!(pending.get(&path), Some(&FileChange::Created));
+
+        merge(&mut pending, path.clone(), FileChange::Deleted);
+        assert!(pending.is_empty());
+
+        merge(&mut pending, path.clone(), FileChange::Deleted);
+        merge(&mut pending, path.clone(), FileChange::Created);
+        assert_eq!(pending.get(&path), Some(&FileChange::Changed));
+    }
+
+    #[test]
+    fn matches_watchers() {
+        let root = Path::new("/project");
+        let watcher = Watcher::new(
+            &lsp::FileSystemWatcher {
+                glob_pattern: lsp::GlobPattern::String("**/*.rs".to_string()),
+                kind: Some(lsp::WatchKind::Create | lsp::WatchKind::Delete),
+            },
+            root,
+        )
+        .unwrap();
+
+        let event = |path: &str, change| FileEvent {
+            path: PathBuf::from(path),
+            change,
+        };
+        assert!(watcher.matches(&event("/project/src/main.rs", FileChange::Created)));
+        assert!(!watcher.matches(&event("/project/src/main.rs", FileChange::Changed)));
+        assert!(!watcher.matches(&event("/project/Cargo.toml", FileChange::Deleted)));
+
+        let watcher = Watcher::new(
+            &lsp::FileSystemWatcher {
+                glob_pattern: lsp::GlobPattern::String("*.toml".to_string()),
+                kind: None,
+            },
+            root,
+        )
+        .unwrap();
+        assert!(watcher.matches(&event("/project/Cargo.toml", FileChange::Changed)));
+        assert!(!watcher.matches(&event("/project/sub/Cargo.toml", FileChange::Changed)));
+    }
+
+    #[test]
+    fn scans_skip_ignored_files() {
+        let dir = std::env::temp_dir().join(format!("helix-file-watcher-{}", std::process::id()));
+        for sub in [".git", "src", "target"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        std::fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        for file in [".git/a.rs", "target/b.rs", "src/c.rs", ".d.rs"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let watcher = Watcher::new(
+            &lsp::FileSystemWatcher {
+                glob_pattern: lsp::GlobPattern::String("**/*.rs".to_string()),
+                kind: None,
+            },
+            &dir,
+        )
+        .unwrap();
+        let watched = Watched {
+            watchers: vec![watcher],
+            ..Default::default()
+        };
+        let mut files: Vec<_> = scan(&watched).into_keys().collect();
+        files.sort();
+        assert_eq!(files, [dir.join(".d.rs"), dir.join("src/c.rs")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
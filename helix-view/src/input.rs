@@ -230,6 +230,53 @@ impl From<crossterm::event::KeyEvent> for KeyEvent {
     }
 }
 
+#[cfg(feature = "term")]
+impl From<KeyEvent> for crossterm::event::KeyEvent {
+    fn from(KeyEvent { code, modifiers }: KeyEvent) -> crossterm::event::KeyEvent {
+        crossterm::event::KeyEvent {
+            code: code.into(),
+            modifiers: modifiers.into(),
+        }
+    }
+}
+
+/// Serialize a sequence of keys into the form used to store macros in registers. Keys with
+/// a name longer than a single character are wrapped in angle brackets: `ihello<esc>`.
+pub fn format_macro(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(|key| {
+            let s = key.to_string();
+            if s.chars().count() > 1 {
+                format!("<{}>", s)
+            } else {
+                s
+            }
+        })
+        .collect()
+}
+
+/// Parse a sequence of keys written by [`format_macro`].
+pub fn parse_macro(keys: &str) -> Result<Vec<KeyEvent>, Error> {
+    let mut events = Vec::new();
+    let mut rest = keys;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<' {
+            let end = rest
+                .find('>')
+                .ok_or_else(|| anyhow!("Unterminated key in macro '{}'", keys))?;
+            events.push(rest[1..end].parse()?);
+            rest = &rest[end + 1..];
+        } else {
+            events.push(KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE,
+            });
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    Ok(events)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -315,4 +362,35 @@ mod test {
         assert!(str::parse::<KeyEvent>("123").is_err());
         assert!(str::parse::<KeyEvent>("S--").is_err());
     }
+
+    #[test]
+    fn parsing_macros() {
+        assert_eq!(
+            parse_macro("ih<C-w><esc>").unwrap(),
+            vec![
+                KeyEvent {
+                    code: KeyCode::Char('i'),
+                    modifiers: KeyModifiers::NONE
+                },
+                KeyEvent {
+                    code: KeyCode::Char('h'),
+                    modifiers: KeyModifiers::NONE
+                },
+                KeyEvent {
+                    code: KeyCode::Char('w'),
+                    modifiers: KeyModifiers::CONTROL
+                },
+                KeyEvent {
+                    code: KeyCode::Esc,
+                    modifiers: KeyModifiers::NONE
+                },
+            ]
+        );
+
+        let keys = parse_macro("d<lt><space>x<A-minus>").unwrap();
+        assert_eq!(format_macro(&keys), "d<lt><space>x<A-minus>");
+
+        assert!(parse_macro("i<esc").is_err());
+        assert!(parse_macro("<foo>").is_err());
+    }
 }
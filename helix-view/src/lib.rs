@@ -4,10 +4,12 @@ pub mod macros;
 pub mod clipboard;
 pub mod document;
 pub mod editor;
+pub mod file_watcher;
 pub mod graphics;
 pub mod info;
 pub mod input;
 pub mod keyboard;
+pub mod session;
 pub mod theme;
 pub mod tree;
 pub mod view;
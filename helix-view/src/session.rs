@@ -0,0 +1,265 @@
+//! Editor state kept between restarts: the last selection, view offset and jumplist of each
+//! file, prompt history and named registers.
+
+use anyhow::{anyhow, Error};
+use helix_core::{Position, Range, Rope, Selection};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Bumped whenever the layout of the state file changes. Files written by another version are
+/// ignored.
+const VERSION: u32 = 1;
+
+/// Registers that store prompt history (command, search/select and shell prompts).
+pub const HISTORY_REGISTERS: &[char] = &[':', '/', '|'];
+
+/// Maximum number of entries kept per prompt history.
+const MAX_HISTORY: usize = 100;
+
+/// Maximum number of files whose state is kept. The ones saved longest ago are dropped first.
+const MAX_FILES: usize = 500;
+
+/// Returns true for registers that are persisted as named registers.
+pub fn is_named_register(name: char) -> bool {
+    name.is_ascii_alphabetic()
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    version: u32,
+    /// Last known state of each file, keyed by canonical path.
+    files: HashMap<PathBuf, FileState>,
+    /// Prompt history, keyed by the register the prompt stores it in.
+    history: HashMap<String, Vec<String>>,
+    /// Contents of named registers.
+    registers: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    /// First visible line and column.
+    pub offset: (usize, usize),
+    /// Seconds since the Unix epoch at which the state was saved. Serialized before the tables
+    /// below, as TOML doesn't allow values after them.
+    #[serde(default)]
+    pub saved_at: u64,
+    pub selection: SelectionState,
+    pub jumps: Vec<JumpState>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelectionState {
+    pub primary_index: usize,
+    /// `(anchor, head)` char positions of each range.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JumpState {
+    pub path: PathBuf,
+    pub selection: SelectionState,
+}
+
+impl From<&Selection> for SelectionState {
+    fn from(selection: &Selection) -> Self {
+        Self {
+            primary_index: selection.primary_index(),
+            ranges: selection
+                .iter()
+                .map(|range| (range.anchor, range.head))
+                .collect(),
+        }
+    }
+}
+
+impl SelectionState {
+    /// Convert back into a [`Selection`], clamping ranges to `text` in case the file changed
+    /// since the state was saved.
+    pub fn to_selection(&self, text: &Rope) -> Selection {
+        let len = text.len_chars();
+        let ranges: helix_core::SmallVec<[Range; 1]> = self
+            .ranges
+            .iter()
+            .map(|&(anchor, head)| Range::new(anchor.min(len), head.min(len)))
+            .collect();
+
+        if ranges.is_empty() {
+            return Selection::point(0);
+        }
+        let primary_index = self.primary_index.min(ranges.len() - 1);
+        Selection::new(ranges, primary_index)
+    }
+}
+
+impl FileState {
+    pub fn offset(&self) -> Position {
+        Position::new(self.offset.0, self.offset.1)
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            version: VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// Location of the state file.
+    pub fn path() -> PathBuf {
+        helix_core::config_dir().join("state.toml")
+    }
+
+    /// Load the state file, returning an empty session if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let session: Self = toml::from_slice(&std::fs::read(path)?)?;
+        if session.version != VERSION {
+            return Err(anyhow!(
+                "unsupported state file version {} (expected {})",
+                session.version,
+                VERSION
+            ));
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&FileState> {
+        self.files.get(path)
+    }
+
+    /// Store the state of a file, dropping the least recently saved file if there are more than
+    /// [`MAX_FILES`].
+    pub fn set_file(&mut self, path: PathBuf, state: FileState) {
+        self.files.insert(path, state);
+        if self.files.len() > MAX_FILES {
+            let oldest = self
+                .files
+                .iter()
+                .min_by_key(|(_, state)| state.saved_at)
+                .map(|(path, _)| path.clone());
+            if let Some(path) = oldest {
+                self.files.remove(&path);
+            }
+        }
+    }
+
+    /// Prompt history stored for each history register.
+    pub fn history(&self) -> impl Iterator<Item = (char, &[String])> {
+        Self::by_register(&self.history)
+    }
+
+    pub fn set_history(&mut self, register: char, values: &[String]) {
+        let start = values.len().saturating_sub(MAX_HISTORY);
+        self.history
+            .insert(register.to_string(), values[start..].to_vec());
+    }
+
+    /// Contents of each named register.
+    pub fn registers(&self) -> impl Iterator<Item = (char, &[String])> {
+        Self::by_register(&self.registers)
+    }
+
+    pub fn set_register(&mut self, register: char, values: &[String]) {
+        self.registers.insert(register.to_string(), values.to_vec());
+    }
+
+    fn by_register(map: &HashMap<String, Vec<String>>) -> impl Iterator<Item = (char, &[String])> {
+        map.iter().filter_map(|(name, values)| {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(name), None) => Some((name, values.as_slice())),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut session = Session::new();
+        session.set_file(
+            PathBuf::from("/tmp/foo.rs"),
+            FileState {
+                offset: (4, 0),
+                saved_at: 1,
+                selection: SelectionState {
+                    primary_index: 1,
+                    ranges: vec![(0, 3), (10, 5)],
+                },
+                jumps: vec![JumpState {
+                    path: PathBuf::from("/tmp/bar.rs"),
+                    selection: SelectionState {
+                        primary_index: 0,
+                        ranges: vec![(1, 1)],
+                    },
+                }],
+            },
+        );
+        session.set_history(':', &["w".to_string(), "q".to_string()]);
+        session.set_register('a', &["hello".to_string()]);
+
+        let serialized = toml::to_string(&session).unwrap();
+        let deserialized: Session = toml::from_str(&serialized).unwrap();
+        assert_eq!(session, deserialized);
+    }
+
+    #[test]
+    fn history_is_truncated() {
+        let mut session = Session::new();
+        let values: Vec<String> = (0..MAX_HISTORY + 10).map(|i| i.to_string()).collect();
+        session.set_history('/', &values);
+
+        let (_, history) = session.history().next().unwrap();
+        assert_eq!(history.len(), MAX_HISTORY);
+        assert_eq!(history.last(), values.last());
+    }
+
+    #[test]
+    fn least_recently_saved_files_are_dropped() {
+        let mut session = Session::new();
+        for i in 0..MAX_FILES + 1 {
+            let state = FileState {
+                saved_at: (MAX_FILES - i) as u64,
+                ..Default::default()
+            };
+            session.set_file(PathBuf::from(format!("/tmp/{}.rs", i)), state);
+        }
+
+        assert_eq!(session.files.len(), MAX_FILES);
+        assert!(session.file(Path::new("/tmp/0.rs")).is_some());
+        assert!(session.file(Path::new("/tmp/1.rs")).is_some());
+        assert!(session
+            .file(&PathBuf::from(format!("/tmp/{}.rs", MAX_FILES)))
+            .is_none());
+    }
+
+    #[test]
+    fn selection_is_clamped() {
+        let text = Rope::from("hello");
+        let state = SelectionState {
+            primary_index: 3,
+            ranges: vec![(0, 2), (4, 20)],
+        };
+        let selection = state.to_selection(&text);
+        assert_eq!(selection.primary_index(), 1);
+        assert_eq!(selection.ranges()[1], Range::new(4, 5));
+    }
+}
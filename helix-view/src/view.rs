@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 
-use crate::{graphics::Rect, Document, DocumentId, ViewId};
+use crate::{document::InlayHint, graphics::Rect, Document, DocumentId, ViewId};
 use helix_core::{
     coords_at_pos,
     graphemes::{grapheme_width, RopeGraphemes},
@@ -55,6 +55,10 @@ impl JumpList {
             None
         }
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Jump> {
+        self.jumps.iter()
+    }
 }
 
 #[derive(Debug)]
@@ -66,6 +70,9 @@ pub struct View {
     pub jumps: JumpList,
     /// the last accessed file before the current one
     pub last_accessed_doc: Option<DocumentId>,
+    /// Selections before each `expand_selection`, restored by `shrink_selection`, along with
+    /// the document and version they were taken from.
+    object_selections: Vec<(DocumentId, i32, Selection)>,
 }
 
 impl View {
@@ -77,7 +84,40 @@ impl View {
             area: Rect::default(), // will get calculated upon inserting into tree
             jumps: JumpList::new((doc, Selection::point(0))), // TODO: use actual sel
             last_accessed_doc: None,
+            object_selections: Vec::new(),
+        }
+    }
+
+    /// Remember the selection of `doc` before `expand_selection` replaces it.
+    pub fn push_object_selection(&mut self, doc: &Document, selection: Selection) {
+        if !self.object_selections_match(doc) {
+            self.object_selections.clear();
         }
+        self.object_selections
+            .push((doc.id(), doc.version(), selection));
+    }
+
+    /// Take the selection from before the last `expand_selection`, unless `doc` was switched or
+    /// edited since, which makes all of them stale.
+    pub fn pop_object_selection(&mut self, doc: &Document) -> Option<Selection> {
+        if !self.object_selections_match(doc) {
+            self.object_selections.clear();
+            return None;
+        }
+        self.object_selections
+            .pop()
+            .map(|(_, _, selection)| selection)
+    }
+
+    pub fn clear_object_selections(&mut self) {
+        self.object_selections.clear();
+    }
+
+    fn object_selections_match(&self, doc: &Document) -> bool {
+        matches!(
+            self.object_selections.last(),
+            Some((doc_id, version, _)) if *doc_id == doc.id() && *version == doc.version()
+        )
     }
 
     pub fn inner_area(&self) -> Rect {
@@ -110,6 +150,15 @@ impl View {
             self.offset.row = line.saturating_sub(scrolloff);
         }
 
+        // code lens lines take up rows as well, scroll further until the cursor line and the
+        // scrolloff below it fit
+        while self.offset.row < line
+            && self.rows_between(doc, self.offset.row, line + scrolloff)
+                > inner_area.height as usize
+        {
+            self.offset.row += 1;
+        }
+
         if col > last_col.saturating_sub(scrolloff) {
             // scroll right
             self.offset.col += col - (last_col.saturating_sub(scrolloff));
@@ -122,12 +171,35 @@ impl View {
     /// Calculates the last visible line on screen
     #[inline]
     pub fn last_line(&self, doc: &Document) -> usize {
-        let height = self.inner_area().height;
-        std::cmp::min(
-            // Saturating subs to make it inclusive zero indexing.
-            (self.offset.row + height as usize).saturating_sub(1),
-            doc.text().len_lines().saturating_sub(1),
-        )
+        let height = self.inner_area().height as usize;
+        let last_doc_line = doc.text().len_lines().saturating_sub(1);
+
+        let mut line = self.offset.row;
+        let mut rows = self.rows_between(doc, line, line);
+        while line < last_doc_line {
+            let next_rows = rows + self.rows_between(doc, line + 1, line + 1);
+            if next_rows > height {
+                break;
+            }
+            rows = next_rows;
+            line += 1;
+        }
+        std::cmp::min(line, last_doc_line)
+    }
+
+    /// Number of screen rows taken by the lines `first..=last`, including the virtual lines of
+    /// code lenses above them.
+    fn rows_between(&self, doc: &Document, first: usize, last: usize) -> usize {
+        (first..=last)
+            .map(|line| 1 + doc.has_code_lens_line(line) as usize)
+            .sum()
+    }
+
+    /// Inlay hints shown in this view, sorted by position.
+    fn inlay_hints<'a>(&self, doc: &'a Document) -> &'a [InlayHint] {
+        doc.inlay_hints(self.id)
+            .map(|inlay_hints| inlay_hints.hints.as_slice())
+            .unwrap_or(&[])
     }
 
     /// Translates a document position to an absolute position in the terminal.
@@ -160,8 +232,16 @@ impl View {
             }
         }
 
+        // inlay hints are drawn before the char they're attached to, which shifts it to the right
+        col += self
+            .inlay_hints(doc)
+            .iter()
+            .filter(|hint| (line_start..=pos).contains(&hint.char_idx))
+            .map(InlayHint::width)
+            .sum::<usize>();
+
         // It is possible for underflow to occur if the buffer length is larger than the terminal width.
-        let row = line.saturating_sub(self.offset.row);
+        let row = self.rows_between(doc, self.offset.row, line) - 1;
         let col = col.saturating_sub(self.offset.col);
 
         Some(Position::new(row, col))
@@ -173,6 +253,19 @@ impl View {
         row: u16,
         column: u16,
         tab_width: usize,
+    ) -> Option<usize> {
+        self.text_pos_at_screen_coords_with_hints(text, row, column, tab_width, &[])
+    }
+
+    /// Like [`Self::text_pos_at_screen_coords`], also skipping over the width of `inlay_hints`.
+    /// Coordinates on a hint map to the char it's attached to.
+    fn text_pos_at_screen_coords_with_hints(
+        &self,
+        text: &RopeSlice,
+        row: u16,
+        column: u16,
+        tab_width: usize,
+        inlay_hints: &[InlayHint],
     ) -> Option<usize> {
         let inner = self.inner_area();
         // 1 for status
@@ -196,8 +289,16 @@ impl View {
 
         let target = (column - inner.x) as usize + self.offset.col;
         let mut selected = 0;
+        let line_start = pos;
+        let mut inlay_hints = inlay_hints
+            .iter()
+            .skip_while(|hint| hint.char_idx < line_start)
+            .peekable();
 
         for grapheme in RopeGraphemes::new(current_line) {
+            while let Some(hint) = inlay_hints.next_if(|hint| hint.char_idx <= pos) {
+                selected += hint.width();
+            }
             if selected >= target {
                 break;
             }
@@ -216,7 +317,35 @@ impl View {
     /// Translates a screen position to position in the text document.
     /// Returns a usize typed position in bounds of the text if found in this view, None if out of view.
     pub fn pos_at_screen_coords(&self, doc: &Document, row: u16, column: u16) -> Option<usize> {
-        self.text_pos_at_screen_coords(&doc.text().slice(..), row, column, doc.tab_width())
+        // skip over the code lens lines to find the row the line would have without them
+        let inner = self.inner_area();
+        let mut row = row;
+        if row >= inner.top() && row < inner.bottom() {
+            let target = (row - inner.y) as usize;
+            let mut rows = 0;
+            let mut line = self.offset.row;
+            loop {
+                if doc.has_code_lens_line(line) {
+                    if rows == target {
+                        return None;
+                    }
+                    rows += 1;
+                }
+                if rows >= target {
+                    break;
+                }
+                rows += 1;
+                line += 1;
+            }
+            row = inner.y.saturating_add((line - self.offset.row) as u16);
+        }
+        self.text_pos_at_screen_coords_with_hints(
+            &doc.text().slice(..),
+            row,
+            column,
+            doc.tab_width(),
+            self.inlay_hints(doc),
+        )
     }
     // pub fn traverse<F>(&self, text: RopeSlice, start: usize, end: usize, fun: F)
     // where
@@ -357,4 +486,98 @@ mod tests {
             Some(7)
         );
     }
+
+    #[test]
+    fn code_lenses_take_up_rows() {
+        use crate::document::{CodeLens, DocumentCodeLenses};
+        use helix_lsp::lsp;
+
+        let mut view = View::new(DocumentId::default());
+        view.area = Rect::new(40, 40, 40, 4);
+        let mut doc = Document::from(Rope::from_str("a\nb\nc\nd\n"), None);
+        let lens = lsp::CodeLens {
+            range: lsp::Range::default(),
+            command: Some(lsp::Command::new("Run".into(), "run".into(), None)),
+            data: None,
+        };
+        doc.set_code_lenses(Some(DocumentCodeLenses {
+            lenses: vec![CodeLens { char_idx: 2, lens }],
+            ..Default::default()
+        }));
+        let text = doc.text().slice(..);
+
+        // three text rows fit below the statusline, one of them goes to the lens of `b`
+        assert_eq!(view.last_line(&doc), 1);
+        assert_eq!(
+            view.screen_coords_at_pos(&doc, text, 2),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(view.pos_at_screen_coords(&doc, 41, 40 + OFFSET), None);
+        assert_eq!(view.pos_at_screen_coords(&doc, 42, 40 + OFFSET), Some(2));
+    }
+
+    #[test]
+    fn inlay_hints_take_up_columns() {
+        use crate::document::{DocumentInlayHints, InlayHintKind};
+
+        let mut view = View::new(DocumentId::default());
+        view.area = Rect::new(40, 40, 40, 4);
+        let mut doc = Document::from(Rope::from_str("let x = 1;\nx\n"), None);
+        let hint = InlayHint {
+            char_idx: 5,
+            text: ": i32".into(),
+            kind: InlayHintKind::Type,
+            padding_left: false,
+            padding_right: true,
+        };
+        doc.set_inlay_hints(
+            view.id,
+            DocumentInlayHints {
+                hints: vec![hint],
+                ..Default::default()
+            },
+        );
+        let text = doc.text().slice(..);
+
+        // `let x: i32 = 1;`, the hint pushes everything after it 6 columns to the right
+        assert_eq!(
+            view.screen_coords_at_pos(&doc, text, 4),
+            Some(Position::new(0, 4))
+        );
+        assert_eq!(
+            view.screen_coords_at_pos(&doc, text, 5),
+            Some(Position::new(0, 11))
+        );
+        assert_eq!(
+            view.screen_coords_at_pos(&doc, text, 6),
+            Some(Position::new(0, 12))
+        );
+        assert_eq!(
+            view.screen_coords_at_pos(&doc, text, 12),
+            Some(Position::new(1, 1))
+        );
+
+        assert_eq!(
+            view.pos_at_screen_coords(&doc, 40, 40 + OFFSET + 4),
+            Some(4)
+        );
+        // on the hint
+        assert_eq!(
+            view.pos_at_screen_coords(&doc, 40, 40 + OFFSET + 5),
+            Some(5)
+        );
+        assert_eq!(
+            view.pos_at_screen_coords(&doc, 40, 40 + OFFSET + 10),
+            Some(5)
+        );
+        assert_eq!(
+            view.pos_at_screen_coords(&doc, 40, 40 + OFFSET + 11),
+            Some(5)
+        );
+        assert_eq!(
+            view.pos_at_screen_coords(&doc, 40, 40 + OFFSET + 12),
+            Some(6)
+        );
+        assert_eq!(view.pos_at_screen_coords(&doc, 41, 40 + OFFSET), Some(11));
+    }
 }
@@ -0,0 +1,13 @@
+; inherits: c
+
+(class_specifier
+  body: (_) @class.inside) @class.around
+
+(lambda_expression
+  body: (_) @function.inside) @function.around
+
+(template_parameter_list
+  (_) @parameter.inside @parameter.around)
+
+(template_argument_list
+  (_) @parameter.inside @parameter.around)
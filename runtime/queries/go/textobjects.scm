@@ -0,0 +1,20 @@
+(function_declaration
+  body: (block)? @function.inside) @function.around
+
+(func_literal
+  (_)? @function.inside) @function.around
+
+(method_declaration
+  body: (block)? @function.inside) @function.around
+
+(type_declaration
+  (type_spec
+    type: [(struct_type) (interface_type)] @class.inside)) @class.around
+
+(parameter_list
+  (_) @parameter.inside @parameter.around)
+
+(argument_list
+  (_) @parameter.inside @parameter.around)
+
+(comment) @comment.inside @comment.around
@@ -0,0 +1,19 @@
+(function_definition
+  body: (block)? @function.inside) @function.around
+
+(lambda
+  body: (_)? @function.inside) @function.around
+
+(class_definition
+  body: (block)? @class.inside) @class.around
+
+(parameters
+  (_) @parameter.inside @parameter.around)
+
+(lambda_parameters
+  (_) @parameter.inside @parameter.around)
+
+(argument_list
+  (_) @parameter.inside @parameter.around)
+
+(comment) @comment.inside @comment.around
@@ -0,0 +1,38 @@
+(function_item
+  body: (_) @function.inside) @function.around
+
+(closure_expression
+  body: (_) @function.inside) @function.around
+
+[
+  (struct_item
+    body: (_) @class.inside)
+  (enum_item
+    body: (_) @class.inside)
+  (union_item
+    body: (_) @class.inside)
+  (trait_item
+    body: (_) @class.inside)
+  (impl_item
+    body: (_) @class.inside)
+] @class.around
+
+(parameters
+  (_) @parameter.inside @parameter.around)
+
+(closure_parameters
+  (_) @parameter.inside @parameter.around)
+
+(type_parameters
+  (_) @parameter.inside @parameter.around)
+
+(type_arguments
+  (_) @parameter.inside @parameter.around)
+
+(arguments
+  (_) @parameter.inside @parameter.around)
+
+[
+  (line_comment)
+  (block_comment)
+] @comment.inside @comment.around
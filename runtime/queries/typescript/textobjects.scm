@@ -0,0 +1,10 @@
+; inherits: javascript
+
+(interface_declaration
+  body: (_) @class.inside) @class.around
+
+(type_parameters
+  (_) @parameter.inside @parameter.around)
+
+(type_arguments
+  (_) @parameter.inside @parameter.around)